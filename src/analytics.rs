@@ -0,0 +1,397 @@
+use serde::Serialize;
+use crate::timestamp::Timestamp;
+use std::collections::{BTreeSet, HashMap};
+
+const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+
+/// Upper bounds (in seconds) of the response-time histogram buckets.
+const RESPONSE_BUCKETS: [(&str, i64); 7] = [
+    ("<1m", 60),
+    ("1-5m", 300),
+    ("5-15m", 900),
+    ("15-60m", 3_600),
+    ("1-6h", 21_600),
+    ("6-24h", 86_400),
+    (">24h", i64::MAX),
+];
+
+/// One non-deleted message. Hours, weekdays and days are taken in the
+/// database timezone.
+#[derive(Debug, Clone)]
+pub struct ActivityRow {
+    pub id: i32,
+    pub user_id: i32,
+    pub reply_to_id: Option<i32>,
+    pub created_at: Timestamp,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParticipantActivity {
+    pub user_id: i32,
+    pub username: String,
+    pub message_count: usize,
+    pub share_percent: f64,
+    pub first_message_at: Timestamp,
+    pub last_message_at: Timestamp,
+    pub median_response_secs: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HourBucket {
+    pub hour: u32,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WeekdayBucket {
+    pub weekday: &'static str,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResponseBucket {
+    pub label: &'static str,
+    pub count: usize,
+}
+
+/// Time between consecutive messages from different participants.
+#[derive(Debug, Serialize)]
+pub struct ResponseTimes {
+    pub count: usize,
+    pub mean_secs: Option<i64>,
+    pub median_secs: Option<i64>,
+    pub p90_secs: Option<i64>,
+    pub buckets: Vec<ResponseBucket>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Streak {
+    pub days: i64,
+    pub start: String,
+    pub end: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplyChain {
+    pub root_message_id: i32,
+    pub root_author: String,
+    pub replies: usize,
+    pub depth: usize,
+    pub participants: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConversationAnalytics {
+    pub conversation_id: i32,
+    pub total_messages: usize,
+    pub first_activity: Option<Timestamp>,
+    pub last_activity: Option<Timestamp>,
+    pub active_days: usize,
+    pub participants: Vec<ParticipantActivity>,
+    pub by_hour: Vec<HourBucket>,
+    pub by_weekday: Vec<WeekdayBucket>,
+    pub response_times: ResponseTimes,
+    pub longest_streak: Option<Streak>,
+    pub current_streak: Option<Streak>,
+    pub top_reply_chains: Vec<ReplyChain>,
+}
+
+/// Computes conversation analytics from messages ordered oldest first.
+/// `usernames` resolves author ids; unknown authors are labelled by id.
+pub fn compute(
+    conversation_id: i32,
+    rows: &[ActivityRow],
+    usernames: &HashMap<i32, String>,
+    top_chains: usize,
+) -> ConversationAnalytics {
+    let name_of = |user_id: i32| {
+        usernames
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_else(|| format!("user #{}", user_id))
+    };
+
+    let mut by_hour = vec![0usize; 24];
+    let mut by_weekday = vec![0usize; 7];
+    for row in rows {
+        by_hour[row.created_at.hour() as usize] += 1;
+        by_weekday[row.created_at.weekday() as usize] += 1;
+    }
+
+    // Response times: a message answering the previous speaker.
+    let mut all_responses = Vec::new();
+    let mut responses_by_user: HashMap<i32, Vec<i64>> = HashMap::new();
+    for pair in rows.windows(2) {
+        if pair[0].user_id != pair[1].user_id {
+            let delta = (pair[1].created_at.unix_seconds() - pair[0].created_at.unix_seconds()).max(0);
+            all_responses.push(delta);
+            responses_by_user.entry(pair[1].user_id).or_default().push(delta);
+        }
+    }
+
+    let mut participants: Vec<ParticipantActivity> = Vec::new();
+    let mut index: HashMap<i32, usize> = HashMap::new();
+    for row in rows {
+        match index.get(&row.user_id) {
+            Some(&i) => {
+                participants[i].message_count += 1;
+                participants[i].last_message_at = row.created_at;
+            }
+            None => {
+                index.insert(row.user_id, participants.len());
+                participants.push(ParticipantActivity {
+                    user_id: row.user_id,
+                    username: name_of(row.user_id),
+                    message_count: 1,
+                    share_percent: 0.0,
+                    first_message_at: row.created_at,
+                    last_message_at: row.created_at,
+                    median_response_secs: None,
+                });
+            }
+        }
+    }
+    for participant in &mut participants {
+        participant.share_percent = percent(participant.message_count, rows.len());
+        if let Some(deltas) = responses_by_user.get_mut(&participant.user_id) {
+            deltas.sort_unstable();
+            participant.median_response_secs = percentile(deltas, 50);
+        }
+    }
+    participants.sort_by_key(|p| std::cmp::Reverse(p.message_count));
+
+    all_responses.sort_unstable();
+    let response_times = ResponseTimes {
+        count: all_responses.len(),
+        mean_secs: if all_responses.is_empty() {
+            None
+        } else {
+            Some(all_responses.iter().sum::<i64>() / all_responses.len() as i64)
+        },
+        median_secs: percentile(&all_responses, 50),
+        p90_secs: percentile(&all_responses, 90),
+        buckets: RESPONSE_BUCKETS
+            .iter()
+            .enumerate()
+            .map(|(i, (label, upper))| {
+                let lower = if i == 0 { 0 } else { RESPONSE_BUCKETS[i - 1].1 };
+                ResponseBucket {
+                    label,
+                    count: all_responses.iter().filter(|d| **d >= lower && **d < *upper).count(),
+                }
+            })
+            .collect(),
+    };
+
+    let (active_days, longest_streak, current_streak) = streaks(rows);
+
+    ConversationAnalytics {
+        conversation_id,
+        total_messages: rows.len(),
+        first_activity: rows.first().map(|r| r.created_at),
+        last_activity: rows.last().map(|r| r.created_at),
+        active_days,
+        participants,
+        by_hour: by_hour
+            .into_iter()
+            .enumerate()
+            .map(|(hour, count)| HourBucket { hour: hour as u32, count })
+            .collect(),
+        by_weekday: by_weekday
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| WeekdayBucket { weekday: WEEKDAYS[i], count })
+            .collect(),
+        response_times,
+        longest_streak,
+        current_streak,
+        top_reply_chains: reply_chains(rows, &name_of, top_chains),
+    }
+}
+
+fn percent(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    (part as f64 * 1000.0 / total as f64).round() / 10.0
+}
+
+/// Nearest-rank percentile of an ascending slice.
+fn percentile(sorted: &[i64], pct: usize) -> Option<i64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    Some(sorted[rank - 1])
+}
+
+/// Returns the number of active days, the longest run of consecutive active
+/// days, and the run that includes the most recent active day.
+fn streaks(rows: &[ActivityRow]) -> (usize, Option<Streak>, Option<Streak>) {
+    let mut days: Vec<(i64, Timestamp)> = Vec::new();
+    let mut seen = BTreeSet::new();
+    for row in rows {
+        if seen.insert(row.created_at.day_number()) {
+            days.push((row.created_at.day_number(), row.created_at));
+        }
+    }
+    days.sort_unstable_by_key(|(n, _)| *n);
+
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    let mut start = 0;
+    for i in 1..=days.len() {
+        if i == days.len() || days[i].0 != days[i - 1].0 + 1 {
+            if !days.is_empty() {
+                runs.push((start, i - 1));
+            }
+            start = i;
+        }
+    }
+
+    let to_streak = |(first, last): (usize, usize)| Streak {
+        days: days[last].0 - days[first].0 + 1,
+        start: days[first].1.date_string(),
+        end: days[last].1.date_string(),
+    };
+
+    let longest = runs
+        .iter()
+        .copied()
+        .max_by_key(|(first, last)| (last - first, *first))
+        .map(to_streak);
+    let current = runs.last().copied().map(to_streak);
+
+    (days.len(), longest, current)
+}
+
+/// Groups messages into reply trees and returns the largest ones.
+fn reply_chains(rows: &[ActivityRow], name_of: &dyn Fn(i32) -> String, top: usize) -> Vec<ReplyChain> {
+    let by_id: HashMap<i32, &ActivityRow> = rows.iter().map(|r| (r.id, r)).collect();
+
+    // Walk each reply up to the oldest ancestor still present in the conversation.
+    let mut chains: HashMap<i32, (usize, usize, BTreeSet<i32>)> = HashMap::new();
+    for row in rows {
+        let mut root = row;
+        let mut depth = 0;
+        while let Some(parent) = root.reply_to_id.and_then(|id| by_id.get(&id)) {
+            root = parent;
+            depth += 1;
+            if depth > rows.len() {
+                break;
+            }
+        }
+        if depth == 0 {
+            continue;
+        }
+
+        let entry = chains.entry(root.id).or_insert_with(|| (0, 0, BTreeSet::from([root.user_id])));
+        entry.0 += 1;
+        entry.1 = entry.1.max(depth);
+        entry.2.insert(row.user_id);
+    }
+
+    let mut chains: Vec<ReplyChain> = chains
+        .into_iter()
+        .map(|(root_id, (replies, depth, users))| ReplyChain {
+            root_message_id: root_id,
+            root_author: name_of(by_id[&root_id].user_id),
+            replies,
+            depth,
+            participants: users.len(),
+        })
+        .collect();
+    chains.sort_by_key(|c| (std::cmp::Reverse(c.replies), std::cmp::Reverse(c.depth), c.root_message_id));
+    chains.truncate(top);
+    chains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(id: i32, user_id: i32, reply_to_id: Option<i32>, created_at: &str) -> ActivityRow {
+        ActivityRow { id, user_id, reply_to_id, created_at: Timestamp::parse_bound(created_at, false).unwrap() }
+    }
+
+    fn usernames() -> HashMap<i32, String> {
+        HashMap::from([(1, "alice".to_string()), (2, "bob".to_string())])
+    }
+
+    /// Friday 1 March to Monday 4 March 2024 (UTC), with a gap on Sunday.
+    fn rows() -> Vec<ActivityRow> {
+        vec![
+            row(1, 1, None, "2024-03-01T09:00:00+00:00"),
+            row(2, 2, Some(1), "2024-03-01T09:00:30+00:00"),
+            row(3, 1, Some(2), "2024-03-01T09:10:30+00:00"),
+            row(4, 1, None, "2024-03-01T09:11:00+00:00"),
+            row(5, 3, Some(1), "2024-03-02T09:11:00+00:00"),
+            row(6, 2, None, "2024-03-04T14:00:00+00:00"),
+        ]
+    }
+
+    #[test]
+    fn empty_conversation_has_no_activity() {
+        let analytics = compute(1, &[], &usernames(), 5);
+        assert_eq!(analytics.total_messages, 0);
+        assert_eq!((analytics.first_activity, analytics.active_days), (None, 0));
+        assert!(analytics.participants.is_empty() && analytics.top_reply_chains.is_empty());
+        assert_eq!((analytics.response_times.count, analytics.response_times.median_secs), (0, None));
+        assert!(analytics.longest_streak.is_none() && analytics.current_streak.is_none());
+    }
+
+    #[test]
+    fn participants_are_ranked_by_message_count() {
+        let analytics = compute(1, &rows(), &usernames(), 5);
+        let summary: Vec<(&str, usize, f64)> = analytics
+            .participants
+            .iter()
+            .map(|p| (p.username.as_str(), p.message_count, p.share_percent))
+            .collect();
+        assert_eq!(summary, [("alice", 3, 50.0), ("bob", 2, 33.3), ("user #3", 1, 16.7)]);
+        assert_eq!(analytics.participants[0].last_message_at.to_rfc3339(), "2024-03-01T09:11:00+00:00");
+    }
+
+    #[test]
+    fn response_times_count_only_changes_of_speaker() {
+        let times = compute(1, &rows(), &usernames(), 5).response_times;
+        // 30s, 10m, 1d, ~2d5h; alice's message 4 follows her own message 3.
+        assert_eq!(times.count, 4);
+        assert_eq!(times.median_secs, Some(600));
+        assert_eq!(times.p90_secs, Some(190_140));
+        let buckets: Vec<(&str, usize)> = times.buckets.iter().map(|b| (b.label, b.count)).collect();
+        assert_eq!(buckets, [("<1m", 1), ("1-5m", 0), ("5-15m", 1), ("15-60m", 0), ("1-6h", 0), ("6-24h", 0), (">24h", 2)]);
+
+        let alice = &compute(1, &rows(), &usernames(), 5).participants[0];
+        assert_eq!(alice.median_response_secs, Some(600));
+    }
+
+    #[test]
+    fn hours_weekdays_and_streaks_follow_the_calendar() {
+        let analytics = compute(1, &rows(), &usernames(), 5);
+        assert_eq!(analytics.by_hour[9].count, 5);
+        assert_eq!(analytics.by_hour[14].count, 1);
+        assert_eq!((analytics.by_weekday[4].weekday, analytics.by_weekday[4].count), ("Friday", 4));
+        assert_eq!(analytics.by_weekday[0].count, 1);
+        assert_eq!(analytics.active_days, 3);
+
+        let longest = analytics.longest_streak.unwrap();
+        assert_eq!((longest.days, longest.start.as_str(), longest.end.as_str()), (2, "2024-03-01", "2024-03-02"));
+        let current = analytics.current_streak.unwrap();
+        assert_eq!((current.days, current.start.as_str()), (1, "2024-03-04"));
+    }
+
+    #[test]
+    fn reply_chains_are_grouped_under_their_root() {
+        let mut rows = rows();
+        // A reply to a message outside the conversation starts no chain.
+        rows.push(row(7, 2, Some(99), "2024-03-04T15:00:00+00:00"));
+
+        let chains = compute(1, &rows, &usernames(), 5).top_reply_chains;
+        assert_eq!(chains.len(), 1);
+        let chain = &chains[0];
+        assert_eq!((chain.root_message_id, chain.root_author.as_str()), (1, "alice"));
+        assert_eq!((chain.replies, chain.depth, chain.participants), (3, 2, 3));
+
+        assert!(compute(1, &rows, &usernames(), 0).top_reply_chains.is_empty());
+    }
+}
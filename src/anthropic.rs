@@ -0,0 +1,297 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::{json, Value};
+use crate::llm::{error_body, ChatResponse, FunctionDelta, LlmProvider, Message, StreamSink, ToolCallAccumulator, ToolCallDelta};
+use crate::sse::SseDecoder;
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Anthropic-style Messages API (`/v1/messages`).
+pub struct AnthropicProvider {
+    client: Client,
+    api_base: String,
+    api_key: String,
+    model: String,
+    max_tokens: u32,
+}
+
+impl AnthropicProvider {
+    pub fn new(client: Client, api_base: &str, api_key: &str, model: &str, max_tokens: u32) -> Self {
+        AnthropicProvider {
+            client,
+            api_base: api_base.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            max_tokens,
+        }
+    }
+}
+
+/// Appends content blocks to the conversation, merging them into the last
+/// message when it has the same role, since roles must alternate.
+fn push_blocks(converted: &mut Vec<Value>, role: &str, blocks: Vec<Value>) {
+    if blocks.is_empty() {
+        return;
+    }
+    if let Some(last) = converted.last_mut().filter(|last| last["role"] == role) {
+        if let Some(content) = last["content"].as_array_mut() {
+            content.extend(blocks);
+            return;
+        }
+    }
+    converted.push(json!({ "role": role, "content": blocks }));
+}
+
+fn text_block(text: &str) -> Vec<Value> {
+    if text.trim().is_empty() {
+        Vec::new()
+    } else {
+        vec![json!({ "type": "text", "text": text })]
+    }
+}
+
+/// Splits off the system prompt and converts the rest to Messages API
+/// turns. System notes added mid-conversation become user text, tool calls
+/// become `tool_use` blocks and their results `tool_result` blocks.
+fn to_anthropic_messages(messages: &[Message]) -> (String, Vec<Value>) {
+    let leading = messages.iter().take_while(|m| m.role == "system").count();
+    let system = messages[..leading].iter().map(|m| m.content.as_str()).collect::<Vec<_>>().join("\n\n");
+    let mut converted = Vec::new();
+
+    for message in &messages[leading..] {
+        match (message.role.as_str(), &message.tool_calls, &message.tool_call_id) {
+            ("assistant", Some(calls), _) => {
+                let mut blocks = text_block(&message.content);
+                blocks.extend(calls.iter().map(|call| json!({
+                    "type": "tool_use",
+                    "id": call.id,
+                    "name": call.function.name,
+                    "input": serde_json::from_str::<Value>(&call.function.arguments).unwrap_or_else(|_| json!({}))
+                })));
+                push_blocks(&mut converted, "assistant", blocks);
+            }
+            ("assistant", None, _) => push_blocks(&mut converted, "assistant", text_block(&message.content)),
+            ("tool", _, Some(call_id)) => push_blocks(&mut converted, "user", vec![json!({
+                "type": "tool_result",
+                "tool_use_id": call_id,
+                "content": message.content
+            })]),
+            ("system", _, _) => push_blocks(&mut converted, "user", text_block(&format!("[System note] {}", message.content))),
+            _ => push_blocks(&mut converted, "user", text_block(&message.content)),
+        }
+    }
+
+    (system, converted)
+}
+
+/// Converts OpenAI function schemas to Messages API tool definitions.
+fn to_anthropic_tools(tools: &[Value]) -> Vec<Value> {
+    tools
+        .iter()
+        .map(|tool| json!({
+            "name": tool["function"]["name"],
+            "description": tool["function"]["description"],
+            "input_schema": tool["function"]["parameters"]
+        }))
+        .collect()
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn kind(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: Option<&[Value]>,
+        sink: &mut StreamSink<'_>,
+    ) -> Result<ChatResponse, Box<dyn std::error::Error>> {
+        let (system, converted) = to_anthropic_messages(messages);
+        let mut body = json!({
+            "model": self.model,
+            "max_tokens": self.max_tokens,
+            "messages": converted,
+            "stream": true
+        });
+        if !system.is_empty() {
+            body["system"] = json!(system);
+        }
+        if let Some(tools) = tools.filter(|t| !t.is_empty()) {
+            body["tools"] = json!(to_anthropic_tools(tools));
+            body["tool_choice"] = json!({ "type": "auto" });
+        }
+
+        let response = self.client
+            .post(format!("{}/messages", self.api_base))
+            .header("Content-Type", "application/json")
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to send request to Anthropic API: {}", e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("Anthropic API returned {}: {}", status, error_body(response, 500).await).into());
+        }
+
+        let mut stream = response.bytes_stream();
+        let mut decoder = SseDecoder::new();
+        let mut content = String::new();
+        let mut tool_calls = ToolCallAccumulator::default();
+        let mut done = false;
+
+        use futures_util::stream::StreamExt;
+
+        'stream: while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| format!("Error reading stream: {}", e))?;
+
+            for event in decoder.feed(&chunk) {
+                let event = event.map_err(|e| format!("Malformed stream from Anthropic API: {}", e))?;
+                let data: Value = serde_json::from_str(&event.data)
+                    .map_err(|e| format!("Malformed stream event from Anthropic API ({}): {}", e, event.data.chars().take(200).collect::<String>()))?;
+                // Content blocks are numbered; tool_use blocks map onto the
+                // tool call accumulator by that index.
+                let index = data["index"].as_u64().map(|i| i as usize);
+
+                match data["type"].as_str().unwrap_or_default() {
+                    "content_block_start" if data["content_block"]["type"] == "tool_use" => {
+                        tool_calls.push(&ToolCallDelta {
+                            index,
+                            id: data["content_block"]["id"].as_str().map(str::to_string),
+                            function: Some(FunctionDelta {
+                                name: data["content_block"]["name"].as_str().map(str::to_string),
+                                arguments: None,
+                            }),
+                        });
+                    }
+                    "content_block_delta" => match data["delta"]["type"].as_str().unwrap_or_default() {
+                        "text_delta" => {
+                            let text = data["delta"]["text"].as_str().unwrap_or_default();
+                            content.push_str(text);
+                            sink.push(text).await;
+                        }
+                        "input_json_delta" => tool_calls.push(&ToolCallDelta {
+                            index,
+                            id: None,
+                            function: Some(FunctionDelta {
+                                name: None,
+                                arguments: data["delta"]["partial_json"].as_str().map(str::to_string),
+                            }),
+                        }),
+                        _ => {}
+                    },
+                    "message_stop" => {
+                        done = true;
+                        break 'stream;
+                    }
+                    "error" => {
+                        let message = data["error"]["message"].as_str().map(str::to_string).unwrap_or_else(|| data["error"].to_string());
+                        return Err(format!("Anthropic API reported an error: {}", message).into());
+                    }
+                    // message_start, content_block_stop, message_delta, ping
+                    _ => {}
+                }
+            }
+        }
+
+        if !done {
+            decoder.finish().map_err(|e| format!("Malformed stream from Anthropic API: {}", e))?;
+            return Err("Anthropic API stream ended before message_stop".into());
+        }
+
+        Ok(ChatResponse {
+            content,
+            tool_calls: tool_calls.finish(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{NativeFunctionCall, NativeToolCall};
+
+    fn message(role: &str, content: &str) -> Message {
+        Message { role: role.to_string(), content: content.to_string(), ..Default::default() }
+    }
+
+    fn call(id: &str, name: &str, arguments: &str) -> NativeToolCall {
+        NativeToolCall {
+            id: id.to_string(),
+            kind: "function".to_string(),
+            function: NativeFunctionCall { name: name.to_string(), arguments: arguments.to_string() },
+        }
+    }
+
+    fn tool_result(call_id: &str, content: &str) -> Message {
+        Message { tool_call_id: Some(call_id.to_string()), ..message("tool", content) }
+    }
+
+    #[test]
+    fn splits_off_leading_system_messages() {
+        let (system, converted) = to_anthropic_messages(&[message("system", "You are Orbit."), message("system", "Be brief."), message("user", "hi")]);
+        assert_eq!(system, "You are Orbit.\n\nBe brief.");
+        assert_eq!(converted, vec![json!({ "role": "user", "content": [{ "type": "text", "text": "hi" }] })]);
+    }
+
+    #[test]
+    fn pairs_tool_use_with_tool_results_in_one_user_turn() {
+        let (_, converted) = to_anthropic_messages(&[
+            message("system", "prompt"),
+            message("user", "add and root"),
+            Message {
+                tool_calls: Some(vec![call("call_1", "add", r#"{"a":1,"b":2}"#), call("call_2", "sqrt", "not json")]),
+                ..message("assistant", "Working on it.")
+            },
+            tool_result("call_1", "3"),
+            tool_result("call_2", "2"),
+            message("assistant", "Done."),
+        ]);
+
+        assert_eq!(converted.len(), 4);
+        assert_eq!(converted[1], json!({
+            "role": "assistant",
+            "content": [
+                { "type": "text", "text": "Working on it." },
+                { "type": "tool_use", "id": "call_1", "name": "add", "input": { "a": 1, "b": 2 } },
+                { "type": "tool_use", "id": "call_2", "name": "sqrt", "input": {} }
+            ]
+        }));
+        assert_eq!(converted[2], json!({
+            "role": "user",
+            "content": [
+                { "type": "tool_result", "tool_use_id": "call_1", "content": "3" },
+                { "type": "tool_result", "tool_use_id": "call_2", "content": "2" }
+            ]
+        }));
+        assert_eq!(converted[3]["role"], "assistant");
+    }
+
+    #[test]
+    fn merges_consecutive_turns_of_the_same_role() {
+        let (_, converted) = to_anthropic_messages(&[
+            message("system", "prompt"),
+            message("user", "hi"),
+            message("assistant", "  "),
+            message("system", "Activity note"),
+            message("user", "anyone?"),
+        ]);
+
+        assert_eq!(converted, vec![json!({
+            "role": "user",
+            "content": [
+                { "type": "text", "text": "hi" },
+                { "type": "text", "text": "[System note] Activity note" },
+                { "type": "text", "text": "anyone?" }
+            ]
+        })]);
+    }
+}
@@ -1,17 +1,89 @@
 use mysql::*;
 use mysql::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use crate::analytics::{self, ActivityRow, ConversationAnalytics};
+use crate::privacy::{mask_email, UserRedactionPolicy};
+use crate::search::{self, SearchMode};
+use crate::timestamp::{self, Timestamp};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+
+/// One connection pool per process; `Database` handles are cheap clones of it.
+static SHARED_POOL: OnceLock<Pool> = OnceLock::new();
+
+const USER_CACHE_TTL: Duration = Duration::from_secs(60);
+const USER_CACHE_CAPACITY: usize = 512;
+
+/// Small process-wide cache of user rows so resolving message authors does
+/// not cost one query (and one pool connection) per message.
+static USER_CACHE: OnceLock<Mutex<HashMap<i32, (Instant, User)>>> = OnceLock::new();
+
+fn user_cache() -> &'static Mutex<HashMap<i32, (Instant, User)>> {
+    USER_CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Keyset filters shared by message listing and search. Cursors are message ids;
+/// rows are compared on (created_at, id) so ties on created_at stay stable.
+const MESSAGE_RANGE_FILTER: &str = "
+               AND m.deleted_at IS NULL
+               AND (:before_id IS NULL OR (m.created_at, m.id) < (SELECT created_at, id FROM messages WHERE id = :before_id))
+               AND (:after_id IS NULL OR (m.created_at, m.id) > (SELECT created_at, id FROM messages WHERE id = :after_id))
+               AND (:since IS NULL OR m.created_at >= :since)
+               AND (:until IS NULL OR m.created_at <= :until)";
+
+/// User row for internal use. The password column is never loaded, and the
+/// type is deliberately not serializable: anything that reaches the model or
+/// the web UI goes through [`User::to_public`].
+#[derive(Clone)]
 pub struct User {
     pub id: i32,
     pub username: String,
     pub email: String,
-    pub password: String,
     pub chat_role: String,
     pub is_active: bool,
-    pub created_at: String,
+    pub created_at: Timestamp,
+}
+
+impl std::fmt::Debug for User {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("User")
+            .field("id", &self.id)
+            .field("username", &self.username)
+            .field("email", &mask_email(&self.email))
+            .field("chat_role", &self.chat_role)
+            .field("is_active", &self.is_active)
+            .field("created_at", &self.created_at)
+            .finish_non_exhaustive()
+    }
+}
+
+impl User {
+    pub fn to_public(&self, policy: &UserRedactionPolicy) -> PublicUser {
+        PublicUser {
+            id: self.id,
+            username: self.username.clone(),
+            email: policy.apply_email(&self.email),
+            chat_role: self.chat_role.clone(),
+            is_active: self.is_active,
+            created_at: policy.apply_created_at(&self.created_at),
+        }
+    }
+}
+
+/// Tool-facing projection of a user with PII redacted per policy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicUser {
+    pub id: i32,
+    pub username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    pub chat_role: String,
+    pub is_active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,7 +91,7 @@ pub struct Conversation {
     pub id: i32,
     pub title: String,
     pub is_group: bool,
-    pub created_at: String,
+    pub created_at: Timestamp,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,7 +102,151 @@ pub struct Message {
     pub content: String,
     pub reaction: Option<String>,
     pub reply_to_id: Option<i32>,
-    pub created_at: String,
+    pub created_at: Timestamp,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageWithAuthor {
+    #[serde(flatten)]
+    pub message: Message,
+    pub author: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub message: MessageWithAuthor,
+    pub score: f64,
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationHits {
+    pub conversation_id: i32,
+    pub title: String,
+    pub is_group: bool,
+    pub hits: Vec<SearchHit>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    pub query: String,
+    pub mode: SearchMode,
+    pub author_id: Option<i32>,
+    pub before_id: Option<i32>,
+    pub after_id: Option<i32>,
+    pub since: Option<Timestamp>,
+    pub until: Option<Timestamp>,
+    pub limit: i32,
+}
+
+/// Selects a window of a conversation. Without `after_id` the newest messages
+/// (older than `before_id`, if given) are returned; with only `after_id` the
+/// page walks forward from that message.
+#[derive(Debug, Clone, Default)]
+pub struct MessageQuery {
+    pub limit: i32,
+    pub before_id: Option<i32>,
+    pub after_id: Option<i32>,
+    pub since: Option<Timestamp>,
+    pub until: Option<Timestamp>,
+}
+
+/// Messages in chronological order plus cursors for the neighbouring pages.
+/// Pass `older_cursor` as `before_id` or `newer_cursor` as `after_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessagePage {
+    pub messages: Vec<MessageWithAuthor>,
+    pub older_cursor: Option<i32>,
+    pub newer_cursor: Option<i32>,
+}
+
+/// A message together with the replies it received, recursively.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadNode {
+    #[serde(flatten)]
+    pub message: MessageWithAuthor,
+    pub replies: Vec<ThreadNode>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageThread {
+    /// Messages this one replies to, outermost first.
+    pub ancestors: Vec<MessageWithAuthor>,
+    pub message: ThreadNode,
+    /// Set when the depth or size limit cut the thread short.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageContext {
+    pub before: Vec<MessageWithAuthor>,
+    pub message: MessageWithAuthor,
+    pub after: Vec<MessageWithAuthor>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactionCount {
+    pub reaction: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorReactions {
+    pub user_id: i32,
+    pub author: String,
+    pub reactions_received: usize,
+    pub by_reaction: Vec<ReactionCount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactedMessage {
+    #[serde(flatten)]
+    pub message: MessageWithAuthor,
+    pub reaction_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactionStats {
+    pub total_reactions: usize,
+    pub by_reaction: Vec<ReactionCount>,
+    pub by_author: Vec<AuthorReactions>,
+    pub top_messages: Vec<ReactedMessage>,
+}
+
+/// Which messages reaction statistics are computed over.
+#[derive(Debug, Clone, Copy)]
+pub enum ReactionScope {
+    Conversation(i32),
+    Author(i32),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSummary {
+    pub summary: String,
+    pub message_count: usize,
+    pub older_cursor: Option<i32>,
+    pub newer_cursor: Option<i32>,
+}
+
+/// An LLM-written summary covering a conversation up to `last_message_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedSummary {
+    pub conversation_id: i32,
+    pub summary: String,
+    pub last_message_id: i32,
+    pub updated_by: Option<i32>,
+    pub updated_at: Timestamp,
+}
+
+/// A previous version of a message, recorded before an edit or delete.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageEdit {
+    pub message_id: i32,
+    pub editor_id: i32,
+    pub action: String,
+    pub previous_content: String,
+    pub created_at: Timestamp,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,12 +256,197 @@ pub struct ConversationUser {
     pub is_admin: bool,
 }
 
+/// Column of a read-only query result with its MySQL type, e.g. `long` or `datetime`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadOnlyColumn {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReadOnlyRows {
+    pub columns: Vec<ReadOnlyColumn>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+fn column_type_name(column_type: consts::ColumnType) -> String {
+    format!("{:?}", column_type)
+        .trim_start_matches("MYSQL_TYPE_")
+        .to_lowercase()
+}
+
+/// Keeps messages of matching conversations for `max_age_days`, or forever
+/// when None. `scope` is conversation, group, direct or global.
+#[derive(Debug, Clone, Serialize)]
+pub struct RetentionRule {
+    pub scope: String,
+    pub conversation_id: Option<i32>,
+    pub max_age_days: Option<u32>,
+    pub updated_at: Timestamp,
+}
+
+/// A message as written to a retention archive.
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchivedMessage {
+    #[serde(flatten)]
+    pub message: Message,
+    pub edited_at: Option<Timestamp>,
+    pub deleted_at: Option<Timestamp>,
+    pub edits: Vec<MessageEdit>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RetentionAudit {
+    pub started_at: Timestamp,
+    pub finished_at: Timestamp,
+    pub triggered_by: String,
+    pub dry_run: bool,
+    pub conversations: usize,
+    pub messages_deleted: usize,
+    pub archive_path: Option<String>,
+    /// JSON of the per-conversation plan.
+    pub details: String,
+    pub error: Option<String>,
+}
+
+/// Outcome of claiming an idempotency key.
+#[derive(Debug, Clone)]
+pub enum IdempotencyClaim {
+    /// First use inside the window; the write should go ahead.
+    Claimed,
+    /// Already used. `result` is None while the first call is still running
+    /// (or crashed before finishing).
+    Existing { tool: String, arguments: String, result: Option<String> },
+}
+
+// ===== SCHEMA MIGRATIONS =====
+
+/// A change Orbit needs on top of the tunispace schema. `check` returns a
+/// row once the change is in place.
+struct Migration {
+    name: &'static str,
+    check: &'static str,
+    apply: &'static str,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        name: "messages.edited_at",
+        check: "SELECT 1 FROM information_schema.COLUMNS
+                WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'messages' AND COLUMN_NAME = 'edited_at'",
+        apply: "ALTER TABLE messages ADD COLUMN edited_at DATETIME NULL",
+    },
+    Migration {
+        name: "messages.deleted_at",
+        check: "SELECT 1 FROM information_schema.COLUMNS
+                WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'messages' AND COLUMN_NAME = 'deleted_at'",
+        apply: "ALTER TABLE messages ADD COLUMN deleted_at DATETIME NULL",
+    },
+    Migration {
+        name: "messages.ft_messages_content",
+        check: "SELECT 1 FROM information_schema.STATISTICS
+                WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'messages'
+                  AND INDEX_TYPE = 'FULLTEXT' AND COLUMN_NAME = 'content'
+                LIMIT 1",
+        apply: "ALTER TABLE messages ADD FULLTEXT INDEX ft_messages_content (content)",
+    },
+    Migration {
+        name: "orbit_message_edits",
+        check: "SELECT 1 FROM information_schema.TABLES
+                WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'orbit_message_edits'",
+        apply: "CREATE TABLE IF NOT EXISTS orbit_message_edits (
+                id INT AUTO_INCREMENT PRIMARY KEY,
+                message_id INT NOT NULL,
+                editor_id INT NOT NULL,
+                action VARCHAR(16) NOT NULL,
+                previous_content TEXT NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                INDEX idx_orbit_message_edits_message (message_id)
+            )",
+    },
+    Migration {
+        name: "orbit_import_map",
+        check: "SELECT 1 FROM information_schema.TABLES
+                WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'orbit_import_map'",
+        apply: "CREATE TABLE IF NOT EXISTS orbit_import_map (
+                source VARCHAR(64) NOT NULL,
+                kind VARCHAR(16) NOT NULL,
+                external_id VARCHAR(191) NOT NULL,
+                local_id INT NOT NULL,
+                imported_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (source, kind, external_id)
+            )",
+    },
+    Migration {
+        name: "orbit_conversation_summaries",
+        check: "SELECT 1 FROM information_schema.TABLES
+                WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'orbit_conversation_summaries'",
+        apply: "CREATE TABLE IF NOT EXISTS orbit_conversation_summaries (
+                conversation_id INT PRIMARY KEY,
+                summary TEXT NOT NULL,
+                last_message_id INT NOT NULL,
+                updated_by INT NULL,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP
+            )",
+    },
+    Migration {
+        name: "orbit_idempotency_keys",
+        check: "SELECT 1 FROM information_schema.TABLES
+                WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'orbit_idempotency_keys'",
+        apply: "CREATE TABLE IF NOT EXISTS orbit_idempotency_keys (
+                acting_user VARCHAR(191) NOT NULL,
+                idempotency_key VARCHAR(191) NOT NULL,
+                tool VARCHAR(64) NOT NULL,
+                arguments TEXT NOT NULL,
+                result MEDIUMTEXT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (acting_user, idempotency_key)
+            )",
+    },
+    Migration {
+        name: "orbit_retention_rules",
+        check: "SELECT 1 FROM information_schema.TABLES
+                WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'orbit_retention_rules'",
+        apply: "CREATE TABLE IF NOT EXISTS orbit_retention_rules (
+                scope VARCHAR(16) NOT NULL,
+                conversation_id INT NOT NULL DEFAULT 0,
+                max_age_days INT NULL,
+                updated_by INT NULL,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+                PRIMARY KEY (scope, conversation_id)
+            )",
+    },
+    Migration {
+        name: "orbit_retention_audit",
+        check: "SELECT 1 FROM information_schema.TABLES
+                WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'orbit_retention_audit'",
+        apply: "CREATE TABLE IF NOT EXISTS orbit_retention_audit (
+                id INT AUTO_INCREMENT PRIMARY KEY,
+                started_at DATETIME NOT NULL,
+                finished_at DATETIME NOT NULL,
+                triggered_by VARCHAR(191) NOT NULL,
+                dry_run TINYINT(1) NOT NULL,
+                conversations INT NOT NULL,
+                messages_deleted INT NOT NULL,
+                archive_path VARCHAR(512) NULL,
+                details MEDIUMTEXT NOT NULL,
+                error TEXT NULL
+            )",
+    },
+];
+
 pub struct Database {
     pool: Pool,
 }
 
 impl Database {
+    /// Returns a handle on the process-wide pool, creating it on first use.
     pub fn new() -> Result<Self> {
+        if let Some(pool) = SHARED_POOL.get() {
+            return Ok(Database { pool: pool.clone() }.with_server_zone());
+        }
+
         let db_host = env::var("DB_HOST").unwrap_or_else(|_| "localhost".to_string());
         let db_name = env::var("DB_NAME").unwrap_or_else(|_| "tunispace".to_string());
         let db_user = env::var("DB_USER").unwrap_or_else(|_| "root".to_string());
@@ -56,46 +457,153 @@ impl Database {
             db_user, db_pass, db_host, db_name
         );
 
-        let pool = Pool::new(url.as_str())?;
-        Ok(Database { pool })
+        let mut opts = OptsBuilder::from_opts(Opts::from_url(&url)?)
+            .tcp_connect_timeout(Some(Duration::from_secs(5)));
+        // Keep NOW() and CURRENT_TIMESTAMP in the same offset Timestamp reads
+        // with; without DB_TIMEZONE the server's zone is kept and detected.
+        if let Some(zone) = timestamp::session_time_zone() {
+            opts = opts.init(vec![format!("SET time_zone = '{}'", zone)]);
+        }
+
+        let pool = Pool::new(opts)?;
+        // Another thread may have won the race; its pool is kept.
+        let pool = SHARED_POOL.get_or_init(|| pool).clone();
+        Ok(Database { pool }.with_server_zone())
+    }
+
+    /// Detects the server's zone once when DB_TIMEZONE is not set. An
+    /// unreachable server is retried with the next handle; the caller's own
+    /// query reports the outage.
+    fn with_server_zone(self) -> Self {
+        if timestamp::needs_server_zone() {
+            let _ = self.detect_server_zone();
+        }
+        self
+    }
+
+    fn detect_server_zone(&self) -> Result<()> {
+        let zone: Option<(i32, String)> = self.pool.get_conn()?.query_first(
+            "SELECT CAST(TIMESTAMPDIFF(SECOND, UTC_TIMESTAMP(), NOW()) AS SIGNED), @@session.time_zone",
+        )?;
+        if let Some((offset_seconds, session_zone)) = zone {
+            timestamp::set_server_zone(offset_seconds, &session_zone);
+        }
+        Ok(())
+    }
+
+    /// Round-trips a trivial query; used by the health checker.
+    pub fn ping(&self) -> Result<()> {
+        self.pool.get_conn()?.query_drop("SELECT 1")
+    }
+
+    /// Migrations not yet applied to the connected database, by name.
+    pub fn pending_migrations(&self) -> Result<Vec<&'static str>> {
+        let mut conn = self.pool.get_conn()?;
+        let mut pending = Vec::new();
+        for migration in MIGRATIONS {
+            let applied: Option<i32> = conn.query_first(migration.check)?;
+            if applied.is_none() {
+                pending.push(migration.name);
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Applies the pending migrations in order and returns their names.
+    /// Run by `chat-IBM migrate`; the server itself never changes the schema.
+    pub fn migrate(&self) -> Result<Vec<&'static str>> {
+        let pending = self.pending_migrations()?;
+        let mut conn = self.pool.get_conn()?;
+        for migration in MIGRATIONS.iter().filter(|m| pending.contains(&m.name)) {
+            conn.query_drop(migration.apply)?;
+        }
+        Ok(pending)
     }
 
     // ===== USER OPERATIONS =====
 
-    pub fn find_user_by_id(&self, user_id: i32) -> Result<Option<User>> {
+    /// Batched user lookup. Cached rows are reused; the rest are fetched with a
+    /// single query and added to the cache.
+    pub fn find_users_by_ids(&self, user_ids: &[i32]) -> Result<HashMap<i32, User>> {
+        let mut found = HashMap::new();
+        let mut missing = Vec::new();
+
+        {
+            let cache = user_cache().lock().unwrap();
+            for &id in user_ids {
+                match cache.get(&id) {
+                    Some((fetched_at, user)) if fetched_at.elapsed() < USER_CACHE_TTL => {
+                        found.insert(id, user.clone());
+                    }
+                    _ if !missing.contains(&id) => missing.push(id),
+                    _ => {}
+                }
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(found);
+        }
+
         let mut conn = self.pool.get_conn()?;
-        let result = conn.exec_first(
-            "SELECT id, username, email, COALESCE(password, '') as password, chat_role, is_active,
-                    DATE_FORMAT(created_at, '%Y-%m-%d %H:%i:%s') as created_at
-             FROM user WHERE id = :id",
-            params! { "id" => user_id },
+        let placeholders = vec!["?"; missing.len()].join(", ");
+        let users = conn.exec_map(
+            format!(
+                "SELECT id, username, email, chat_role, is_active,
+                        created_at
+                 FROM user WHERE id IN ({})",
+                placeholders
+            ),
+            missing,
+            |(id, username, email, chat_role, is_active, created_at)| User {
+                id, username, email, chat_role, is_active, created_at,
+            },
         )?;
 
-        Ok(result.map(|(id, username, email, password, chat_role, is_active, created_at)| User {
-            id,
-            username,
-            email,
-            password,
-            chat_role,
-            is_active,
-            created_at,
-        }))
+        let mut cache = user_cache().lock().unwrap();
+        if cache.len() + users.len() > USER_CACHE_CAPACITY {
+            cache.retain(|_, (fetched_at, _)| fetched_at.elapsed() < USER_CACHE_TTL);
+        }
+        for user in users {
+            if cache.len() < USER_CACHE_CAPACITY {
+                cache.insert(user.id, (Instant::now(), user.clone()));
+            }
+            found.insert(user.id, user);
+        }
+
+        Ok(found)
+    }
+
+    /// Pairs messages with their author's username using one batched lookup.
+    pub fn with_authors(&self, messages: Vec<Message>) -> Result<Vec<MessageWithAuthor>> {
+        let ids: Vec<i32> = messages.iter().map(|m| m.user_id).collect();
+        let users = self.find_users_by_ids(&ids)?;
+
+        Ok(messages
+            .into_iter()
+            .map(|message| MessageWithAuthor {
+                author: users
+                    .get(&message.user_id)
+                    .map(|u| u.username.clone())
+                    .unwrap_or_else(|| "Unknown".to_string()),
+                message,
+            })
+            .collect())
     }
 
     pub fn find_user_by_email(&self, email: &str) -> Result<Option<User>> {
         let mut conn = self.pool.get_conn()?;
         let result = conn.exec_first(
-            "SELECT id, username, email, COALESCE(password, '') as password, chat_role, is_active,
-                    DATE_FORMAT(created_at, '%Y-%m-%d %H:%i:%s') as created_at
+            "SELECT id, username, email, chat_role, is_active,
+                    created_at
              FROM user WHERE email = :email",
             params! { "email" => email },
         )?;
 
-        Ok(result.map(|(id, username, email, password, chat_role, is_active, created_at)| User {
+        Ok(result.map(|(id, username, email, chat_role, is_active, created_at)| User {
             id,
             username,
             email,
-            password,
             chat_role,
             is_active,
             created_at,
@@ -105,17 +613,16 @@ impl Database {
     pub fn find_user_by_username(&self, username: &str) -> Result<Option<User>> {
         let mut conn = self.pool.get_conn()?;
         let result = conn.exec_first(
-            "SELECT id, username, email, COALESCE(password, '') as password, chat_role, is_active,
-                    DATE_FORMAT(created_at, '%Y-%m-%d %H:%i:%s') as created_at
+            "SELECT id, username, email, chat_role, is_active,
+                    created_at
              FROM user WHERE username = :username",
             params! { "username" => username },
         )?;
 
-        Ok(result.map(|(id, username, email, password, chat_role, is_active, created_at)| User {
+        Ok(result.map(|(id, username, email, chat_role, is_active, created_at)| User {
             id,
             username,
             email,
-            password,
             chat_role,
             is_active,
             created_at,
@@ -128,26 +635,26 @@ impl Database {
 
         let query = if let Some(exclude_id) = exclude_user_id {
             conn.exec_map(
-                "SELECT id, username, email, COALESCE(password, '') as password, chat_role, is_active,
-                        DATE_FORMAT(created_at, '%Y-%m-%d %H:%i:%s') as created_at
+                "SELECT id, username, email, chat_role, is_active,
+                        created_at
                  FROM user
                  WHERE (username LIKE :term OR email LIKE :term) AND id <> :exclude
                  ORDER BY username ASC LIMIT 50",
                 params! { "term" => &search_term, "exclude" => exclude_id },
-                |(id, username, email, password, chat_role, is_active, created_at)| User {
-                    id, username, email, password, chat_role, is_active, created_at,
+                |(id, username, email, chat_role, is_active, created_at)| User {
+                    id, username, email, chat_role, is_active, created_at,
                 },
             )?
         } else {
             conn.exec_map(
-                "SELECT id, username, email, COALESCE(password, '') as password, chat_role, is_active,
-                        DATE_FORMAT(created_at, '%Y-%m-%d %H:%i:%s') as created_at
+                "SELECT id, username, email, chat_role, is_active,
+                        created_at
                  FROM user
                  WHERE username LIKE :term OR email LIKE :term
                  ORDER BY username ASC LIMIT 50",
                 params! { "term" => &search_term },
-                |(id, username, email, password, chat_role, is_active, created_at)| User {
-                    id, username, email, password, chat_role, is_active, created_at,
+                |(id, username, email, chat_role, is_active, created_at)| User {
+                    id, username, email, chat_role, is_active, created_at,
                 },
             )?
         };
@@ -160,7 +667,7 @@ impl Database {
     pub fn find_conversation_by_id(&self, conversation_id: i32) -> Result<Option<Conversation>> {
         let mut conn = self.pool.get_conn()?;
         let result = conn.exec_first(
-            "SELECT id, title, is_group, DATE_FORMAT(created_at, '%Y-%m-%d %H:%i:%s') as created_at
+            "SELECT id, title, is_group, created_at
              FROM conversations WHERE id = :id",
             params! { "id" => conversation_id },
         )?;
@@ -173,10 +680,32 @@ impl Database {
         }))
     }
 
+    pub fn find_conversations_by_ids(&self, conversation_ids: &[i32]) -> Result<Vec<Conversation>> {
+        if conversation_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut conn = self.pool.get_conn()?;
+        let placeholders = vec!["?"; conversation_ids.len()].join(", ");
+        let conversations = conn.exec_map(
+            format!(
+                "SELECT id, title, is_group, created_at
+                 FROM conversations WHERE id IN ({})",
+                placeholders
+            ),
+            conversation_ids.to_vec(),
+            |(id, title, is_group, created_at)| Conversation {
+                id, title, is_group, created_at,
+            },
+        )?;
+
+        Ok(conversations)
+    }
+
     pub fn find_conversations_by_user(&self, user_id: i32) -> Result<Vec<Conversation>> {
         let mut conn = self.pool.get_conn()?;
         let conversations = conn.exec_map(
-            "SELECT c.id, c.title, c.is_group, DATE_FORMAT(c.created_at, '%Y-%m-%d %H:%i:%s') as created_at
+            "SELECT c.id, c.title, c.is_group, c.created_at
              FROM conversations c
              JOIN conversation_users cu ON cu.conversation_id = c.id
              WHERE cu.user_id = :uid
@@ -203,161 +732,1274 @@ impl Database {
     pub fn get_conversation_participants(&self, conversation_id: i32) -> Result<Vec<User>> {
         let mut conn = self.pool.get_conn()?;
         let participants = conn.exec_map(
-            "SELECT u.id, u.username, u.email, COALESCE(u.password, '') as password, u.chat_role, u.is_active,
-                    DATE_FORMAT(u.created_at, '%Y-%m-%d %H:%i:%s') as created_at
+            "SELECT u.id, u.username, u.email, u.chat_role, u.is_active,
+                    u.created_at
              FROM conversation_users cu
              JOIN user u ON u.id = cu.user_id
              WHERE cu.conversation_id = :cid
              ORDER BY u.username ASC",
             params! { "cid" => conversation_id },
-            |(id, username, email, password, chat_role, is_active, created_at)| User {
-                id, username, email, password, chat_role, is_active, created_at,
+            |(id, username, email, chat_role, is_active, created_at)| User {
+                id, username, email, chat_role, is_active, created_at,
             },
         )?;
 
         Ok(participants)
     }
 
-    // ===== MESSAGE OPERATIONS =====
+    pub fn find_membership(&self, conversation_id: i32, user_id: i32) -> Result<Option<ConversationUser>> {
+        let mut conn = self.pool.get_conn()?;
+        let result = conn.exec_first(
+            "SELECT conversation_id, user_id, is_admin FROM conversation_users
+             WHERE conversation_id = :cid AND user_id = :uid",
+            params! { "cid" => conversation_id, "uid" => user_id },
+        )?;
+
+        Ok(result.map(|(conversation_id, user_id, is_admin)| ConversationUser {
+            conversation_id, user_id, is_admin,
+        }))
+    }
 
-    pub fn find_messages_by_conversation(&self, conversation_id: i32, limit: i32) -> Result<Vec<Message>> {
+    pub fn count_conversation_admins(&self, conversation_id: i32) -> Result<i32> {
         let mut conn = self.pool.get_conn()?;
-        let messages = conn.exec_map(
-            "SELECT id, conversation_id, user_id, content, reaction, reply_to_id,
-                    DATE_FORMAT(created_at, '%Y-%m-%d %H:%i:%s') as created_at
-             FROM messages
-             WHERE conversation_id = :cid
-             ORDER BY created_at ASC
-             LIMIT :limit",
-            params! { "cid" => conversation_id, "limit" => limit },
-            |(id, conversation_id, user_id, content, reaction, reply_to_id, created_at)| Message {
-                id, conversation_id, user_id, content, reaction, reply_to_id, created_at,
-            },
+        let count: Option<i32> = conn.exec_first(
+            "SELECT COUNT(*) FROM conversation_users WHERE conversation_id = :cid AND is_admin = 1",
+            params! { "cid" => conversation_id },
         )?;
 
-        Ok(messages)
+        Ok(count.unwrap_or(0))
     }
 
-    pub fn find_message_by_id(&self, message_id: i32) -> Result<Option<Message>> {
+    /// Finds the existing one-to-one conversation between two users, if any.
+    pub fn find_direct_conversation(&self, user_a: i32, user_b: i32) -> Result<Option<i32>> {
         let mut conn = self.pool.get_conn()?;
         let result = conn.exec_first(
-            "SELECT id, conversation_id, user_id, content, reaction, reply_to_id,
-                    DATE_FORMAT(created_at, '%Y-%m-%d %H:%i:%s') as created_at
-             FROM messages WHERE id = :id",
-            params! { "id" => message_id },
+            "SELECT c.id FROM conversations c
+             JOIN conversation_users a ON a.conversation_id = c.id AND a.user_id = :a
+             JOIN conversation_users b ON b.conversation_id = c.id AND b.user_id = :b
+             WHERE c.is_group = 0
+               AND (SELECT COUNT(*) FROM conversation_users cu WHERE cu.conversation_id = c.id) = 2
+             ORDER BY c.id ASC LIMIT 1",
+            params! { "a" => user_a, "b" => user_b },
         )?;
 
-        Ok(result.map(|(id, conversation_id, user_id, content, reaction, reply_to_id, created_at)| Message {
-            id, conversation_id, user_id, content, reaction, reply_to_id, created_at,
-        }))
+        Ok(result)
     }
 
-    pub fn insert_message(
-        &self,
-        conversation_id: i32,
-        user_id: i32,
-        content: &str,
-        reply_to_id: Option<i32>,
-    ) -> Result<i32> {
+    pub fn rename_conversation(&self, conversation_id: i32, title: &str) -> Result<bool> {
         let mut conn = self.pool.get_conn()?;
         conn.exec_drop(
-            "INSERT INTO messages (conversation_id, user_id, content, reply_to_id)
-             VALUES (:cid, :uid, :content, :reply_to_id)",
-            params! {
-                "cid" => conversation_id,
-                "uid" => user_id,
-                "content" => content,
-                "reply_to_id" => reply_to_id,
-            },
+            "UPDATE conversations SET title = :title WHERE id = :id",
+            params! { "title" => title, "id" => conversation_id },
         )?;
 
-        Ok(conn.last_insert_id() as i32)
+        Ok(conn.affected_rows() > 0)
     }
 
-    pub fn get_all_conversations(&self) -> Result<Vec<Conversation>> {
+    /// Adds a participant. Returns false if they were already a member.
+    pub fn add_participant(&self, conversation_id: i32, user_id: i32, is_admin: bool) -> Result<bool> {
         let mut conn = self.pool.get_conn()?;
-        let conversations = conn.query_map(
-            "SELECT id, title, is_group, DATE_FORMAT(created_at, '%Y-%m-%d %H:%i:%s') as created_at
-             FROM conversations ORDER BY created_at DESC",
-            |(id, title, is_group, created_at)| Conversation {
-                id, title, is_group, created_at,
-            },
+        conn.exec_drop(
+            "INSERT IGNORE INTO conversation_users (conversation_id, user_id, is_admin)
+             VALUES (:cid, :uid, :is_admin)",
+            params! { "cid" => conversation_id, "uid" => user_id, "is_admin" => is_admin },
         )?;
 
-        Ok(conversations)
+        Ok(conn.affected_rows() > 0)
     }
 
-    // ===== AI HELPER METHODS =====
+    /// Removes a participant. Returns false if they were not a member.
+    pub fn remove_participant(&self, conversation_id: i32, user_id: i32) -> Result<bool> {
+        let mut conn = self.pool.get_conn()?;
+        conn.exec_drop(
+            "DELETE FROM conversation_users WHERE conversation_id = :cid AND user_id = :uid",
+            params! { "cid" => conversation_id, "uid" => user_id },
+        )?;
 
-    pub fn get_conversation_summary(&self, conversation_id: i32, message_limit: i32) -> Result<String> {
-        let messages = self.find_messages_by_conversation(conversation_id, message_limit)?;
+        Ok(conn.affected_rows() > 0)
+    }
 
-        if messages.is_empty() {
-            return Ok("No messages in this conversation.".to_string());
-        }
+    /// Opens a transaction for multi-step writes that live outside this
+    /// module, such as imports.
+    pub fn start_transaction(&self) -> Result<Transaction<'static>> {
+        self.pool.start_transaction(TxOpts::default())
+    }
 
-        let mut summary = String::new();
-        summary.push_str(&format!("Conversation summary (last {} messages):\n\n", messages.len()));
+    /// Runs `f` in a transaction: committed if it returns Ok, rolled back
+    /// otherwise. Compose the `*_in` write helpers inside it.
+    pub fn transaction<T>(&self, f: impl FnOnce(&mut Transaction<'static>) -> Result<T>) -> Result<T> {
+        let mut tx = self.start_transaction()?;
+        let value = f(&mut tx)?;
+        tx.commit()?;
+        Ok(value)
+    }
 
-        for msg in messages.iter() {
-            let user = self.find_user_by_id(msg.user_id)?;
-            let username = user.map(|u| u.username).unwrap_or_else(|| "Unknown".to_string());
-            summary.push_str(&format!("[{}] {}: {}\n", msg.created_at, username, msg.content));
-        }
+    /// Runs an already validated SELECT inside a read-only transaction with
+    /// a server-side statement timeout. Values are returned as the server sent them.
+    pub fn run_read_only_query(&self, sql: &str, timeout: Duration) -> Result<ReadOnlyRows> {
+        let mut conn = self.pool.get_conn()?;
+        conn.query_drop(format!("SET SESSION MAX_EXECUTION_TIME = {}", timeout.as_millis()))?;
+
+        let result = (|| {
+            let mut tx = conn.start_transaction(TxOpts::default().set_access_mode(Some(AccessMode::ReadOnly)))?;
+            let rows = {
+                let mut result = tx.query_iter(sql)?;
+                let columns = result
+                    .columns()
+                    .as_ref()
+                    .iter()
+                    .map(|column| ReadOnlyColumn {
+                        name: column.name_str().to_string(),
+                        column_type: column_type_name(column.column_type()),
+                    })
+                    .collect();
+                let rows = result
+                    .by_ref()
+                    .map(|row| row.map(Row::unwrap))
+                    .collect::<Result<Vec<_>>>()?;
+                ReadOnlyRows { columns, rows }
+            };
+            tx.rollback()?;
+            Ok(rows)
+        })();
 
-        Ok(summary)
+        // The connection goes back to the pool; don't leave the limit on it.
+        conn.query_drop("SET SESSION MAX_EXECUTION_TIME = DEFAULT")?;
+        result
     }
 
-    pub fn search_messages(&self, conversation_id: i32, search_term: &str) -> Result<Vec<Message>> {
+    // ===== MESSAGE OPERATIONS =====
+
+    pub fn find_messages_page(&self, conversation_id: i32, query: &MessageQuery) -> Result<MessagePage> {
         let mut conn = self.pool.get_conn()?;
-        let search_pattern = format!("%{}%", search_term);
+        let forward = query.after_id.is_some() && query.before_id.is_none();
+        let order = if forward { "ASC" } else { "DESC" };
 
-        let messages = conn.exec_map(
-            "SELECT id, conversation_id, user_id, content, reaction, reply_to_id,
-                    DATE_FORMAT(created_at, '%Y-%m-%d %H:%i:%s') as created_at
-             FROM messages
-             WHERE conversation_id = :cid AND content LIKE :term
-             ORDER BY created_at DESC
-             LIMIT 50",
-            params! { "cid" => conversation_id, "term" => &search_pattern },
+        let sql = format!(
+            "SELECT m.id, m.conversation_id, m.user_id, m.content, m.reaction, m.reply_to_id,
+                    m.created_at
+             FROM messages m
+             WHERE m.conversation_id = :cid{filter}
+             ORDER BY m.created_at {order}, m.id {order}
+             LIMIT :limit",
+            filter = MESSAGE_RANGE_FILTER,
+            order = order,
+        );
+
+        let mut messages = conn.exec_map(
+            sql,
+            params! {
+                "cid" => conversation_id,
+                "before_id" => query.before_id,
+                "after_id" => query.after_id,
+                "since" => &query.since,
+                "until" => &query.until,
+                "limit" => query.limit + 1,
+            },
             |(id, conversation_id, user_id, content, reaction, reply_to_id, created_at)| Message {
                 id, conversation_id, user_id, content, reaction, reply_to_id, created_at,
             },
         )?;
 
-        Ok(messages)
+        let has_more = messages.len() > query.limit.max(0) as usize;
+        messages.truncate(query.limit.max(0) as usize);
+        if !forward {
+            messages.reverse();
+        }
+
+        // The direction we walked is answered by the extra row; the other
+        // direction needs one existence check past the opposite edge.
+        let (has_older, has_newer) = match (messages.first(), messages.last()) {
+            (Some(first), Some(_)) if forward => {
+                (self.has_messages_beyond(conversation_id, first.id, false, query)?, has_more)
+            }
+            (Some(_), Some(last)) => {
+                (has_more, self.has_messages_beyond(conversation_id, last.id, true, query)?)
+            }
+            _ => (query.after_id.is_some(), query.before_id.is_some()),
+        };
+
+        Ok(MessagePage {
+            older_cursor: messages.first().filter(|_| has_older).map(|m| m.id),
+            newer_cursor: messages.last().filter(|_| has_newer).map(|m| m.id),
+            messages: self.with_authors(messages)?,
+        })
     }
 
-    pub fn get_conversation_statistics(&self, conversation_id: i32) -> Result<serde_json::Value> {
+    fn has_messages_beyond(&self, conversation_id: i32, pivot_id: i32, newer: bool, query: &MessageQuery) -> Result<bool> {
         let mut conn = self.pool.get_conn()?;
+        let (before_id, after_id) = if newer { (None, Some(pivot_id)) } else { (Some(pivot_id), None) };
 
-        let total_messages: i32 = conn.exec_first(
-            "SELECT COUNT(*) FROM messages WHERE conversation_id = :cid",
-            params! { "cid" => conversation_id },
-        )?.unwrap_or(0);
+        let result: Option<i32> = conn.exec_first(
+            format!(
+                "SELECT 1 FROM messages m WHERE m.conversation_id = :cid{} LIMIT 1",
+                MESSAGE_RANGE_FILTER
+            ),
+            params! {
+                "cid" => conversation_id,
+                "before_id" => before_id,
+                "after_id" => after_id,
+                "since" => &query.since,
+                "until" => &query.until,
+            },
+        )?;
 
-        let participant_count: i32 = conn.exec_first(
-            "SELECT COUNT(*) FROM conversation_users WHERE conversation_id = :cid",
-            params! { "cid" => conversation_id },
-        )?.unwrap_or(0);
+        Ok(result.is_some())
+    }
 
-        let conversation = self.find_conversation_by_id(conversation_id)?
-            .ok_or_else(|| {
-                Error::from(mysql::error::Error::from(
-                    std::io::Error::new(
-                        std::io::ErrorKind::NotFound,
-                        "Conversation not found"
-                    )
-                ))
-            })?;
+    pub fn find_message_by_id(&self, message_id: i32) -> Result<Option<Message>> {
+        let mut conn = self.pool.get_conn()?;
+        let result = conn.exec_first(
+            "SELECT id, conversation_id, user_id, content, reaction, reply_to_id,
+                    created_at
+             FROM messages WHERE id = :id AND deleted_at IS NULL",
+            params! { "id" => message_id },
+        )?;
 
-        Ok(serde_json::json!({
-            "conversation_id": conversation_id,
-            "title": conversation.title,
-            "is_group": conversation.is_group,
-            "total_messages": total_messages,
-            "participant_count": participant_count,
-            "created_at": conversation.created_at,
+        Ok(result.map(|(id, conversation_id, user_id, content, reaction, reply_to_id, created_at)| Message {
+            id, conversation_id, user_id, content, reaction, reply_to_id, created_at,
         }))
     }
-}
\ No newline at end of file
+
+    /// Like `find_message_by_id`, but also finds soft-deleted messages and
+    /// returns when they were deleted.
+    pub fn find_message_including_deleted(&self, message_id: i32) -> Result<Option<(Message, Option<Timestamp>)>> {
+        let mut conn = self.pool.get_conn()?;
+        let result = conn.exec_first(
+            "SELECT id, conversation_id, user_id, content, reaction, reply_to_id,
+                    created_at, deleted_at
+             FROM messages WHERE id = :id",
+            params! { "id" => message_id },
+        )?;
+
+        Ok(result.map(|(id, conversation_id, user_id, content, reaction, reply_to_id, created_at, deleted_at)| (
+            Message { id, conversation_id, user_id, content, reaction, reply_to_id, created_at },
+            deleted_at,
+        )))
+    }
+
+    /// Oldest-first batch of messages after `after_id` (or from the start),
+    /// for walking a whole conversation.
+    pub fn find_messages_ascending(&self, conversation_id: i32, after_id: Option<i32>, limit: i32) -> Result<Vec<Message>> {
+        let mut conn = self.pool.get_conn()?;
+        let sql = format!(
+            "SELECT m.id, m.conversation_id, m.user_id, m.content, m.reaction, m.reply_to_id,
+                    m.created_at
+             FROM messages m
+             WHERE m.conversation_id = :cid{filter}
+             ORDER BY m.created_at ASC, m.id ASC
+             LIMIT :limit",
+            filter = MESSAGE_RANGE_FILTER,
+        );
+
+        let messages = conn.exec_map(
+            sql,
+            params! {
+                "cid" => conversation_id,
+                "before_id" => None::<i32>,
+                "after_id" => after_id,
+                "since" => None::<Timestamp>,
+                "until" => None::<Timestamp>,
+                "limit" => limit,
+            },
+            |(id, conversation_id, user_id, content, reaction, reply_to_id, created_at)| Message {
+                id, conversation_id, user_id, content, reaction, reply_to_id, created_at,
+            },
+        )?;
+
+        Ok(messages)
+    }
+
+    /// Highest message id in a conversation, deleted or not. Used as the
+    /// starting point for live feeds.
+    pub fn latest_message_id(&self, conversation_id: i32) -> Result<Option<i32>> {
+        let mut conn = self.pool.get_conn()?;
+        let latest: Option<Option<i32>> = conn.exec_first(
+            "SELECT MAX(id) FROM messages WHERE conversation_id = :cid",
+            params! { "cid" => conversation_id },
+        )?;
+
+        Ok(latest.flatten())
+    }
+
+    /// Messages with an id greater than `after_id`, in id order. Ids grow
+    /// with inserts, so this is how live feeds pick up new rows.
+    pub fn find_messages_after_id(&self, conversation_id: i32, after_id: i32, limit: i32) -> Result<Vec<Message>> {
+        let mut conn = self.pool.get_conn()?;
+        let messages = conn.exec_map(
+            "SELECT id, conversation_id, user_id, content, reaction, reply_to_id, created_at
+             FROM messages
+             WHERE conversation_id = :cid AND id > :after_id AND deleted_at IS NULL
+             ORDER BY id ASC
+             LIMIT :limit",
+            params! { "cid" => conversation_id, "after_id" => after_id, "limit" => limit },
+            |(id, conversation_id, user_id, content, reaction, reply_to_id, created_at)| Message {
+                id, conversation_id, user_id, content, reaction, reply_to_id, created_at,
+            },
+        )?;
+
+        Ok(messages)
+    }
+
+    /// Non-deleted messages across all conversations with an id above
+    /// `after_id`, in id order. Feeds the embedding index.
+    pub fn find_messages_after_id_global(&self, after_id: i32, limit: i32) -> Result<Vec<Message>> {
+        let mut conn = self.pool.get_conn()?;
+        let messages = conn.exec_map(
+            "SELECT id, conversation_id, user_id, content, reaction, reply_to_id, created_at
+             FROM messages
+             WHERE id > :after_id AND deleted_at IS NULL
+             ORDER BY id ASC
+             LIMIT :limit",
+            params! { "after_id" => after_id, "limit" => limit },
+            |(id, conversation_id, user_id, content, reaction, reply_to_id, created_at)| Message {
+                id, conversation_id, user_id, content, reaction, reply_to_id, created_at,
+            },
+        )?;
+
+        Ok(messages)
+    }
+
+    /// Non-deleted messages edited at or after `since`, paged by id after
+    /// `after_id`.
+    pub fn find_messages_edited_since(&self, since: Timestamp, after_id: i32, limit: i32) -> Result<Vec<Message>> {
+        let mut conn = self.pool.get_conn()?;
+        let messages = conn.exec_map(
+            "SELECT id, conversation_id, user_id, content, reaction, reply_to_id, created_at
+             FROM messages
+             WHERE edited_at >= :since AND id > :after_id AND deleted_at IS NULL
+             ORDER BY id ASC
+             LIMIT :limit",
+            params! { "since" => since, "after_id" => after_id, "limit" => limit },
+            |(id, conversation_id, user_id, content, reaction, reply_to_id, created_at)| Message {
+                id, conversation_id, user_id, content, reaction, reply_to_id, created_at,
+            },
+        )?;
+
+        Ok(messages)
+    }
+
+    /// The ids among `message_ids` that still exist and are not deleted.
+    pub fn find_live_message_ids(&self, message_ids: &[i32]) -> Result<HashSet<i32>> {
+        let mut live = HashSet::new();
+        let mut conn = self.pool.get_conn()?;
+        for chunk in message_ids.chunks(1000) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let ids: Vec<i32> = conn.exec(
+                format!("SELECT id FROM messages WHERE id IN ({}) AND deleted_at IS NULL", placeholders),
+                chunk.to_vec(),
+            )?;
+            live.extend(ids);
+        }
+        Ok(live)
+    }
+
+    /// Non-deleted messages among `message_ids`, in no particular order.
+    pub fn find_messages_by_ids(&self, message_ids: &[i32]) -> Result<Vec<Message>> {
+        if message_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut conn = self.pool.get_conn()?;
+        let placeholders = vec!["?"; message_ids.len()].join(", ");
+        let messages = conn.exec_map(
+            format!(
+                "SELECT id, conversation_id, user_id, content, reaction, reply_to_id, created_at
+                 FROM messages WHERE id IN ({}) AND deleted_at IS NULL",
+                placeholders
+            ),
+            message_ids.to_vec(),
+            |(id, conversation_id, user_id, content, reaction, reply_to_id, created_at)| Message {
+                id, conversation_id, user_id, content, reaction, reply_to_id, created_at,
+            },
+        )?;
+
+        Ok(messages)
+    }
+
+    /// Direct replies to any of `parent_ids`, oldest first.
+    pub fn find_replies(&self, parent_ids: &[i32]) -> Result<Vec<Message>> {
+        if parent_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut conn = self.pool.get_conn()?;
+        let placeholders = vec!["?"; parent_ids.len()].join(", ");
+        let messages = conn.exec_map(
+            format!(
+                "SELECT id, conversation_id, user_id, content, reaction, reply_to_id,
+                        created_at
+                 FROM messages WHERE reply_to_id IN ({}) AND deleted_at IS NULL
+                 ORDER BY created_at ASC, id ASC",
+                placeholders
+            ),
+            parent_ids.to_vec(),
+            |(id, conversation_id, user_id, content, reaction, reply_to_id, created_at)| Message {
+                id, conversation_id, user_id, content, reaction, reply_to_id, created_at,
+            },
+        )?;
+
+        Ok(messages)
+    }
+
+    pub fn insert_message(
+        &self,
+        conversation_id: i32,
+        user_id: i32,
+        content: &str,
+        reply_to_id: Option<i32>,
+    ) -> Result<i32> {
+        let mut conn = self.pool.get_conn()?;
+        insert_message_in(&mut conn, conversation_id, user_id, content, reply_to_id)
+    }
+
+    /// Replaces a message's content, keeping the previous content in the edit
+    /// history. Returns false if the message does not exist or was deleted.
+    pub fn edit_message(&self, message_id: i32, editor_id: i32, content: &str) -> Result<bool> {
+        let mut conn = self.pool.get_conn()?;
+        let mut tx = conn.start_transaction(TxOpts::default())?;
+
+        let previous: Option<String> = tx.exec_first(
+            "SELECT content FROM messages WHERE id = :id AND deleted_at IS NULL FOR UPDATE",
+            params! { "id" => message_id },
+        )?;
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return Ok(false),
+        };
+
+        tx.exec_drop(
+            "INSERT INTO orbit_message_edits (message_id, editor_id, action, previous_content)
+             VALUES (:id, :editor, 'edit', :previous)",
+            params! { "id" => message_id, "editor" => editor_id, "previous" => &previous },
+        )?;
+        tx.exec_drop(
+            "UPDATE messages SET content = :content, edited_at = NOW() WHERE id = :id",
+            params! { "content" => content, "id" => message_id },
+        )?;
+
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Hides a message from every query while keeping the row and recording
+    /// its content in the edit history under `action` (delete or undo).
+    pub fn soft_delete_message(&self, message_id: i32, editor_id: i32, action: &str) -> Result<bool> {
+        let mut conn = self.pool.get_conn()?;
+        let mut tx = conn.start_transaction(TxOpts::default())?;
+
+        let previous: Option<String> = tx.exec_first(
+            "SELECT content FROM messages WHERE id = :id AND deleted_at IS NULL FOR UPDATE",
+            params! { "id" => message_id },
+        )?;
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return Ok(false),
+        };
+
+        tx.exec_drop(
+            "INSERT INTO orbit_message_edits (message_id, editor_id, action, previous_content)
+             VALUES (:id, :editor, :action, :previous)",
+            params! { "id" => message_id, "editor" => editor_id, "action" => action, "previous" => &previous },
+        )?;
+        tx.exec_drop(
+            "UPDATE messages SET deleted_at = NOW() WHERE id = :id",
+            params! { "id" => message_id },
+        )?;
+
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Edit history of a message, oldest change first.
+    pub fn get_message_edit_history(&self, message_id: i32) -> Result<Vec<MessageEdit>> {
+        let mut conn = self.pool.get_conn()?;
+        let edits = conn.exec_map(
+            "SELECT message_id, editor_id, action, previous_content,
+                    created_at
+             FROM orbit_message_edits
+             WHERE message_id = :id
+             ORDER BY created_at ASC, id ASC",
+            params! { "id" => message_id },
+            |(message_id, editor_id, action, previous_content, created_at)| MessageEdit {
+                message_id, editor_id, action, previous_content, created_at,
+            },
+        )?;
+
+        Ok(edits)
+    }
+
+    // ===== IDEMPOTENCY KEYS =====
+
+    /// Claims `key` for a write by `acting_user`. Claims older than `window`
+    /// are forgotten first, so a key can be reused once its window has passed.
+    pub fn claim_idempotency_key(
+        &self,
+        acting_user: &str,
+        key: &str,
+        tool: &str,
+        arguments: &str,
+        window: Duration,
+    ) -> Result<IdempotencyClaim> {
+        let mut conn = self.pool.get_conn()?;
+        conn.exec_drop(
+            "DELETE FROM orbit_idempotency_keys
+             WHERE acting_user = :user AND idempotency_key = :key
+               AND created_at < NOW() - INTERVAL :secs SECOND",
+            params! { "user" => acting_user, "key" => key, "secs" => window.as_secs() },
+        )?;
+        conn.exec_drop(
+            "INSERT IGNORE INTO orbit_idempotency_keys (acting_user, idempotency_key, tool, arguments)
+             VALUES (:user, :key, :tool, :arguments)",
+            params! { "user" => acting_user, "key" => key, "tool" => tool, "arguments" => arguments },
+        )?;
+        if conn.affected_rows() > 0 {
+            return Ok(IdempotencyClaim::Claimed);
+        }
+
+        let existing: Option<(String, String, Option<String>)> = conn.exec_first(
+            "SELECT tool, arguments, result FROM orbit_idempotency_keys
+             WHERE acting_user = :user AND idempotency_key = :key",
+            params! { "user" => acting_user, "key" => key },
+        )?;
+        Ok(match existing {
+            Some((tool, arguments, result)) => IdempotencyClaim::Existing { tool, arguments, result },
+            // Expired and deleted by someone else in between; treat as new.
+            None => IdempotencyClaim::Claimed,
+        })
+    }
+
+    /// Stores the result of a claimed write so retries can replay it.
+    pub fn complete_idempotency_key(&self, acting_user: &str, key: &str, result: &str) -> Result<()> {
+        let mut conn = self.pool.get_conn()?;
+        conn.exec_drop(
+            "UPDATE orbit_idempotency_keys SET result = :result
+             WHERE acting_user = :user AND idempotency_key = :key",
+            params! { "result" => result, "user" => acting_user, "key" => key },
+        )
+    }
+
+    /// Drops a claim whose write failed, so the call can be retried.
+    pub fn release_idempotency_key(&self, acting_user: &str, key: &str) -> Result<()> {
+        let mut conn = self.pool.get_conn()?;
+        conn.exec_drop(
+            "DELETE FROM orbit_idempotency_keys
+             WHERE acting_user = :user AND idempotency_key = :key AND result IS NULL",
+            params! { "user" => acting_user, "key" => key },
+        )
+    }
+
+    // ===== RETENTION =====
+
+    pub fn get_retention_rules(&self) -> Result<Vec<RetentionRule>> {
+        let mut conn = self.pool.get_conn()?;
+        let rules = conn.query_map(
+            "SELECT scope, conversation_id, max_age_days, updated_at
+             FROM orbit_retention_rules
+             ORDER BY scope, conversation_id",
+            |(scope, conversation_id, max_age_days, updated_at): (String, i32, Option<u32>, Timestamp)| RetentionRule {
+                scope,
+                conversation_id: (conversation_id != 0).then_some(conversation_id),
+                max_age_days,
+                updated_at,
+            },
+        )?;
+
+        Ok(rules)
+    }
+
+    /// Creates or replaces a rule. `max_age_days` None keeps messages forever.
+    pub fn set_retention_rule(
+        &self,
+        scope: &str,
+        conversation_id: Option<i32>,
+        max_age_days: Option<u32>,
+        user_id: i32,
+    ) -> Result<()> {
+        let mut conn = self.pool.get_conn()?;
+        conn.exec_drop(
+            "INSERT INTO orbit_retention_rules (scope, conversation_id, max_age_days, updated_by)
+             VALUES (:scope, :cid, :days, :uid)
+             ON DUPLICATE KEY UPDATE max_age_days = VALUES(max_age_days), updated_by = VALUES(updated_by)",
+            params! {
+                "scope" => scope,
+                "cid" => conversation_id.unwrap_or(0),
+                "days" => max_age_days,
+                "uid" => user_id,
+            },
+        )
+    }
+
+    pub fn delete_retention_rule(&self, scope: &str, conversation_id: Option<i32>) -> Result<bool> {
+        let mut conn = self.pool.get_conn()?;
+        conn.exec_drop(
+            "DELETE FROM orbit_retention_rules WHERE scope = :scope AND conversation_id = :cid",
+            params! { "scope" => scope, "cid" => conversation_id.unwrap_or(0) },
+        )?;
+
+        Ok(conn.affected_rows() > 0)
+    }
+
+    /// Messages created before `cutoff`, including soft-deleted ones.
+    pub fn count_messages_before(&self, conversation_id: i32, cutoff: Timestamp) -> Result<usize> {
+        let mut conn = self.pool.get_conn()?;
+        let count: Option<usize> = conn.exec_first(
+            "SELECT COUNT(*) FROM messages WHERE conversation_id = :cid AND created_at < :cutoff",
+            params! { "cid" => conversation_id, "cutoff" => cutoff },
+        )?;
+
+        Ok(count.unwrap_or(0))
+    }
+
+    /// Full rows (soft-deleted included) with their edit history, oldest id
+    /// first, for archiving before a purge.
+    pub fn find_messages_for_archive(
+        &self,
+        conversation_id: i32,
+        cutoff: Timestamp,
+        after_id: i32,
+        limit: usize,
+    ) -> Result<Vec<ArchivedMessage>> {
+        let mut conn = self.pool.get_conn()?;
+        // A row that does not convert fails the purge instead of being archived
+        // with made-up values and then deleted.
+        let mut messages = conn
+            .exec_map_opt(
+                "SELECT id, conversation_id, user_id, content, reaction, reply_to_id, created_at, edited_at, deleted_at
+                 FROM messages
+                 WHERE conversation_id = :cid AND created_at < :cutoff AND id > :after_id
+                 ORDER BY id ASC
+                 LIMIT :limit",
+                params! { "cid" => conversation_id, "cutoff" => cutoff, "after_id" => after_id, "limit" => limit },
+                |row| row.map(|(id, conversation_id, user_id, content, reaction, reply_to_id, created_at, edited_at, deleted_at)| ArchivedMessage {
+                    message: Message { id, conversation_id, user_id, content, reaction, reply_to_id, created_at },
+                    edited_at,
+                    deleted_at,
+                    edits: Vec::new(),
+                }),
+            )?
+            .into_iter()
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        if !messages.is_empty() {
+            let ids: Vec<i32> = messages.iter().map(|m| m.message.id).collect();
+            let placeholders = vec!["?"; ids.len()].join(", ");
+            let edits = conn.exec_map(
+                format!(
+                    "SELECT message_id, editor_id, action, previous_content, created_at
+                     FROM orbit_message_edits
+                     WHERE message_id IN ({})
+                     ORDER BY created_at ASC, id ASC",
+                    placeholders
+                ),
+                ids,
+                |(message_id, editor_id, action, previous_content, created_at)| MessageEdit {
+                    message_id, editor_id, action, previous_content, created_at,
+                },
+            )?;
+            let mut by_id: HashMap<i32, Vec<MessageEdit>> = HashMap::new();
+            for edit in edits {
+                by_id.entry(edit.message_id).or_default().push(edit);
+            }
+            for message in &mut messages {
+                message.edits = by_id.remove(&message.message.id).unwrap_or_default();
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// Hard-deletes messages with their edit history in one transaction.
+    /// Replies to them lose their reply link, and the conversations' cached
+    /// summaries are dropped since they may quote the purged content.
+    pub fn purge_messages(&self, conversation_id: i32, ids: &[i32]) -> Result<usize> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let placeholders = vec!["?"; ids.len()].join(", ");
+
+        self.transaction(|tx| {
+            tx.exec_drop(
+                format!("UPDATE messages SET reply_to_id = NULL WHERE reply_to_id IN ({})", placeholders),
+                ids.to_vec(),
+            )?;
+            tx.exec_drop(
+                format!("DELETE FROM orbit_message_edits WHERE message_id IN ({})", placeholders),
+                ids.to_vec(),
+            )?;
+            tx.exec_drop(
+                format!("DELETE FROM messages WHERE id IN ({})", placeholders),
+                ids.to_vec(),
+            )?;
+            let deleted = tx.affected_rows() as usize;
+            tx.exec_drop(
+                "DELETE FROM orbit_conversation_summaries WHERE conversation_id = :cid",
+                params! { "cid" => conversation_id },
+            )?;
+            Ok(deleted)
+        })
+    }
+
+    pub fn record_retention_audit(&self, audit: &RetentionAudit) -> Result<()> {
+        let mut conn = self.pool.get_conn()?;
+        conn.exec_drop(
+            "INSERT INTO orbit_retention_audit
+                (started_at, finished_at, triggered_by, dry_run, conversations, messages_deleted, archive_path, details, error)
+             VALUES (:started_at, :finished_at, :triggered_by, :dry_run, :conversations, :messages_deleted, :archive_path, :details, :error)",
+            params! {
+                "started_at" => audit.started_at,
+                "finished_at" => audit.finished_at,
+                "triggered_by" => &audit.triggered_by,
+                "dry_run" => audit.dry_run,
+                "conversations" => audit.conversations,
+                "messages_deleted" => audit.messages_deleted,
+                "archive_path" => &audit.archive_path,
+                "details" => &audit.details,
+                "error" => &audit.error,
+            },
+        )
+    }
+
+    // ===== SUMMARY CACHE =====
+
+    pub fn get_cached_summary(&self, conversation_id: i32) -> Result<Option<CachedSummary>> {
+        let mut conn = self.pool.get_conn()?;
+        let result = conn.exec_first(
+            "SELECT conversation_id, summary, last_message_id, updated_by, updated_at
+             FROM orbit_conversation_summaries WHERE conversation_id = :cid",
+            params! { "cid" => conversation_id },
+        )?;
+
+        Ok(result.map(|(conversation_id, summary, last_message_id, updated_by, updated_at)| CachedSummary {
+            conversation_id, summary, last_message_id, updated_by, updated_at,
+        }))
+    }
+
+    /// Stores a summary covering messages up to `last_message_id`, replacing
+    /// the previous one. An older summary never overwrites a newer one;
+    /// returns false in that case.
+    pub fn save_cached_summary(&self, conversation_id: i32, summary: &str, last_message_id: i32, user_id: i32) -> Result<bool> {
+        let mut conn = self.pool.get_conn()?;
+        conn.exec_drop(
+            "INSERT INTO orbit_conversation_summaries (conversation_id, summary, last_message_id, updated_by)
+             VALUES (:cid, :summary, :last_id, :uid)
+             ON DUPLICATE KEY UPDATE
+                summary = IF(VALUES(last_message_id) >= last_message_id, VALUES(summary), summary),
+                updated_by = IF(VALUES(last_message_id) >= last_message_id, VALUES(updated_by), updated_by),
+                last_message_id = GREATEST(last_message_id, VALUES(last_message_id))",
+            params! { "cid" => conversation_id, "summary" => summary, "last_id" => last_message_id, "uid" => user_id },
+        )?;
+
+        let stored: Option<i32> = conn.exec_first(
+            "SELECT last_message_id FROM orbit_conversation_summaries WHERE conversation_id = :cid",
+            params! { "cid" => conversation_id },
+        )?;
+        Ok(stored.is_some_and(|stored| stored <= last_message_id))
+    }
+
+    /// Non-deleted messages with an id above `after_id`.
+    pub fn count_messages_after_id(&self, conversation_id: i32, after_id: i32) -> Result<i64> {
+        let mut conn = self.pool.get_conn()?;
+        let count: Option<i64> = conn.exec_first(
+            "SELECT COUNT(*) FROM messages WHERE conversation_id = :cid AND id > :after_id AND deleted_at IS NULL",
+            params! { "cid" => conversation_id, "after_id" => after_id },
+        )?;
+
+        Ok(count.unwrap_or(0))
+    }
+
+    pub fn get_all_conversations(&self) -> Result<Vec<Conversation>> {
+        let mut conn = self.pool.get_conn()?;
+        let conversations = conn.query_map(
+            "SELECT id, title, is_group, created_at
+             FROM conversations ORDER BY created_at DESC",
+            |(id, title, is_group, created_at)| Conversation {
+                id, title, is_group, created_at,
+            },
+        )?;
+
+        Ok(conversations)
+    }
+
+    // ===== THREAD AND REACTION OPERATIONS =====
+
+    /// Rebuilds the thread around a message: the chain of messages it replies
+    /// to and the full tree of replies below it, within fixed size limits.
+    pub fn get_message_thread(&self, message_id: i32) -> Result<Option<MessageThread>> {
+        const MAX_DEPTH: usize = 20;
+        const MAX_REPLIES: usize = 200;
+
+        let message = match self.find_message_by_id(message_id)? {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+
+        let mut truncated = false;
+        let mut ancestors = Vec::new();
+        let mut seen = vec![message.id];
+        let mut parent_id = message.reply_to_id;
+        while let Some(id) = parent_id {
+            if seen.contains(&id) {
+                break;
+            }
+            if ancestors.len() >= MAX_DEPTH {
+                truncated = true;
+                break;
+            }
+            match self.find_message_by_id(id)? {
+                Some(parent) => {
+                    seen.push(parent.id);
+                    parent_id = parent.reply_to_id;
+                    ancestors.push(parent);
+                }
+                None => break,
+            }
+        }
+        ancestors.reverse();
+
+        let mut descendants = Vec::new();
+        let mut frontier = vec![message.id];
+        let mut depth = 0;
+        loop {
+            let replies: Vec<Message> = self
+                .find_replies(&frontier)?
+                .into_iter()
+                .filter(|m| !seen.contains(&m.id))
+                .collect();
+            if replies.is_empty() {
+                break;
+            }
+            if depth == MAX_DEPTH || descendants.len() + replies.len() > MAX_REPLIES {
+                truncated = true;
+                break;
+            }
+            depth += 1;
+            frontier = replies.iter().map(|m| m.id).collect();
+            seen.extend(frontier.iter().copied());
+            descendants.extend(replies);
+        }
+
+        let ancestors = self.with_authors(ancestors)?;
+        let mut entries = self.with_authors(std::iter::once(message).chain(descendants).collect())?;
+        let root = entries.remove(0);
+
+        Ok(Some(MessageThread {
+            ancestors,
+            message: build_thread_node(root, &mut entries),
+            truncated,
+        }))
+    }
+
+    /// Returns a message with up to `before` earlier and `after` later messages
+    /// from the same conversation.
+    pub fn get_message_context(&self, message_id: i32, before: i32, after: i32) -> Result<Option<MessageContext>> {
+        let message = match self.find_message_by_id(message_id)? {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+
+        let older = self.find_messages_page(message.conversation_id, &MessageQuery {
+            limit: before,
+            before_id: Some(message.id),
+            ..Default::default()
+        })?;
+        let newer = self.find_messages_page(message.conversation_id, &MessageQuery {
+            limit: after,
+            after_id: Some(message.id),
+            ..Default::default()
+        })?;
+
+        Ok(Some(MessageContext {
+            before: older.messages,
+            message: self.with_authors(vec![message])?.remove(0),
+            after: newer.messages,
+        }))
+    }
+
+    /// Aggregates reactions by value, by the author who received them and by
+    /// message. A reaction column holding several comma-separated reactions
+    /// counts each of them. With a `viewer_id`, only conversations that user
+    /// belongs to are counted.
+    pub fn get_reaction_stats(&self, scope: ReactionScope, viewer_id: Option<i32>, top: usize) -> Result<ReactionStats> {
+        let mut conn = self.pool.get_conn()?;
+        let (filter, scope_id) = match scope {
+            ReactionScope::Conversation(id) => ("conversation_id = :scope", id),
+            ReactionScope::Author(id) => ("user_id = :scope", id),
+        };
+
+        let messages = conn.exec_map(
+            format!(
+                "SELECT id, conversation_id, user_id, content, reaction, reply_to_id,
+                        created_at
+                 FROM messages
+                 WHERE {} AND reaction IS NOT NULL AND reaction <> '' AND deleted_at IS NULL
+                   AND (:viewer IS NULL OR conversation_id IN
+                        (SELECT conversation_id FROM conversation_users WHERE user_id = :viewer))
+                 ORDER BY created_at ASC, id ASC",
+                filter
+            ),
+            params! { "scope" => scope_id, "viewer" => viewer_id },
+            |(id, conversation_id, user_id, content, reaction, reply_to_id, created_at)| Message {
+                id, conversation_id, user_id, content, reaction, reply_to_id, created_at,
+            },
+        )?;
+        let messages = self.with_authors(messages)?;
+
+        let mut by_reaction: Vec<ReactionCount> = Vec::new();
+        let mut by_author: Vec<AuthorReactions> = Vec::new();
+        let mut top_messages = Vec::new();
+        let mut total_reactions = 0;
+
+        for entry in messages {
+            let reactions = split_reactions(entry.message.reaction.as_deref().unwrap_or(""));
+            if reactions.is_empty() {
+                continue;
+            }
+            total_reactions += reactions.len();
+
+            let author = match by_author.iter_mut().position(|a| a.user_id == entry.message.user_id) {
+                Some(idx) => &mut by_author[idx],
+                None => {
+                    by_author.push(AuthorReactions {
+                        user_id: entry.message.user_id,
+                        author: entry.author.clone(),
+                        reactions_received: 0,
+                        by_reaction: Vec::new(),
+                    });
+                    by_author.last_mut().unwrap()
+                }
+            };
+            author.reactions_received += reactions.len();
+
+            for reaction in &reactions {
+                increment_reaction(&mut by_reaction, reaction);
+                increment_reaction(&mut author.by_reaction, reaction);
+            }
+
+            top_messages.push(ReactedMessage {
+                reaction_count: reactions.len(),
+                message: entry,
+            });
+        }
+
+        by_reaction.sort_by_key(|x| std::cmp::Reverse(x.count));
+        by_author.sort_by_key(|x| std::cmp::Reverse(x.reactions_received));
+        for author in by_author.iter_mut() {
+            author.by_reaction.sort_by_key(|x| std::cmp::Reverse(x.count));
+        }
+        // Stable sort keeps older messages first among equal counts.
+        top_messages.sort_by_key(|x| std::cmp::Reverse(x.reaction_count));
+        top_messages.truncate(top);
+
+        Ok(ReactionStats {
+            total_reactions,
+            by_reaction,
+            by_author,
+            top_messages,
+        })
+    }
+
+    // ===== AI HELPER METHODS =====
+
+    pub fn get_conversation_summary(&self, conversation_id: i32, query: &MessageQuery) -> Result<ConversationSummary> {
+        let page = self.find_messages_page(conversation_id, query)?;
+
+        let mut summary = String::new();
+        if page.messages.is_empty() {
+            summary.push_str("No messages in this conversation.");
+        } else if query.after_id.is_some() && query.before_id.is_none() {
+            summary.push_str(&format!("Conversation summary ({} messages after #{}):\n\n", page.messages.len(), query.after_id.unwrap_or_default()));
+        } else {
+            summary.push_str(&format!("Conversation summary (last {} messages):\n\n", page.messages.len()));
+        }
+
+        for entry in page.messages.iter() {
+            let msg = &entry.message;
+            summary.push_str(&format!("[#{} {}] {}: {}\n", msg.id, msg.created_at, entry.author, msg.content));
+        }
+
+        Ok(ConversationSummary {
+            summary,
+            message_count: page.messages.len(),
+            older_cursor: page.older_cursor,
+            newer_cursor: page.newer_cursor,
+        })
+    }
+
+    /// Returns the hits together with the mode the search actually ran in.
+    pub fn search_messages(&self, conversation_id: i32, options: &SearchOptions) -> Result<(Vec<SearchHit>, SearchMode)> {
+        self.run_search("m.conversation_id = :scope", conversation_id, options)
+    }
+
+    /// Searches every conversation `user_id` participates in, grouping hits by
+    /// conversation. Conversations are ordered by their best-scoring hit.
+    pub fn search_messages_global(&self, user_id: i32, options: &SearchOptions) -> Result<(Vec<ConversationHits>, SearchMode)> {
+        let (hits, mode) = self.run_search(
+            "m.conversation_id IN (SELECT conversation_id FROM conversation_users WHERE user_id = :scope)",
+            user_id,
+            options,
+        )?;
+
+        let mut conversation_ids: Vec<i32> = hits.iter().map(|h| h.message.message.conversation_id).collect();
+        conversation_ids.sort_unstable();
+        conversation_ids.dedup();
+        let conversations = self.find_conversations_by_ids(&conversation_ids)?;
+
+        let mut groups: Vec<ConversationHits> = Vec::new();
+        for hit in hits {
+            let conversation_id = hit.message.message.conversation_id;
+            match groups.iter_mut().find(|g| g.conversation_id == conversation_id) {
+                Some(group) => group.hits.push(hit),
+                None => {
+                    let conversation = conversations.iter().find(|c| c.id == conversation_id);
+                    groups.push(ConversationHits {
+                        conversation_id,
+                        title: conversation.map(|c| c.title.clone()).unwrap_or_default(),
+                        is_group: conversation.map(|c| c.is_group).unwrap_or(false),
+                        hits: vec![hit],
+                    });
+                }
+            }
+        }
+
+        Ok((groups, mode))
+    }
+
+    /// Boolean queries are sanitized first; one MySQL still rejects as a
+    /// syntax error (1064) is retried in natural language mode. Returns the
+    /// mode that produced the hits.
+    fn run_search(&self, scope: &str, scope_id: i32, options: &SearchOptions) -> Result<(Vec<SearchHit>, SearchMode)> {
+        if options.mode == SearchMode::Natural {
+            let hits = self.run_search_in_mode(scope, scope_id, options, SearchMode::Natural, &options.query)?;
+            return Ok((hits, SearchMode::Natural));
+        }
+        let query = search::sanitize_boolean_query(&options.query);
+        match self.run_search_in_mode(scope, scope_id, options, SearchMode::Boolean, &query) {
+            Ok(hits) => Ok((hits, SearchMode::Boolean)),
+            Err(Error::MySqlError(ref e)) if e.code == 1064 => {
+                let hits = self.run_search_in_mode(scope, scope_id, options, SearchMode::Natural, &options.query)?;
+                Ok((hits, SearchMode::Natural))
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    fn run_search_in_mode(
+        &self,
+        scope: &str,
+        scope_id: i32,
+        options: &SearchOptions,
+        mode: SearchMode,
+        search_query: &str,
+    ) -> Result<Vec<SearchHit>> {
+        let mut conn = self.pool.get_conn()?;
+
+        let query = format!(
+            "SELECT m.id, m.conversation_id, m.user_id, m.content, m.reaction, m.reply_to_id,
+                    m.created_at,
+                    COALESCE(u.username, 'Unknown') as author,
+                    MATCH(m.content) AGAINST (:query {mode}) as score
+             FROM messages m
+             LEFT JOIN user u ON u.id = m.user_id
+             WHERE {scope}
+               AND MATCH(m.content) AGAINST (:query {mode})
+               AND (:author IS NULL OR m.user_id = :author){filter}
+             ORDER BY score DESC, m.created_at DESC
+             LIMIT :limit",
+            mode = mode.as_sql(),
+            scope = scope,
+            filter = MESSAGE_RANGE_FILTER,
+        );
+
+        let terms = search::query_terms(&options.query);
+        let hits = conn.exec_map(
+            query,
+            params! {
+                "scope" => scope_id,
+                "query" => search_query,
+                "author" => options.author_id,
+                "before_id" => options.before_id,
+                "after_id" => options.after_id,
+                "since" => &options.since,
+                "until" => &options.until,
+                "limit" => options.limit,
+            },
+            |row: Row| {
+                let (id, conversation_id, user_id, content, reaction, reply_to_id, created_at, author, score): (i32, i32, i32, String, Option<String>, Option<i32>, Timestamp, String, f64) = from_row(row);
+                let snippet = search::highlight_snippet(&content, &terms, 80);
+                SearchHit {
+                    message: MessageWithAuthor {
+                        message: Message { id, conversation_id, user_id, content, reaction, reply_to_id, created_at },
+                        author,
+                    },
+                    score,
+                    snippet,
+                }
+            },
+        )?;
+
+        Ok(hits)
+    }
+
+    pub fn get_conversation_statistics(&self, conversation_id: i32) -> Result<serde_json::Value> {
+        let mut conn = self.pool.get_conn()?;
+
+        let total_messages: i32 = conn.exec_first(
+            "SELECT COUNT(*) FROM messages WHERE conversation_id = :cid AND deleted_at IS NULL",
+            params! { "cid" => conversation_id },
+        )?.unwrap_or(0);
+
+        let participant_count: i32 = conn.exec_first(
+            "SELECT COUNT(*) FROM conversation_users WHERE conversation_id = :cid",
+            params! { "cid" => conversation_id },
+        )?.unwrap_or(0);
+
+        let conversation = self.find_conversation_by_id(conversation_id)?
+            .ok_or_else(|| not_found("Conversation not found"))?;
+
+        Ok(serde_json::json!({
+            "conversation_id": conversation_id,
+            "title": conversation.title,
+            "is_group": conversation.is_group,
+            "total_messages": total_messages,
+            "participant_count": participant_count,
+            "created_at": conversation.created_at,
+        }))
+    }
+
+    /// Participation, timing, streak and reply-chain analytics for a conversation.
+    pub fn get_conversation_analytics(&self, conversation_id: i32, top_chains: usize) -> Result<ConversationAnalytics> {
+        let mut conn = self.pool.get_conn()?;
+
+        let rows: Vec<ActivityRow> = conn.exec_map(
+            "SELECT id, user_id, reply_to_id, created_at
+             FROM messages
+             WHERE conversation_id = :cid AND deleted_at IS NULL
+             ORDER BY created_at ASC, id ASC",
+            params! { "cid" => conversation_id },
+            |(id, user_id, reply_to_id, created_at)| ActivityRow {
+                id, user_id, reply_to_id, created_at,
+            },
+        )?;
+
+        let mut author_ids: Vec<i32> = rows.iter().map(|r| r.user_id).collect();
+        author_ids.sort_unstable();
+        author_ids.dedup();
+        let usernames = self
+            .find_users_by_ids(&author_ids)?
+            .into_iter()
+            .map(|(id, user)| (id, user.username))
+            .collect();
+
+        Ok(analytics::compute(conversation_id, &rows, &usernames, top_chains))
+    }
+}
+
+fn build_thread_node(message: MessageWithAuthor, pool: &mut Vec<MessageWithAuthor>) -> ThreadNode {
+    let parent_id = message.message.id;
+    let mut children = Vec::new();
+    let mut i = 0;
+    while i < pool.len() {
+        if pool[i].message.reply_to_id == Some(parent_id) {
+            children.push(pool.remove(i));
+        } else {
+            i += 1;
+        }
+    }
+
+    ThreadNode {
+        message,
+        replies: children.into_iter().map(|child| build_thread_node(child, pool)).collect(),
+    }
+}
+
+pub fn split_reactions(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn increment_reaction(counts: &mut Vec<ReactionCount>, reaction: &str) {
+    match counts.iter_mut().find(|c| c.reaction == reaction) {
+        Some(count) => count.count += 1,
+        None => counts.push(ReactionCount {
+            reaction: reaction.to_string(),
+            count: 1,
+        }),
+    }
+}
+
+fn not_found(message: &str) -> Error {
+    Error::from(std::io::Error::new(std::io::ErrorKind::NotFound, message.to_string()))
+}
+
+fn no_insert_id() -> Error {
+    Error::from(std::io::Error::other("insert returned no id"))
+}
+
+// ===== TRANSACTIONAL WRITE STEPS =====
+// Usable on a pooled connection or inside `Database::transaction`.
+
+/// Inserts a conversation and its membership rows. The creator is admin of
+/// group conversations; direct conversations have no admins.
+pub fn create_conversation_in(
+    conn: &mut impl Queryable,
+    title: &str,
+    is_group: bool,
+    creator_id: i32,
+    participant_ids: &[i32],
+) -> Result<i32> {
+    let conversation_id = conn
+        .exec_iter(
+            "INSERT INTO conversations (title, is_group) VALUES (:title, :is_group)",
+            params! { "title" => title, "is_group" => is_group },
+        )?
+        .last_insert_id()
+        .ok_or_else(no_insert_id)? as i32;
+
+    let mut members = vec![creator_id];
+    for id in participant_ids {
+        if !members.contains(id) {
+            members.push(*id);
+        }
+    }
+
+    conn.exec_batch(
+        "INSERT INTO conversation_users (conversation_id, user_id, is_admin)
+         VALUES (:cid, :uid, :is_admin)",
+        members.iter().map(|uid| params! {
+            "cid" => conversation_id,
+            "uid" => uid,
+            "is_admin" => is_group && *uid == creator_id,
+        }),
+    )?;
+
+    Ok(conversation_id)
+}
+
+pub fn insert_message_in(
+    conn: &mut impl Queryable,
+    conversation_id: i32,
+    user_id: i32,
+    content: &str,
+    reply_to_id: Option<i32>,
+) -> Result<i32> {
+    let message_id = conn
+        .exec_iter(
+            "INSERT INTO messages (conversation_id, user_id, content, reply_to_id)
+             VALUES (:cid, :uid, :content, :reply_to_id)",
+            params! {
+                "cid" => conversation_id,
+                "uid" => user_id,
+                "content" => content,
+                "reply_to_id" => reply_to_id,
+            },
+        )?
+        .last_insert_id()
+        .ok_or_else(no_insert_id)?;
+
+    Ok(message_id as i32)
+}
@@ -0,0 +1,122 @@
+use serde::Serialize;
+use serde_json::json;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration};
+use crate::data_base::Database;
+use crate::timestamp::Timestamp;
+use crate::ws_server::WebSocketServer;
+
+/// Optimistic until the first check so tools work while it is in flight.
+static AVAILABLE: AtomicBool = AtomicBool::new(true);
+static LAST_STATUS: OnceLock<Mutex<Option<DbStatus>>> = OnceLock::new();
+
+fn last_status() -> &'static Mutex<Option<DbStatus>> {
+    LAST_STATUS.get_or_init(|| Mutex::new(None))
+}
+
+/// Result of the latest health check, as sent in `db_status` events.
+#[derive(Debug, Clone, Serialize)]
+pub struct DbStatus {
+    pub available: bool,
+    pub checked_at: Timestamp,
+    /// When the current outage started; None while available.
+    pub down_since: Option<Timestamp>,
+    pub error: Option<String>,
+    /// Seconds until the next reconnect attempt; None while available.
+    pub retry_in_secs: Option<u64>,
+}
+
+impl DbStatus {
+    pub fn to_event(&self) -> serde_json::Value {
+        let mut event = json!(self);
+        event["type"] = json!("db_status");
+        event
+    }
+}
+
+/// Whether the last check reached the database.
+pub fn is_available() -> bool {
+    AVAILABLE.load(Ordering::Relaxed)
+}
+
+/// The latest status event, for clients that connect mid-outage.
+pub fn current_event() -> Option<serde_json::Value> {
+    last_status().lock().unwrap().as_ref().map(DbStatus::to_event)
+}
+
+fn env_secs(name: &str, default: u64) -> Duration {
+    let secs = env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+    Duration::from_secs(secs.max(1))
+}
+
+/// Checks the database every `ORBIT_DB_HEALTH_INTERVAL_SECS` (default 15).
+/// After a failure it retries with exponential backoff from 1s up to
+/// `ORBIT_DB_BACKOFF_MAX_SECS` (default 60) until a check succeeds.
+///
+/// Every failed attempt and every recovery is broadcast as `db_status`;
+/// transitions between up and down are also sent on `changes`.
+pub fn spawn(ws_server: WebSocketServer, changes: mpsc::UnboundedSender<bool>) {
+    let interval = env_secs("ORBIT_DB_HEALTH_INTERVAL_SECS", 15);
+    let max_backoff = env_secs("ORBIT_DB_BACKOFF_MAX_SECS", 60);
+
+    tokio::spawn(async move {
+        let mut backoff = Duration::from_secs(1);
+        let mut down_since: Option<Timestamp> = None;
+
+        loop {
+            let checked = tokio::task::spawn_blocking(|| Database::new().and_then(|db| db.ping())).await;
+            let error = match checked {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(e.to_string()),
+                Err(e) => Some(format!("health check task failed: {}", e)),
+            };
+
+            let was_available = AVAILABLE.swap(error.is_none(), Ordering::Relaxed);
+            let delay = match &error {
+                None => {
+                    backoff = Duration::from_secs(1);
+                    interval
+                }
+                Some(_) => {
+                    let delay = backoff;
+                    backoff = (backoff * 2).min(max_backoff);
+                    delay
+                }
+            };
+
+            if error.is_some() && down_since.is_none() {
+                down_since = Some(Timestamp::now());
+            }
+            let changed = was_available != error.is_none() || last_status().lock().unwrap().is_none();
+            if error.is_none() {
+                down_since = None;
+            }
+
+            let status = DbStatus {
+                available: error.is_none(),
+                checked_at: Timestamp::now(),
+                down_since,
+                retry_in_secs: error.as_ref().map(|_| delay.as_secs()),
+                error,
+            };
+            *last_status().lock().unwrap() = Some(status.clone());
+
+            if changed || !status.available {
+                match &status.error {
+                    Some(e) => eprintln!("Database unavailable, retrying in {}s: {}", delay.as_secs(), e),
+                    None if changed => println!("\x1b[1;32m✓ Database available\x1b[0m"),
+                    None => {}
+                }
+                ws_server.broadcast_json(&status.to_event()).await;
+            }
+            if changed {
+                let _ = changes.send(status.available);
+            }
+
+            sleep(delay).await;
+        }
+    });
+}
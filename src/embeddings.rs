@@ -0,0 +1,597 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+use tokio::time::{sleep, Duration};
+use crate::data_base::{Database, Message};
+use crate::timestamp::Timestamp;
+
+/// Messages fetched from the database per sync round.
+const SYNC_FETCH_LIMIT: i32 = 500;
+
+static SERVICE: OnceLock<EmbeddingService> = OnceLock::new();
+
+#[derive(Debug)]
+pub enum EmbeddingError {
+    Http(reqwest::Error),
+    Api(String),
+    Database(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for EmbeddingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbeddingError::Http(e) => write!(f, "Embedding request failed: {}", e),
+            EmbeddingError::Api(e) => write!(f, "Embedding API error: {}", e),
+            EmbeddingError::Database(e) => write!(f, "Database error: {}", e),
+            EmbeddingError::Io(e) => write!(f, "Index file error: {}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for EmbeddingError {
+    fn from(e: reqwest::Error) -> Self {
+        EmbeddingError::Http(e)
+    }
+}
+
+impl From<std::io::Error> for EmbeddingError {
+    fn from(e: std::io::Error) -> Self {
+        EmbeddingError::Io(e)
+    }
+}
+
+// ===== CLIENT =====
+
+/// Client for an OpenAI-compatible `POST {api_base}/embeddings` endpoint.
+#[derive(Clone)]
+pub struct EmbeddingClient {
+    http: reqwest::Client,
+    api_base: String,
+    api_key: String,
+    model: String,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+    index: usize,
+}
+
+impl EmbeddingClient {
+    pub fn new(api_base: &str, api_key: &str, model: &str) -> Self {
+        EmbeddingClient {
+            http: reqwest::Client::new(),
+            api_base: api_base.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+        }
+    }
+
+    /// Embeds `inputs`, returning one vector per input in input order.
+    pub async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let response = self
+            .http
+            .post(format!("{}/embeddings", self.api_base))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .json(&json!({ "model": self.model, "input": inputs }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(EmbeddingError::Api(format!("{}: {}", status, body)));
+        }
+
+        let mut data = response.json::<EmbeddingResponse>().await?.data;
+        if data.len() != inputs.len() {
+            return Err(EmbeddingError::Api(format!(
+                "expected {} embeddings, got {}",
+                inputs.len(),
+                data.len()
+            )));
+        }
+        // Servers may return entries out of order; `index` is authoritative.
+        data.sort_by_key(|d| d.index);
+        Ok(data.into_iter().map(|d| d.embedding).collect())
+    }
+}
+
+// ===== INDEX =====
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    conversation_id: i32,
+    /// Hash of the embedded content, so unchanged messages are not re-embedded.
+    content_hash: u64,
+    vector: Vec<f32>,
+}
+
+/// Message vectors kept in a local JSON file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct EmbeddingIndex {
+    model: String,
+    /// Highest message id seen by the incremental sync.
+    last_message_id: i32,
+    /// When edited messages were last checked.
+    synced_at: Option<Timestamp>,
+    entries: HashMap<i32, IndexEntry>,
+}
+
+impl EmbeddingIndex {
+    pub fn new(model: &str) -> Self {
+        EmbeddingIndex {
+            model: model.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Loads the index at `path`. A missing file, or one built with a
+    /// different model, starts a fresh index.
+    pub fn load(path: &Path, model: &str) -> Self {
+        let loaded = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<EmbeddingIndex>(&text).ok());
+
+        match loaded {
+            Some(index) if index.model == model => index,
+            _ => EmbeddingIndex::new(model),
+        }
+    }
+
+    /// Writes to a temporary file first so a crash never leaves a torn index.
+    pub fn save(&self, path: &Path) -> Result<(), EmbeddingError> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(self).map_err(std::io::Error::from)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn needs_embedding(&self, message: &Message) -> bool {
+        self.entries
+            .get(&message.id)
+            .is_none_or(|entry| entry.content_hash != content_hash(&message.content))
+    }
+
+    fn ids(&self) -> Vec<i32> {
+        self.entries.keys().copied().collect()
+    }
+
+    /// Drops entries whose message is not in `live` (deleted or purged).
+    /// Returns how many were dropped.
+    fn retain_live(&mut self, checked: &[i32], live: &HashSet<i32>) -> usize {
+        let before = self.entries.len();
+        for id in checked.iter().filter(|id| !live.contains(id)) {
+            self.entries.remove(id);
+        }
+        before - self.entries.len()
+    }
+
+    fn upsert(&mut self, message: &Message, vector: Vec<f32>) {
+        self.entries.insert(message.id, IndexEntry {
+            conversation_id: message.conversation_id,
+            content_hash: content_hash(&message.content),
+            vector,
+        });
+    }
+
+    /// Top `k` message ids by cosine similarity, restricted to
+    /// `conversations` when given.
+    pub fn search(&self, query: &[f32], conversations: Option<&HashSet<i32>>, k: usize) -> Vec<(i32, f32)> {
+        let mut scored: Vec<(i32, f32)> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| conversations.is_none_or(|allowed| allowed.contains(&entry.conversation_id)))
+            .map(|(id, entry)| (*id, cosine_similarity(query, &entry.vector)))
+            .collect();
+
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        scored.truncate(k);
+        scored
+    }
+}
+
+/// 64-bit FNV-1a. The value is stored in the index file, so it must not
+/// change between builds the way `DefaultHasher` may.
+fn content_hash(content: &str) -> u64 {
+    content.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+// ===== SERVICE =====
+
+/// The embedding client and index shared by the sync task and the
+/// semantic_search tool.
+pub struct EmbeddingService {
+    client: EmbeddingClient,
+    index: RwLock<EmbeddingIndex>,
+    path: Option<PathBuf>,
+    batch_size: usize,
+}
+
+impl EmbeddingService {
+    pub fn new(client: EmbeddingClient, index: EmbeddingIndex, path: Option<PathBuf>, batch_size: usize) -> Self {
+        EmbeddingService {
+            client,
+            index: RwLock::new(index),
+            path,
+            batch_size: batch_size.max(1),
+        }
+    }
+
+    pub fn indexed_messages(&self) -> usize {
+        self.index.read().unwrap().len()
+    }
+
+    /// Embeds messages that are new or whose content changed. Returns how
+    /// many were embedded.
+    pub async fn index_messages(&self, messages: &[Message]) -> Result<usize, EmbeddingError> {
+        let pending: Vec<&Message> = {
+            let index = self.index.read().unwrap();
+            messages
+                .iter()
+                .filter(|m| !m.content.trim().is_empty() && index.needs_embedding(m))
+                .collect()
+        };
+
+        for batch in pending.chunks(self.batch_size) {
+            let inputs: Vec<String> = batch.iter().map(|m| m.content.clone()).collect();
+            let vectors = self.client.embed(&inputs).await?;
+
+            let mut index = self.index.write().unwrap();
+            for (message, vector) in batch.iter().zip(vectors) {
+                index.upsert(message, vector);
+            }
+        }
+
+        Ok(pending.len())
+    }
+
+    /// Pulls new and recently edited messages from the database into the
+    /// index and persists it. Returns how many messages were embedded.
+    pub async fn sync_once(&self) -> Result<usize, EmbeddingError> {
+        let (after_id, edited_since) = {
+            let index = self.index.read().unwrap();
+            (index.last_message_id, index.synced_at)
+        };
+        // Overlap a little with the previous check; unchanged content is skipped anyway.
+        let started_at = Timestamp::from_unix(Timestamp::now().unix_seconds() - 5).unwrap_or_else(Timestamp::now);
+
+        let mut embedded = 0;
+        let mut after_id = after_id;
+        loop {
+            let batch = tokio::task::spawn_blocking(move || {
+                let db = Database::new()?;
+                db.find_messages_after_id_global(after_id, SYNC_FETCH_LIMIT)
+            })
+            .await
+            .map_err(|e| EmbeddingError::Database(e.to_string()))?
+            .map_err(|e| EmbeddingError::Database(e.to_string()))?;
+
+            let Some(last) = batch.last() else { break };
+            after_id = last.id;
+            embedded += self.index_messages(&batch).await?;
+            self.index.write().unwrap().last_message_id = after_id;
+
+            if (batch.len() as i32) < SYNC_FETCH_LIMIT {
+                break;
+            }
+        }
+
+        if let Some(since) = edited_since {
+            let mut after_id = 0;
+            loop {
+                let edited = tokio::task::spawn_blocking(move || {
+                    let db = Database::new()?;
+                    db.find_messages_edited_since(since, after_id, SYNC_FETCH_LIMIT)
+                })
+                .await
+                .map_err(|e| EmbeddingError::Database(e.to_string()))?
+                .map_err(|e| EmbeddingError::Database(e.to_string()))?;
+
+                let Some(last) = edited.last() else { break };
+                after_id = last.id;
+                embedded += self.index_messages(&edited).await?;
+
+                if (edited.len() as i32) < SYNC_FETCH_LIMIT {
+                    break;
+                }
+            }
+        }
+        // Only once every edit page is in, so a failure retries from `since`.
+        self.index.write().unwrap().synced_at = Some(started_at);
+
+        // Deleted and purged messages leave no trace to sync from, so the
+        // indexed ids are re-checked instead.
+        let indexed = self.index.read().unwrap().ids();
+        let (indexed, live) = tokio::task::spawn_blocking(move || {
+            let live = Database::new()?.find_live_message_ids(&indexed)?;
+            Ok::<_, mysql::Error>((indexed, live))
+        })
+        .await
+        .map_err(|e| EmbeddingError::Database(e.to_string()))?
+        .map_err(|e| EmbeddingError::Database(e.to_string()))?;
+        self.index.write().unwrap().retain_live(&indexed, &live);
+
+        if let Some(path) = &self.path {
+            self.index.read().unwrap().save(path)?;
+        }
+        Ok(embedded)
+    }
+
+    /// Embeds `query` and returns the `k` most similar indexed message ids.
+    pub async fn search(
+        &self,
+        query: &str,
+        conversations: Option<&HashSet<i32>>,
+        k: usize,
+    ) -> Result<Vec<(i32, f32)>, EmbeddingError> {
+        let vector = self
+            .client
+            .embed(&[query.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| EmbeddingError::Api("empty embedding response".to_string()))?;
+
+        Ok(self.index.read().unwrap().search(&vector, conversations, k))
+    }
+
+    /// Keeps the index up to date in the background
+    /// (every `ORBIT_EMBEDDINGS_INTERVAL_SECS`, default 30).
+    pub fn spawn_sync(&'static self) {
+        let interval = env::var("ORBIT_EMBEDDINGS_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(30u64)
+            .max(1);
+
+        tokio::spawn(async move {
+            loop {
+                match self.sync_once().await {
+                    Ok(0) => {}
+                    Ok(count) => println!("Embedding index: embedded {} messages ({} indexed)", count, self.indexed_messages()),
+                    Err(e) => eprintln!("Embedding index sync failed: {}", e),
+                }
+                sleep(Duration::from_secs(interval)).await;
+            }
+        });
+    }
+}
+
+/// Sets up semantic search when `ORBIT_EMBEDDINGS_MODEL` is set. The
+/// endpoint defaults to the chat `api_base` (`ORBIT_EMBEDDINGS_API_BASE`
+/// overrides it) and the index lives at `ORBIT_EMBEDDINGS_INDEX`
+/// (default `orbit_embeddings.json`).
+pub fn init_from_env(api_base: &str, api_key: &str) -> Option<&'static EmbeddingService> {
+    let model = env::var("ORBIT_EMBEDDINGS_MODEL").ok().filter(|m| !m.trim().is_empty())?;
+    let api_base = env::var("ORBIT_EMBEDDINGS_API_BASE").unwrap_or_else(|_| api_base.to_string());
+    let path = PathBuf::from(env::var("ORBIT_EMBEDDINGS_INDEX").unwrap_or_else(|_| "orbit_embeddings.json".to_string()));
+    let batch_size = env::var("ORBIT_EMBEDDINGS_BATCH")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(64);
+
+    let index = EmbeddingIndex::load(&path, &model);
+    let client = EmbeddingClient::new(&api_base, api_key, &model);
+    Some(SERVICE.get_or_init(|| EmbeddingService::new(client, index, Some(path), batch_size)))
+}
+
+/// The configured service, if semantic search is enabled.
+pub fn service() -> Option<&'static EmbeddingService> {
+    SERVICE.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, routing::post, Json, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const DIMENSIONS: usize = 64;
+
+    /// Bag-of-words vector: texts sharing words get similar embeddings.
+    fn stub_embedding(text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; DIMENSIONS];
+        for word in text.to_lowercase().split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            vector[(content_hash(word) % DIMENSIONS as u64) as usize] += 1.0;
+        }
+        vector
+    }
+
+    #[derive(Clone, Default)]
+    struct StubState {
+        inputs_seen: Arc<AtomicUsize>,
+        fail: bool,
+    }
+
+    async fn stub_embeddings(
+        State(state): State<StubState>,
+        Json(body): Json<serde_json::Value>,
+    ) -> Result<Json<serde_json::Value>, axum::http::StatusCode> {
+        if state.fail {
+            return Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        let inputs: Vec<String> = serde_json::from_value(body["input"].clone()).unwrap();
+        state.inputs_seen.fetch_add(inputs.len(), Ordering::SeqCst);
+
+        // Reversed on purpose: clients must order by `index`.
+        let data: Vec<serde_json::Value> = inputs
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(index, input)| json!({ "object": "embedding", "index": index, "embedding": stub_embedding(input) }))
+            .collect();
+        Ok(Json(json!({ "object": "list", "model": body["model"], "data": data })))
+    }
+
+    async fn start_stub(state: StubState) -> String {
+        let app = Router::new().route("/v1/embeddings", post(stub_embeddings)).with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}/v1", addr)
+    }
+
+    fn message(id: i32, conversation_id: i32, content: &str) -> Message {
+        Message {
+            id,
+            conversation_id,
+            user_id: 1,
+            content: content.to_string(),
+            reaction: None,
+            reply_to_id: None,
+            created_at: Timestamp::from_unix(1_700_000_000).unwrap(),
+        }
+    }
+
+    fn service(api_base: &str) -> EmbeddingService {
+        EmbeddingService::new(EmbeddingClient::new(api_base, "test-key", "stub"), EmbeddingIndex::new("stub"), None, 2)
+    }
+
+    #[tokio::test]
+    async fn embed_returns_vectors_in_input_order() {
+        let api_base = start_stub(StubState::default()).await;
+        let client = EmbeddingClient::new(&api_base, "test-key", "stub");
+        let inputs = vec!["red apple".to_string(), "blue sky".to_string(), "green tea".to_string()];
+
+        let vectors = client.embed(&inputs).await.unwrap();
+
+        assert_eq!(vectors.len(), 3);
+        for (input, vector) in inputs.iter().zip(&vectors) {
+            assert_eq!(vector, &stub_embedding(input));
+        }
+    }
+
+    #[tokio::test]
+    async fn search_ranks_similar_messages_first_and_respects_conversations() {
+        let api_base = start_stub(StubState::default()).await;
+        let service = service(&api_base);
+        service
+            .index_messages(&[
+                message(1, 10, "the deploy failed on the staging server"),
+                message(2, 10, "lunch at noon?"),
+                message(3, 20, "staging server deploy is broken again"),
+            ])
+            .await
+            .unwrap();
+
+        let results = service.search("deploy staging server", None, 2).await.unwrap();
+        let ids: Vec<i32> = results.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&1) && ids.contains(&3));
+
+        let only_ten = HashSet::from([10]);
+        let results = service.search("deploy staging server", Some(&only_ten), 5).await.unwrap();
+        assert_eq!(results[0].0, 1);
+        assert!(results.iter().all(|(id, _)| *id != 3));
+    }
+
+    #[tokio::test]
+    async fn index_messages_only_embeds_new_or_changed_content() {
+        let state = StubState::default();
+        let api_base = start_stub(state.clone()).await;
+        let service = service(&api_base);
+
+        let embedded = service
+            .index_messages(&[message(1, 10, "first"), message(2, 10, "second"), message(3, 10, "third")])
+            .await
+            .unwrap();
+        assert_eq!(embedded, 3);
+        assert_eq!(state.inputs_seen.load(Ordering::SeqCst), 3);
+
+        // One unchanged, one edited, one new.
+        let embedded = service
+            .index_messages(&[message(1, 10, "first"), message(2, 10, "second, edited"), message(4, 10, "fourth")])
+            .await
+            .unwrap();
+        assert_eq!(embedded, 2);
+        assert_eq!(state.inputs_seen.load(Ordering::SeqCst), 5);
+        assert_eq!(service.indexed_messages(), 4);
+    }
+
+    #[tokio::test]
+    async fn api_errors_are_reported() {
+        let api_base = start_stub(StubState { fail: true, ..Default::default() }).await;
+        let service = service(&api_base);
+
+        let error = service.index_messages(&[message(1, 10, "hello")]).await.unwrap_err();
+        assert!(matches!(error, EmbeddingError::Api(_)));
+        assert_eq!(service.indexed_messages(), 0);
+    }
+
+    #[test]
+    fn content_hash_is_fixed_fnv1a() {
+        assert_eq!(content_hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(content_hash("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(content_hash("foobar"), 0x85944171f73967e8);
+    }
+
+    #[tokio::test]
+    async fn retain_live_drops_deleted_messages_only() {
+        let api_base = start_stub(StubState::default()).await;
+        let service = service(&api_base);
+        service
+            .index_messages(&[message(1, 10, "one"), message(2, 10, "two"), message(3, 10, "three")])
+            .await
+            .unwrap();
+
+        // Message 3 was indexed after the check started, so it was not checked.
+        let removed = service.index.write().unwrap().retain_live(&[1, 2], &HashSet::from([1]));
+        assert_eq!(removed, 1);
+        let mut ids = service.index.read().unwrap().ids();
+        ids.sort_unstable();
+        assert_eq!(ids, [1, 3]);
+    }
+
+    #[tokio::test]
+    async fn index_round_trips_through_disk_and_resets_on_model_change() {
+        let api_base = start_stub(StubState::default()).await;
+        let service = service(&api_base);
+        service.index_messages(&[message(1, 10, "persist me")]).await.unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.json");
+        service.index.read().unwrap().save(&path).unwrap();
+
+        let loaded = EmbeddingIndex::load(&path, "stub");
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded.search(&stub_embedding("persist me"), None, 1)[0].0, 1);
+
+        assert_eq!(EmbeddingIndex::load(&path, "other-model").len(), 0);
+    }
+}
@@ -0,0 +1,522 @@
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use crate::data_base::{split_reactions, Conversation, Database, MessageWithAuthor};
+use crate::timestamp::Timestamp;
+
+/// Messages fetched per round trip while exporting.
+const EXPORT_BATCH_SIZE: i32 = 500;
+
+static EXPORT_LINKS: OnceLock<Mutex<HashMap<String, ExportLink>>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+    Csv,
+    Html,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "md" | "markdown" => Some(ExportFormat::Markdown),
+            "json" => Some(ExportFormat::Json),
+            "csv" => Some(ExportFormat::Csv),
+            "html" => Some(ExportFormat::Html),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Html => "html",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "text/markdown; charset=utf-8",
+            ExportFormat::Json => "application/json",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Html => "text/html; charset=utf-8",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    NotFound(i32),
+    Database(mysql::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::NotFound(id) => write!(f, "Conversation {} not found", id),
+            ExportError::Database(e) => write!(f, "Database error: {}", e),
+            ExportError::Io(e) => write!(f, "Write error: {}", e),
+        }
+    }
+}
+
+impl From<mysql::Error> for ExportError {
+    fn from(e: mysql::Error) -> Self {
+        ExportError::Database(e)
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+impl From<csv::Error> for ExportError {
+    fn from(e: csv::Error) -> Self {
+        ExportError::Io(e.into())
+    }
+}
+
+/// One exported message; the same shape is used by every format.
+#[derive(Debug, Serialize)]
+struct ExportedMessage {
+    id: i32,
+    created_at: Timestamp,
+    author_id: i32,
+    author: String,
+    reply_to_id: Option<i32>,
+    reactions: Vec<String>,
+    content: String,
+}
+
+impl From<MessageWithAuthor> for ExportedMessage {
+    fn from(entry: MessageWithAuthor) -> Self {
+        let message = entry.message;
+        ExportedMessage {
+            id: message.id,
+            created_at: message.created_at,
+            author_id: message.user_id,
+            author: entry.author,
+            reply_to_id: message.reply_to_id,
+            reactions: message.reaction.as_deref().map(split_reactions).unwrap_or_default(),
+            content: message.content,
+        }
+    }
+}
+
+/// Writes a whole conversation to `out`, fetching messages in batches so
+/// large conversations are never held in memory at once. Returns the number
+/// of messages written.
+pub fn write_conversation<W: Write>(
+    db: &Database,
+    conversation_id: i32,
+    format: ExportFormat,
+    out: W,
+) -> Result<usize, ExportError> {
+    let conversation = db
+        .find_conversation_by_id(conversation_id)?
+        .ok_or(ExportError::NotFound(conversation_id))?;
+    let participants: Vec<String> = db
+        .get_conversation_participants(conversation_id)?
+        .into_iter()
+        .map(|user| user.username)
+        .collect();
+
+    let mut writer = new_writer(format, out);
+    writer.header(&conversation, &participants)?;
+
+    let mut written = 0;
+    let mut after_id = None;
+    loop {
+        let batch = db.find_messages_ascending(conversation_id, after_id, EXPORT_BATCH_SIZE)?;
+        let Some(last) = batch.last() else { break };
+        after_id = Some(last.id);
+        let full = batch.len() as i32 == EXPORT_BATCH_SIZE;
+
+        for entry in db.with_authors(batch)? {
+            writer.message(&ExportedMessage::from(entry))?;
+            written += 1;
+        }
+
+        if !full {
+            break;
+        }
+    }
+
+    writer.footer(written)?;
+    Ok(written)
+}
+
+fn new_writer<'a, W: Write + 'a>(format: ExportFormat, out: W) -> Box<dyn ExportWriter<W> + 'a> {
+    match format {
+        ExportFormat::Markdown => Box::new(MarkdownWriter(out)),
+        ExportFormat::Json => Box::new(JsonWriter { out, first: true }),
+        ExportFormat::Csv => Box::new(CsvWriter(csv::Writer::from_writer(out))),
+        ExportFormat::Html => Box::new(HtmlWriter(out)),
+    }
+}
+
+trait ExportWriter<W: Write> {
+    fn header(&mut self, conversation: &Conversation, participants: &[String]) -> Result<(), ExportError>;
+    fn message(&mut self, message: &ExportedMessage) -> Result<(), ExportError>;
+    fn footer(&mut self, count: usize) -> Result<(), ExportError>;
+}
+
+fn kind(conversation: &Conversation) -> &'static str {
+    if conversation.is_group { "group" } else { "direct" }
+}
+
+struct MarkdownWriter<W>(W);
+
+impl<W: Write> ExportWriter<W> for MarkdownWriter<W> {
+    fn header(&mut self, conversation: &Conversation, participants: &[String]) -> Result<(), ExportError> {
+        writeln!(self.0, "# {}\n", conversation.title)?;
+        writeln!(
+            self.0,
+            "Conversation {} · {} · created {}\n",
+            conversation.id, kind(conversation), conversation.created_at
+        )?;
+        writeln!(self.0, "Participants: {}\n\n---\n", participants.join(", "))?;
+        Ok(())
+    }
+
+    fn message(&mut self, message: &ExportedMessage) -> Result<(), ExportError> {
+        write!(self.0, "**{}** · {} · #{}", message.author, message.created_at, message.id)?;
+        if let Some(reply_to) = message.reply_to_id {
+            write!(self.0, " · reply to #{}", reply_to)?;
+        }
+        writeln!(self.0, "\n")?;
+        for line in message.content.lines() {
+            writeln!(self.0, "> {}", line)?;
+        }
+        if !message.reactions.is_empty() {
+            writeln!(self.0, "\nReactions: {}", message.reactions.join(" "))?;
+        }
+        writeln!(self.0)?;
+        Ok(())
+    }
+
+    fn footer(&mut self, count: usize) -> Result<(), ExportError> {
+        writeln!(self.0, "---\n\n{} messages", count)?;
+        self.0.flush()?;
+        Ok(())
+    }
+}
+
+struct JsonWriter<W> {
+    out: W,
+    first: bool,
+}
+
+impl<W: Write> ExportWriter<W> for JsonWriter<W> {
+    fn header(&mut self, conversation: &Conversation, participants: &[String]) -> Result<(), ExportError> {
+        let conversation = serde_json::to_string(conversation).map_err(io::Error::from)?;
+        let participants = serde_json::to_string(participants).map_err(io::Error::from)?;
+        write!(
+            self.out,
+            "{{\"conversation\":{},\"participants\":{},\"messages\":[",
+            conversation, participants
+        )?;
+        Ok(())
+    }
+
+    fn message(&mut self, message: &ExportedMessage) -> Result<(), ExportError> {
+        if !self.first {
+            write!(self.out, ",")?;
+        }
+        self.first = false;
+        serde_json::to_writer(&mut self.out, message).map_err(io::Error::from)?;
+        Ok(())
+    }
+
+    fn footer(&mut self, count: usize) -> Result<(), ExportError> {
+        write!(self.out, "],\"message_count\":{}}}", count)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+struct CsvWriter<W: Write>(csv::Writer<W>);
+
+impl<W: Write> ExportWriter<W> for CsvWriter<W> {
+    fn header(&mut self, _conversation: &Conversation, _participants: &[String]) -> Result<(), ExportError> {
+        self.0.write_record(["id", "created_at", "author_id", "author", "reply_to_id", "reactions", "content"])?;
+        Ok(())
+    }
+
+    fn message(&mut self, message: &ExportedMessage) -> Result<(), ExportError> {
+        self.0.write_record([
+            message.id.to_string(),
+            message.created_at.to_rfc3339(),
+            message.author_id.to_string(),
+            message.author.clone(),
+            message.reply_to_id.map(|id| id.to_string()).unwrap_or_default(),
+            message.reactions.join(" "),
+            message.content.clone(),
+        ])?;
+        Ok(())
+    }
+
+    fn footer(&mut self, _count: usize) -> Result<(), ExportError> {
+        self.0.flush()?;
+        Ok(())
+    }
+}
+
+struct HtmlWriter<W>(W);
+
+const HTML_STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:760px;margin:2rem auto;padding:0 1rem;color:#1f2937}\
+header{border-bottom:1px solid #e5e7eb;margin-bottom:1.5rem}\
+.meta{color:#6b7280;font-size:.875rem}\
+.message{padding:.75rem 0;border-bottom:1px solid #f3f4f6}\
+.author{font-weight:600}\
+.content{white-space:pre-wrap;margin-top:.25rem}\
+.reply{color:#6b7280;font-size:.75rem}\
+.reactions{margin-top:.25rem}\
+a{color:inherit}";
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl<W: Write> ExportWriter<W> for HtmlWriter<W> {
+    fn header(&mut self, conversation: &Conversation, participants: &[String]) -> Result<(), ExportError> {
+        let title = escape_html(&conversation.title);
+        write!(
+            self.0,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n\
+             <header><h1>{}</h1><p class=\"meta\">Conversation {} · {} · created {}<br>Participants: {}</p></header>\n<main>\n",
+            title,
+            HTML_STYLE,
+            title,
+            conversation.id,
+            kind(conversation),
+            conversation.created_at,
+            escape_html(&participants.join(", "))
+        )?;
+        Ok(())
+    }
+
+    fn message(&mut self, message: &ExportedMessage) -> Result<(), ExportError> {
+        write!(
+            self.0,
+            "<div class=\"message\" id=\"m{}\"><span class=\"author\">{}</span> <span class=\"meta\">{} · #{}</span>",
+            message.id,
+            escape_html(&message.author),
+            message.created_at,
+            message.id
+        )?;
+        if let Some(reply_to) = message.reply_to_id {
+            write!(self.0, " <a class=\"reply\" href=\"#m{}\">reply to #{}</a>", reply_to, reply_to)?;
+        }
+        write!(self.0, "<div class=\"content\">{}</div>", escape_html(&message.content))?;
+        if !message.reactions.is_empty() {
+            write!(self.0, "<div class=\"reactions\">{}</div>", escape_html(&message.reactions.join(" ")))?;
+        }
+        writeln!(self.0, "</div>")?;
+        Ok(())
+    }
+
+    fn footer(&mut self, count: usize) -> Result<(), ExportError> {
+        write!(self.0, "</main>\n<footer class=\"meta\"><p>{} messages</p></footer>\n</body>\n</html>\n", count)?;
+        self.0.flush()?;
+        Ok(())
+    }
+}
+
+/// Forwards written bytes to an async response body. Used from a blocking
+/// task; fails with BrokenPipe once the client goes away.
+pub struct ChannelWriter(pub mpsc::Sender<io::Result<Vec<u8>>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(buf.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "export download closed"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// ===== DOWNLOAD LINKS =====
+
+struct ExportLink {
+    conversation_id: i32,
+    expires_at: Instant,
+}
+
+fn export_links() -> &'static Mutex<HashMap<String, ExportLink>> {
+    EXPORT_LINKS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// How long a download link stays valid (ORBIT_EXPORT_LINK_TTL_SECS, default 900).
+pub fn link_ttl() -> Duration {
+    let secs = env::var("ORBIT_EXPORT_LINK_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(900);
+    Duration::from_secs(secs)
+}
+
+/// Base URL put in front of download links (ORBIT_PUBLIC_URL, default
+/// http://localhost:WS_PORT).
+fn public_base_url() -> String {
+    match env::var("ORBIT_PUBLIC_URL") {
+        Ok(url) if !url.trim().is_empty() => url.trim().trim_end_matches('/').to_string(),
+        _ => format!("http://localhost:{}", env::var("WS_PORT").unwrap_or_else(|_| "8080".to_string())),
+    }
+}
+
+/// Issues a download URL for a conversation the caller was authorized to read.
+pub fn create_download_link(conversation_id: i32, format: ExportFormat) -> String {
+    let token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+
+    let mut links = export_links().lock().unwrap();
+    links.retain(|_, link| link.expires_at > Instant::now());
+    links.insert(token.clone(), ExportLink {
+        conversation_id,
+        expires_at: Instant::now() + link_ttl(),
+    });
+
+    format!(
+        "{}/export/{}?format={}&token={}",
+        public_base_url(),
+        conversation_id,
+        format.extension(),
+        token
+    )
+}
+
+/// True if `token` is an unexpired link for `conversation_id`.
+pub fn link_is_valid(token: &str, conversation_id: i32) -> bool {
+    let links = export_links().lock().unwrap();
+    links
+        .get(token)
+        .is_some_and(|link| link.conversation_id == conversation_id && link.expires_at > Instant::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> Timestamp {
+        Timestamp::parse_bound(value, false).unwrap()
+    }
+
+    fn conversation() -> Conversation {
+        Conversation { id: 7, title: "Q&A <launch>".to_string(), is_group: true, created_at: at("2024-03-01T09:00:00+00:00") }
+    }
+
+    fn messages() -> Vec<ExportedMessage> {
+        vec![
+            ExportedMessage {
+                id: 1,
+                created_at: at("2024-03-01T10:00:00+00:00"),
+                author_id: 1,
+                author: "alice".to_string(),
+                reply_to_id: None,
+                reactions: Vec::new(),
+                content: "Ship it?\nTomorrow, \"maybe\"".to_string(),
+            },
+            ExportedMessage {
+                id: 2,
+                created_at: at("2024-03-01T10:05:00+00:00"),
+                author_id: 2,
+                author: "bob".to_string(),
+                reply_to_id: Some(1),
+                reactions: vec![":+1:".to_string(), ":tada:".to_string()],
+                content: "<b>yes</b> & go".to_string(),
+            },
+        ]
+    }
+
+    fn export(format: ExportFormat) -> String {
+        let mut out = Vec::new();
+        {
+            let mut writer = new_writer(format, &mut out);
+            writer.header(&conversation(), &["alice".to_string(), "bob".to_string()]).unwrap();
+            for message in messages() {
+                writer.message(&message).unwrap();
+            }
+            writer.footer(2).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn json_export_is_one_valid_document() {
+        let document: serde_json::Value = serde_json::from_str(&export(ExportFormat::Json)).unwrap();
+        assert_eq!(document["conversation"]["title"], "Q&A <launch>");
+        assert_eq!(document["participants"], serde_json::json!(["alice", "bob"]));
+        assert_eq!(document["message_count"], 2);
+        assert_eq!(document["messages"][1]["reply_to_id"], 1);
+        assert_eq!(document["messages"][1]["reactions"], serde_json::json!([":+1:", ":tada:"]));
+        assert_eq!(document["messages"][0]["created_at"], "2024-03-01T10:00:00Z");
+    }
+
+    #[test]
+    fn csv_export_quotes_multiline_content() {
+        let text = export(ExportFormat::Csv);
+        let mut reader = csv::Reader::from_reader(text.as_bytes());
+        assert_eq!(
+            reader.headers().unwrap().iter().collect::<Vec<_>>(),
+            ["id", "created_at", "author_id", "author", "reply_to_id", "reactions", "content"]
+        );
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(&rows[0][6], "Ship it?\nTomorrow, \"maybe\"");
+        assert_eq!((&rows[1][4], &rows[1][5]), ("1", ":+1: :tada:"));
+    }
+
+    #[test]
+    fn markdown_export_quotes_every_content_line() {
+        let text = export(ExportFormat::Markdown);
+        assert!(text.starts_with("# Q&A <launch>\n"));
+        assert!(text.contains("Conversation 7 · group · created 2024-03-01 09:00:00"));
+        assert!(text.contains("> Ship it?\n> Tomorrow, \"maybe\"\n"));
+        assert!(text.contains("**bob** · 2024-03-01 10:05:00 · #2 · reply to #1"));
+        assert!(text.contains("Reactions: :+1: :tada:"));
+        assert!(text.ends_with("---\n\n2 messages\n"));
+    }
+
+    #[test]
+    fn html_export_escapes_user_content() {
+        let text = export(ExportFormat::Html);
+        assert!(text.contains("<title>Q&amp;A &lt;launch&gt;</title>"));
+        assert!(text.contains("&lt;b&gt;yes&lt;/b&gt; &amp; go"));
+        assert!(text.contains("&quot;maybe&quot;"));
+        assert!(text.contains("<a class=\"reply\" href=\"#m1\">reply to #1</a>"));
+        assert!(!text.contains("<b>yes</b>"));
+        assert!(text.trim_end().ends_with("</html>"));
+    }
+}
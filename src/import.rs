@@ -0,0 +1,817 @@
+use mysql::prelude::*;
+use mysql::{params, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::data_base::Database;
+use crate::timestamp::Timestamp;
+
+/// Warnings kept in the report; the rest are only counted.
+const MAX_REPORTED_WARNINGS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// Orbit's own JSON export (see export_conversation).
+    Json,
+    /// An unzipped Slack workspace export directory.
+    Slack,
+    /// CSV with conversation, author, content and created_at columns.
+    Csv,
+}
+
+impl ImportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "json" => Some(ImportFormat::Json),
+            "slack" => Some(ImportFormat::Slack),
+            "csv" => Some(ImportFormat::Csv),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format from the path: directories are Slack exports.
+    fn detect(path: &Path) -> Option<Self> {
+        if path.is_dir() {
+            return Some(ImportFormat::Slack);
+        }
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(ImportFormat::parse)
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    Parse(String),
+    Database(mysql::Error),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "I/O error: {}", e),
+            ImportError::Parse(e) => write!(f, "Parse error: {}", e),
+            ImportError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<std::io::Error> for ImportError {
+    fn from(e: std::io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+impl From<mysql::Error> for ImportError {
+    fn from(e: mysql::Error) -> Self {
+        ImportError::Database(e)
+    }
+}
+
+fn parse_error(path: &Path, e: impl fmt::Display) -> ImportError {
+    ImportError::Parse(format!("{}: {}", path.display(), e))
+}
+
+// ===== NORMALIZED IMPORT MODEL =====
+
+#[derive(Debug, Clone)]
+pub struct ImportUser {
+    /// Stable id in the source system; the username when it has none.
+    pub external_id: String,
+    pub username: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportMessage {
+    pub external_id: String,
+    pub author: ImportUser,
+    pub content: String,
+    pub created_at: Timestamp,
+    /// External id of the parent message in the same conversation.
+    pub reply_to: Option<String>,
+    pub reactions: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportConversation {
+    pub external_id: String,
+    pub title: String,
+    /// Defaults to "more than two participants".
+    pub is_group: Option<bool>,
+    pub created_at: Option<Timestamp>,
+    pub participants: Vec<ImportUser>,
+    pub messages: Vec<ImportMessage>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub source: String,
+    pub dry_run: bool,
+    pub users_created: usize,
+    pub users_matched: usize,
+    pub conversations_created: usize,
+    pub conversations_existing: usize,
+    pub participants_added: usize,
+    pub messages_imported: usize,
+    pub messages_already_imported: usize,
+    pub replies_linked: usize,
+    pub replies_unresolved: usize,
+    pub warnings: Vec<String>,
+    pub warnings_omitted: usize,
+}
+
+impl ImportReport {
+    fn warn(&mut self, warning: String) {
+        if self.warnings.len() < MAX_REPORTED_WARNINGS {
+            self.warnings.push(warning);
+        } else {
+            self.warnings_omitted += 1;
+        }
+    }
+}
+
+// ===== LOADERS =====
+
+pub fn load(path: &Path, format: ImportFormat) -> Result<Vec<ImportConversation>, ImportError> {
+    match format {
+        ImportFormat::Json => load_orbit_json(path),
+        ImportFormat::Slack => load_slack(path),
+        ImportFormat::Csv => load_csv(path),
+    }
+}
+
+#[derive(Deserialize)]
+struct OrbitExport {
+    conversation: OrbitExportConversation,
+    #[serde(default)]
+    participants: Vec<String>,
+    messages: Vec<OrbitExportMessage>,
+}
+
+#[derive(Deserialize)]
+struct OrbitExportConversation {
+    id: i64,
+    title: String,
+    is_group: bool,
+    created_at: Option<Timestamp>,
+}
+
+#[derive(Deserialize)]
+struct OrbitExportMessage {
+    id: i64,
+    created_at: Timestamp,
+    author: String,
+    reply_to_id: Option<i64>,
+    #[serde(default)]
+    reactions: Vec<String>,
+    content: String,
+}
+
+fn user_by_name(username: &str) -> ImportUser {
+    ImportUser {
+        external_id: username.to_string(),
+        username: username.to_string(),
+    }
+}
+
+/// A single export document or an array of them.
+fn load_orbit_json(path: &Path) -> Result<Vec<ImportConversation>, ImportError> {
+    let text = fs::read_to_string(path)?;
+    let exports: Vec<OrbitExport> = match serde_json::from_str::<serde_json::Value>(&text) {
+        Ok(serde_json::Value::Array(items)) => items
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<Result<_, _>>()
+            .map_err(|e| parse_error(path, e))?,
+        Ok(value) => vec![serde_json::from_value(value).map_err(|e| parse_error(path, e))?],
+        Err(e) => return Err(parse_error(path, e)),
+    };
+
+    Ok(exports
+        .into_iter()
+        .map(|export| {
+            let mut participants: Vec<ImportUser> = export.participants.iter().map(|u| user_by_name(u)).collect();
+            for message in &export.messages {
+                if !participants.iter().any(|p| p.username == message.author) {
+                    participants.push(user_by_name(&message.author));
+                }
+            }
+
+            ImportConversation {
+                external_id: export.conversation.id.to_string(),
+                title: export.conversation.title,
+                is_group: Some(export.conversation.is_group),
+                created_at: export.conversation.created_at,
+                participants,
+                messages: export
+                    .messages
+                    .into_iter()
+                    .map(|message| ImportMessage {
+                        external_id: message.id.to_string(),
+                        author: user_by_name(&message.author),
+                        content: message.content,
+                        created_at: message.created_at,
+                        reply_to: message.reply_to_id.map(|id| id.to_string()),
+                        reactions: message.reactions,
+                    })
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct SlackUser {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct SlackChannel {
+    id: String,
+    name: Option<String>,
+    created: Option<i64>,
+    #[serde(default)]
+    members: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct SlackMessage {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    subtype: Option<String>,
+    user: Option<String>,
+    #[serde(default)]
+    text: String,
+    ts: String,
+    thread_ts: Option<String>,
+    #[serde(default)]
+    reactions: Vec<SlackReaction>,
+}
+
+#[derive(Deserialize)]
+struct SlackReaction {
+    name: String,
+    count: Option<usize>,
+}
+
+/// Slack message subtypes that carry user-written content.
+const SLACK_CONTENT_SUBTYPES: [&str; 3] = ["thread_broadcast", "file_share", "me_message"];
+
+fn read_json_file<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, ImportError> {
+    let text = fs::read_to_string(path)?;
+    serde_json::from_str(&text).map_err(|e| parse_error(path, e))
+}
+
+/// Reads users.json plus channels.json, groups.json, mpims.json and
+/// dms.json (whichever exist), and each conversation's daily message files.
+fn load_slack(dir: &Path) -> Result<Vec<ImportConversation>, ImportError> {
+    let users: Vec<SlackUser> = read_json_file(&dir.join("users.json"))?;
+    let users: HashMap<String, ImportUser> = users
+        .into_iter()
+        .map(|u| (u.id.clone(), ImportUser { external_id: u.id, username: u.name }))
+        .collect();
+    let lookup_user = |id: &str| {
+        users.get(id).cloned().unwrap_or_else(|| ImportUser {
+            external_id: id.to_string(),
+            username: id.to_string(),
+        })
+    };
+
+    let mut conversations = Vec::new();
+    for (file, is_group) in [
+        ("channels.json", Some(true)),
+        ("groups.json", Some(true)),
+        ("mpims.json", Some(true)),
+        ("dms.json", Some(false)),
+    ] {
+        let path = dir.join(file);
+        if !path.exists() {
+            continue;
+        }
+
+        let channels: Vec<SlackChannel> = read_json_file(&path)?;
+        for channel in channels {
+            let participants: Vec<ImportUser> = channel.members.iter().map(|m| lookup_user(m)).collect();
+            let title = match &channel.name {
+                Some(name) => name.clone(),
+                None => participants.iter().map(|p| p.username.as_str()).collect::<Vec<_>>().join(", "),
+            };
+
+            // Direct messages are stored under their id, everything else under the name.
+            let folder = dir.join(channel.name.as_deref().unwrap_or(&channel.id));
+            let mut day_files: Vec<PathBuf> = match fs::read_dir(&folder) {
+                Ok(entries) => entries
+                    .filter_map(|e| e.ok().map(|e| e.path()))
+                    .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+                    .collect(),
+                Err(_) => Vec::new(),
+            };
+            day_files.sort();
+
+            let mut messages = Vec::new();
+            for day_file in day_files {
+                let day: Vec<SlackMessage> = read_json_file(&day_file)?;
+                for message in day {
+                    let is_content = message.kind.as_deref().unwrap_or("message") == "message"
+                        && message.subtype.as_deref().is_none_or(|s| SLACK_CONTENT_SUBTYPES.contains(&s));
+                    let (Some(user), true) = (message.user.as_deref(), is_content) else {
+                        continue;
+                    };
+                    let Some(created_at) = parse_slack_ts(&message.ts) else {
+                        return Err(parse_error(&day_file, format!("invalid ts '{}'", message.ts)));
+                    };
+
+                    messages.push(ImportMessage {
+                        external_id: message.ts.clone(),
+                        author: lookup_user(user),
+                        content: message.text,
+                        created_at,
+                        reply_to: message.thread_ts.filter(|parent| *parent != message.ts),
+                        reactions: message
+                            .reactions
+                            .iter()
+                            .flat_map(|r| std::iter::repeat_n(format!(":{}:", r.name), r.count.unwrap_or(1).max(1)))
+                            .collect(),
+                    });
+                }
+            }
+
+            conversations.push(ImportConversation {
+                external_id: channel.id,
+                title,
+                is_group,
+                created_at: channel.created.and_then(Timestamp::from_unix),
+                participants,
+                messages,
+            });
+        }
+    }
+
+    Ok(conversations)
+}
+
+fn parse_slack_ts(ts: &str) -> Option<Timestamp> {
+    let seconds = ts.split('.').next()?.parse().ok()?;
+    Timestamp::from_unix(seconds)
+}
+
+#[derive(Deserialize)]
+struct CsvRow {
+    conversation: String,
+    author: String,
+    content: String,
+    created_at: String,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    reply_to: Option<String>,
+    #[serde(default)]
+    reactions: Option<String>,
+}
+
+/// One row per message. Rows are grouped into conversations by the
+/// `conversation` column; `id`, `reply_to` and `reactions` are optional.
+fn load_csv(path: &Path) -> Result<Vec<ImportConversation>, ImportError> {
+    let mut reader = csv::Reader::from_path(path).map_err(|e| parse_error(path, e))?;
+    let mut conversations: Vec<ImportConversation> = Vec::new();
+
+    for (index, row) in reader.deserialize::<CsvRow>().enumerate() {
+        let line = index + 2;
+        let row = row.map_err(|e| parse_error(path, e))?;
+        let created_at = Timestamp::parse_bound(&row.created_at, false)
+            .ok_or_else(|| parse_error(path, format!("line {}: invalid created_at '{}'", line, row.created_at)))?;
+
+        let position = match conversations.iter().position(|c| c.external_id == row.conversation) {
+            Some(position) => position,
+            None => {
+                conversations.push(ImportConversation {
+                    external_id: row.conversation.clone(),
+                    title: row.conversation.clone(),
+                    is_group: None,
+                    created_at: None,
+                    participants: Vec::new(),
+                    messages: Vec::new(),
+                });
+                conversations.len() - 1
+            }
+        };
+
+        let conversation = &mut conversations[position];
+        if !conversation.participants.iter().any(|p| p.username == row.author) {
+            conversation.participants.push(user_by_name(&row.author));
+        }
+        conversation.messages.push(ImportMessage {
+            external_id: row.id.filter(|id| !id.is_empty()).unwrap_or_else(|| format!("line-{}", line)),
+            author: user_by_name(&row.author),
+            content: row.content,
+            created_at,
+            reply_to: row.reply_to.filter(|id| !id.is_empty()),
+            reactions: row
+                .reactions
+                .map(|r| r.split([',', ' ']).filter(|s| !s.is_empty()).map(str::to_string).collect())
+                .unwrap_or_default(),
+        });
+    }
+
+    Ok(conversations)
+}
+
+// ===== WRITING =====
+
+/// Imports everything in one transaction. Rows already recorded in
+/// orbit_import_map for `source` are reused, so re-running an import only
+/// adds what is new. A dry run does all the work and rolls it back.
+pub fn run(
+    db: &Database,
+    source: &str,
+    conversations: Vec<ImportConversation>,
+    dry_run: bool,
+) -> Result<ImportReport, ImportError> {
+    let mut tx = db.start_transaction()?;
+    let mut report = ImportReport {
+        source: source.to_string(),
+        dry_run,
+        ..Default::default()
+    };
+    let mut user_ids: HashMap<String, i32> = HashMap::new();
+
+    for conversation in conversations {
+        import_conversation(&mut tx, source, conversation, &mut user_ids, &mut report)?;
+    }
+
+    if dry_run {
+        tx.rollback()?;
+    } else {
+        tx.commit()?;
+    }
+    Ok(report)
+}
+
+fn lookup(tx: &mut Transaction, source: &str, kind: &str, external_id: &str) -> mysql::Result<Option<i32>> {
+    tx.exec_first(
+        "SELECT local_id FROM orbit_import_map WHERE source = :source AND kind = :kind AND external_id = :external_id",
+        params! { "source" => source, "kind" => kind, "external_id" => external_id },
+    )
+}
+
+fn remember(tx: &mut Transaction, source: &str, kind: &str, external_id: &str, local_id: i32) -> mysql::Result<()> {
+    tx.exec_drop(
+        "INSERT INTO orbit_import_map (source, kind, external_id, local_id)
+         VALUES (:source, :kind, :external_id, :local_id)",
+        params! { "source" => source, "kind" => kind, "external_id" => external_id, "local_id" => local_id },
+    )
+}
+
+/// Maps an external user to a tunispace user: a previous import, then an
+/// existing account with the same username, else a new inactive account.
+/// Id of the row the last INSERT on `tx` created.
+fn inserted_id(tx: &Transaction) -> Result<i32, ImportError> {
+    tx.last_insert_id()
+        .map(|id| id as i32)
+        .ok_or_else(|| ImportError::Parse("insert returned no id".into()))
+}
+
+fn resolve_user(
+    tx: &mut Transaction,
+    source: &str,
+    user: &ImportUser,
+    cache: &mut HashMap<String, i32>,
+    report: &mut ImportReport,
+) -> Result<i32, ImportError> {
+    if let Some(id) = cache.get(&user.external_id) {
+        return Ok(*id);
+    }
+
+    let id = match lookup(tx, source, "user", &user.external_id)? {
+        Some(id) => id,
+        None => {
+            let existing: Option<i32> = tx.exec_first(
+                "SELECT id FROM user WHERE username = :username",
+                params! { "username" => &user.username },
+            )?;
+            let id = match existing {
+                Some(id) => {
+                    report.users_matched += 1;
+                    id
+                }
+                None => {
+                    tx.exec_drop(
+                        "INSERT INTO user (username, email, password, chat_role, is_active, created_at)
+                         VALUES (:username, :email, '', 'user', 0, NOW())",
+                        params! {
+                            "username" => &user.username,
+                            "email" => format!("{}@imported.invalid", user.username),
+                        },
+                    )?;
+                    report.users_created += 1;
+                    inserted_id(tx)?
+                }
+            };
+            remember(tx, source, "user", &user.external_id, id)?;
+            id
+        }
+    };
+
+    cache.insert(user.external_id.clone(), id);
+    Ok(id)
+}
+
+fn import_conversation(
+    tx: &mut Transaction,
+    source: &str,
+    mut conversation: ImportConversation,
+    user_ids: &mut HashMap<String, i32>,
+    report: &mut ImportReport,
+) -> Result<(), ImportError> {
+    let conversation_id = match lookup(tx, source, "conversation", &conversation.external_id)? {
+        Some(id) => {
+            report.conversations_existing += 1;
+            id
+        }
+        None => {
+            let is_group = conversation.is_group.unwrap_or(conversation.participants.len() > 2);
+            tx.exec_drop(
+                "INSERT INTO conversations (title, is_group, created_at)
+                 VALUES (:title, :is_group, COALESCE(:created_at, NOW()))",
+                params! {
+                    "title" => &conversation.title,
+                    "is_group" => is_group,
+                    "created_at" => conversation.created_at,
+                },
+            )?;
+            let id = inserted_id(tx)?;
+            remember(tx, source, "conversation", &conversation.external_id, id)?;
+            report.conversations_created += 1;
+            id
+        }
+    };
+
+    let mut members = BTreeSet::new();
+    for user in conversation.participants.iter().chain(conversation.messages.iter().map(|m| &m.author)) {
+        members.insert(resolve_user(tx, source, user, user_ids, report)?);
+    }
+    for user_id in members {
+        tx.exec_drop(
+            "INSERT IGNORE INTO conversation_users (conversation_id, user_id, is_admin)
+             VALUES (:cid, :uid, FALSE)",
+            params! { "cid" => conversation_id, "uid" => user_id },
+        )?;
+        report.participants_added += tx.affected_rows() as usize;
+    }
+
+    // Message ids are only unique within a conversation in some sources.
+    let key = |external_id: &str| format!("{}/{}", conversation.external_id, external_id);
+
+    conversation.messages.sort_by_key(|m| m.created_at);
+    let mut message_ids: HashMap<String, i32> = HashMap::new();
+    let mut pending_replies: Vec<(i32, String)> = Vec::new();
+
+    for message in &conversation.messages {
+        if let Some(id) = lookup(tx, source, "message", &key(&message.external_id))? {
+            message_ids.insert(message.external_id.clone(), id);
+            report.messages_already_imported += 1;
+            continue;
+        }
+
+        let user_id = resolve_user(tx, source, &message.author, user_ids, report)?;
+        let reaction = if message.reactions.is_empty() {
+            None
+        } else {
+            Some(message.reactions.join(","))
+        };
+
+        tx.exec_drop(
+            "INSERT INTO messages (conversation_id, user_id, content, reaction, created_at)
+             VALUES (:cid, :uid, :content, :reaction, :created_at)",
+            params! {
+                "cid" => conversation_id,
+                "uid" => user_id,
+                "content" => &message.content,
+                "reaction" => reaction,
+                "created_at" => message.created_at,
+            },
+        )?;
+        let id = inserted_id(tx)?;
+        remember(tx, source, "message", &key(&message.external_id), id)?;
+        message_ids.insert(message.external_id.clone(), id);
+        report.messages_imported += 1;
+
+        if let Some(parent) = &message.reply_to {
+            pending_replies.push((id, parent.clone()));
+        }
+    }
+
+    // Parents may come later in the source or from an earlier run.
+    for (id, parent) in pending_replies {
+        let parent_id = match message_ids.get(&parent) {
+            Some(parent_id) => Some(*parent_id),
+            None => lookup(tx, source, "message", &key(&parent))?,
+        };
+
+        match parent_id {
+            Some(parent_id) => {
+                tx.exec_drop(
+                    "UPDATE messages SET reply_to_id = :parent WHERE id = :id",
+                    params! { "parent" => parent_id, "id" => id },
+                )?;
+                report.replies_linked += 1;
+            }
+            None => {
+                report.replies_unresolved += 1;
+                report.warn(format!(
+                    "Conversation '{}': reply target '{}' not found",
+                    conversation.title, parent
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// ===== COMMAND LINE =====
+
+const USAGE: &str = "Usage: chat-IBM import <path> [--format json|slack|csv] [--source NAME] [--dry-run]";
+
+/// Entry point for `chat-IBM import ...`. Prints the report as JSON.
+pub fn run_cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut path = None;
+    let mut format = None;
+    let mut source = None;
+    let mut dry_run = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            "--format" => {
+                let value = args.next().ok_or(USAGE)?;
+                format = Some(ImportFormat::parse(value).ok_or_else(|| format!("Unknown format '{}'\n{}", value, USAGE))?);
+            }
+            "--source" => source = Some(args.next().ok_or(USAGE)?.clone()),
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument '{}'\n{}", arg, USAGE).into()),
+        }
+    }
+
+    let path = path.ok_or(USAGE)?;
+    let format = format
+        .or_else(|| ImportFormat::detect(&path))
+        .ok_or_else(|| format!("Cannot detect the format of {}, pass --format\n{}", path.display(), USAGE))?;
+    // The source name scopes the id map, so the same file re-imports idempotently.
+    let source = source.unwrap_or_else(|| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "import".to_string())
+    });
+
+    let conversations = load(&path, format)?;
+    let db = Database::new()?;
+    let report = run(&db, &source, conversations, dry_run)?;
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if dry_run {
+        println!("Dry run: no changes were written.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, contents: &str) -> PathBuf {
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn csv_rows_are_grouped_by_conversation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(dir.path(), "chat.csv", "\
+conversation,author,content,created_at,id,reply_to,reactions
+general,alice,hello,2024-03-01 10:00:00,m1,,
+general,bob,\"hi, alice\",2024-03-01 10:01:00,m2,m1,\":+1: :tada:\"
+random,alice,lunch?,2024-03-01,,,
+");
+
+        let conversations = load_csv(&path).unwrap();
+        assert_eq!(conversations.len(), 2);
+
+        let general = &conversations[0];
+        assert_eq!(general.external_id, "general");
+        assert_eq!(general.is_group, None);
+        assert_eq!(general.participants.iter().map(|p| p.username.as_str()).collect::<Vec<_>>(), ["alice", "bob"]);
+        assert_eq!(general.messages[1].content, "hi, alice");
+        assert_eq!(general.messages[1].reply_to.as_deref(), Some("m1"));
+        assert_eq!(general.messages[1].reactions, [":+1:", ":tada:"]);
+        assert_eq!(general.messages[0].created_at.to_db().to_string(), "2024-03-01 10:00:00");
+
+        // Rows without an id are keyed by their line number.
+        assert_eq!(conversations[1].messages[0].external_id, "line-4");
+        assert_eq!(conversations[1].messages[0].reply_to, None);
+    }
+
+    #[test]
+    fn csv_reports_the_line_of_an_invalid_date() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(dir.path(), "chat.csv", "conversation,author,content,created_at\ngeneral,alice,hello,yesterday\n");
+
+        match load_csv(&path) {
+            Err(ImportError::Parse(message)) => assert!(message.contains("line 2"), "{}", message),
+            other => panic!("expected a parse error, got {:?}", other.map(|c| c.len())),
+        }
+    }
+
+    #[test]
+    fn slack_exports_map_users_threads_and_reactions() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "users.json", r#"[{"id":"U1","name":"alice"},{"id":"U2","name":"bob"}]"#);
+        write(dir.path(), "channels.json", r#"[{"id":"C1","name":"general","created":1709280000,"members":["U1","U2"]}]"#);
+        write(dir.path(), "dms.json", r#"[{"id":"D1","members":["U1","U2"]}]"#);
+        write(dir.path(), "general/2024-03-02.json", r#"[
+            {"type":"message","user":"U2","text":"reply","ts":"1709370000.000200","thread_ts":"1709366400.000100"}
+        ]"#);
+        write(dir.path(), "general/2024-03-01.json", r#"[
+            {"type":"message","user":"U1","text":"hello","ts":"1709366400.000100","thread_ts":"1709366400.000100",
+             "reactions":[{"name":"tada","count":2}]},
+            {"type":"message","subtype":"channel_join","user":"U2","text":"joined","ts":"1709366500.000100"},
+            {"type":"message","subtype":"me_message","user":"U3","text":"waves","ts":"1709366600.000100"}
+        ]"#);
+        write(dir.path(), "D1/2024-03-01.json", r#"[{"type":"message","user":"U1","text":"psst","ts":"1709366700.000100"}]"#);
+
+        let conversations = load_slack(dir.path()).unwrap();
+        assert_eq!(conversations.len(), 2);
+
+        let general = &conversations[0];
+        assert_eq!((general.title.as_str(), general.is_group), ("general", Some(true)));
+        assert_eq!(general.created_at.map(Timestamp::unix_seconds), Some(1709280000));
+        let texts: Vec<&str> = general.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(texts, ["hello", "waves", "reply"]);
+        assert_eq!(general.messages[0].reply_to, None);
+        assert_eq!(general.messages[0].reactions, [":tada:", ":tada:"]);
+        assert_eq!(general.messages[0].created_at.unix_seconds(), 1709366400);
+        // Unknown users keep their Slack id as the username.
+        assert_eq!(general.messages[1].author.username, "U3");
+        assert_eq!(general.messages[2].author.username, "bob");
+        assert_eq!(general.messages[2].reply_to.as_deref(), Some("1709366400.000100"));
+
+        let dm = &conversations[1];
+        assert_eq!((dm.title.as_str(), dm.is_group), ("alice, bob", Some(false)));
+        assert_eq!(dm.messages[0].content, "psst");
+    }
+
+    #[test]
+    fn slack_rejects_an_invalid_ts() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "users.json", "[]");
+        write(dir.path(), "channels.json", r#"[{"id":"C1","name":"general"}]"#);
+        write(dir.path(), "general/2024-03-01.json", r#"[{"type":"message","user":"U1","text":"x","ts":"soon"}]"#);
+
+        assert!(matches!(load_slack(dir.path()), Err(ImportError::Parse(_))));
+    }
+
+    #[test]
+    fn orbit_json_accepts_one_export_or_an_array() {
+        let export = r#"{
+            "conversation": {"id": 7, "title": "Plans", "is_group": true, "created_at": "2024-03-01T09:00:00+00:00"},
+            "participants": ["alice"],
+            "messages": [
+                {"id": 1, "created_at": "2024-03-01T10:00:00+00:00", "author": "alice", "reply_to_id": null, "content": "hi"},
+                {"id": 2, "created_at": "2024-03-01T10:05:00+00:00", "author": "bob", "reply_to_id": 1,
+                 "reactions": [":+1:"], "content": "hello"}
+            ]
+        }"#;
+        let dir = tempfile::tempdir().unwrap();
+
+        let single = load_orbit_json(&write(dir.path(), "one.json", export)).unwrap();
+        let many = load_orbit_json(&write(dir.path(), "many.json", &format!("[{0},{0}]", export))).unwrap();
+        assert_eq!((single.len(), many.len()), (1, 2));
+
+        let conversation = &single[0];
+        assert_eq!((conversation.external_id.as_str(), conversation.title.as_str()), ("7", "Plans"));
+        assert_eq!(conversation.is_group, Some(true));
+        // Authors missing from the participant list are added.
+        assert_eq!(conversation.participants.iter().map(|p| p.username.as_str()).collect::<Vec<_>>(), ["alice", "bob"]);
+        assert_eq!(conversation.messages[1].reply_to.as_deref(), Some("1"));
+        assert_eq!(conversation.messages[1].reactions, [":+1:"]);
+        assert_eq!(conversation.messages[1].created_at.unix_seconds(), 1709287500);
+    }
+
+    #[test]
+    fn orbit_json_reports_malformed_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(dir.path(), "bad.json", r#"{"conversation": {"id": 1}}"#);
+        assert!(matches!(load_orbit_json(&path), Err(ImportError::Parse(_))));
+    }
+}
@@ -0,0 +1,560 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::env;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use crate::anthropic::AnthropicProvider;
+use crate::ollama::OllamaProvider;
+use crate::sse::SseDecoder;
+use crate::stream_tags::{hidden_tags_from_env, TagFilter};
+use crate::ws_server::WebSocketServer;
+
+// ===== CONVERSATION TYPES =====
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+    /// Native tool calls made by an assistant message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<NativeToolCall>>,
+    /// The call a `tool` message answers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+/// A tool call in OpenAI wire format.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NativeToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: NativeFunctionCall,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NativeFunctionCall {
+    pub name: String,
+    /// JSON-encoded arguments object.
+    pub arguments: String,
+}
+
+/// A fragment of a streamed tool call. The first fragment of a call has its
+/// id and name; later ones append to the arguments string.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ToolCallDelta {
+    pub index: Option<usize>,
+    pub id: Option<String>,
+    pub function: Option<FunctionDelta>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FunctionDelta {
+    pub name: Option<String>,
+    pub arguments: Option<String>,
+}
+
+static NEXT_CALL_ID: AtomicU64 = AtomicU64::new(1);
+
+/// An id for a tool call the server did not name. Ids must stay unique
+/// across the whole history: providers pair tool results by id, and
+/// Anthropic rejects repeated tool_use ids.
+pub fn next_call_id() -> String {
+    format!("call_orbit_{}", NEXT_CALL_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Rebuilds complete tool calls from streamed deltas.
+#[derive(Default)]
+pub struct ToolCallAccumulator {
+    calls: Vec<NativeToolCall>,
+}
+
+impl ToolCallAccumulator {
+    pub fn push(&mut self, delta: &ToolCallDelta) {
+        // Servers that omit the index start a new call with a new id.
+        let index = delta.index.unwrap_or_else(|| match (self.calls.last(), &delta.id) {
+            (Some(last), Some(id)) if !last.id.is_empty() && last.id != *id => self.calls.len(),
+            _ => self.calls.len().saturating_sub(1),
+        });
+        while self.calls.len() <= index {
+            self.calls.push(NativeToolCall {
+                id: String::new(),
+                kind: "function".to_string(),
+                function: NativeFunctionCall { name: String::new(), arguments: String::new() },
+            });
+        }
+
+        let call = &mut self.calls[index];
+        if let Some(id) = delta.id.as_ref().filter(|id| !id.is_empty()) {
+            call.id = id.clone();
+        }
+        if let Some(function) = &delta.function {
+            if let Some(name) = &function.name {
+                if call.function.name.is_empty() {
+                    call.function.name = name.clone();
+                } else {
+                    call.function.name.push_str(name);
+                }
+            }
+            if let Some(arguments) = &function.arguments {
+                call.function.arguments.push_str(arguments);
+            }
+        }
+    }
+
+    pub fn finish(self) -> Vec<NativeToolCall> {
+        self.calls
+            .into_iter()
+            .filter(|call| !call.function.name.is_empty())
+            .map(|mut call| {
+                if call.id.is_empty() {
+                    call.id = next_call_id();
+                }
+                call
+            })
+            .collect()
+    }
+}
+
+/// What the model produced in one streamed completion.
+pub struct ChatResponse {
+    pub content: String,
+    pub tool_calls: Vec<NativeToolCall>,
+}
+
+/// The server refused a request that carried the tools array.
+#[derive(Debug)]
+pub struct ToolsUnsupported(pub String);
+
+impl std::fmt::Display for ToolsUnsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "server rejected native tool calling: {}", self.0)
+    }
+}
+
+impl std::error::Error for ToolsUnsupported {}
+
+/// Whether an error response means the server does not accept the tools
+/// array: a 400 or 422 whose body names `tools` or `tool_choice`. Other
+/// failures (auth, rate limits, unknown models) are ordinary errors.
+fn rejects_tools(status: reqwest::StatusCode, body: &str) -> bool {
+    matches!(status.as_u16(), 400 | 422) && {
+        let body = body.to_lowercase();
+        body.contains("tools") || body.contains("tool_choice")
+    }
+}
+
+/// Forwards streamed text to the UI, leaving out <tool_request> blocks and
+/// other hidden sections such as <think>.
+pub struct StreamSink<'a> {
+    ws_server: Option<&'a WebSocketServer>,
+    filter: TagFilter,
+}
+
+impl<'a> StreamSink<'a> {
+    pub fn new(ws_server: Option<&'a WebSocketServer>) -> Self {
+        StreamSink { ws_server, filter: TagFilter::new(hidden_tags_from_env()) }
+    }
+
+    pub async fn push(&mut self, content: &str) {
+        let visible = self.filter.push(content);
+        self.send(&visible).await;
+    }
+
+    /// Call once the completion has finished streaming, to release text
+    /// held back in case it started a tag.
+    pub async fn finish(&mut self) {
+        let visible = self.filter.finish();
+        self.send(&visible).await;
+    }
+
+    async fn send(&self, visible: &str) {
+        if visible.is_empty() {
+            return;
+        }
+        if let Some(ws) = self.ws_server {
+            ws.broadcast_json(&json!({
+                "type": "chunk",
+                "content": visible
+            })).await;
+        }
+    }
+}
+
+/// Reads the first `limit` characters of an error response body.
+pub async fn error_body(response: reqwest::Response, limit: usize) -> String {
+    response.text().await.unwrap_or_default().chars().take(limit).collect()
+}
+
+// ===== PROVIDERS =====
+
+/// A chat backend. Implementations translate the OpenAI-shaped conversation
+/// and tools array into their own wire format and stream the reply.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Short provider name used in model ids, e.g. `ollama`.
+    fn kind(&self) -> &'static str;
+
+    fn model(&self) -> &str;
+
+    /// Streams one completion. With `tools` set the request carries the
+    /// native tools array; a server that rejects it returns `ToolsUnsupported`.
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: Option<&[serde_json::Value]>,
+        sink: &mut StreamSink<'_>,
+    ) -> Result<ChatResponse, Box<dyn std::error::Error>>;
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct StreamChunk {
+    choices: Vec<StreamChoice>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct StreamChoice {
+    delta: StreamDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct StreamDelta {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+/// OpenAI-compatible `/chat/completions` servers: LM Studio, llama.cpp
+/// server, vLLM.
+pub struct OpenAiProvider {
+    client: Client,
+    api_base: String,
+    api_key: String,
+    model: String,
+}
+
+impl OpenAiProvider {
+    pub fn new(client: Client, api_base: &str, api_key: &str, model: &str) -> Self {
+        OpenAiProvider {
+            client,
+            api_base: api_base.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn kind(&self) -> &'static str {
+        "openai"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: Option<&[serde_json::Value]>,
+        sink: &mut StreamSink<'_>,
+    ) -> Result<ChatResponse, Box<dyn std::error::Error>> {
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+            "stream": true
+        });
+        if let Some(tools) = tools.filter(|t| !t.is_empty()) {
+            body["tools"] = json!(tools);
+            body["tool_choice"] = json!("auto");
+        }
+
+        let response = self.client
+            .post(format!("{}/chat/completions", self.api_base))
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to send request to {}: {}", self.api_base, e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let text = error_body(response, 500).await;
+            if tools.is_some() && rejects_tools(status, &text) {
+                return Err(ToolsUnsupported(format!("{} {}", status, text)).into());
+            }
+            return Err(format!("LLM server returned {}: {}", status, text).into());
+        }
+
+        let mut stream = response.bytes_stream();
+        let mut decoder = SseDecoder::new();
+        let mut accumulated_content = String::new();
+        let mut tool_calls = ToolCallAccumulator::default();
+        let mut done = false;
+
+        use futures_util::stream::StreamExt;
+
+        'stream: while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| format!("Error reading stream: {}", e))?;
+
+            for event in decoder.feed(&chunk) {
+                let event = event.map_err(|e| format!("Malformed stream from LLM server: {}", e))?;
+                if event.data == "[DONE]" {
+                    done = true;
+                    break 'stream;
+                }
+
+                let chunk_data = match serde_json::from_str::<StreamChunk>(&event.data) {
+                    Ok(chunk_data) => chunk_data,
+                    Err(e) => return Err(stream_error(&event.data, e).into()),
+                };
+                if let Some(choice) = chunk_data.choices.first() {
+                    for delta in choice.delta.tool_calls.iter().flatten() {
+                        tool_calls.push(delta);
+                    }
+                    if let Some(content) = &choice.delta.content {
+                        accumulated_content.push_str(content);
+                        sink.push(content).await;
+                    }
+                }
+            }
+        }
+
+        if !done {
+            decoder.finish().map_err(|e| format!("Malformed stream from LLM server: {}", e))?;
+        }
+
+        Ok(ChatResponse {
+            content: accumulated_content,
+            tool_calls: tool_calls.finish(),
+        })
+    }
+}
+
+/// Describes a data payload that is not a completion chunk, preferring the
+/// server's own message when it streamed an error object.
+pub fn stream_error(data: &str, parse_error: serde_json::Error) -> String {
+    let server_message = serde_json::from_str::<serde_json::Value>(data).ok().and_then(|value| {
+        let error = value.get("error")?;
+        Some(error.get("message").and_then(|m| m.as_str()).map(str::to_string).unwrap_or_else(|| error.to_string()))
+    });
+    match server_message {
+        Some(message) => format!("LLM server reported an error: {}", message),
+        None => format!("Malformed stream event from LLM server ({}): {}", parse_error, data.chars().take(200).collect::<String>()),
+    }
+}
+
+// ===== MODEL REGISTRY =====
+
+/// A configured model: its provider and whether native tool calling is
+/// still in use for it.
+pub struct ModelEntry {
+    /// `provider:model[@base_url]`, as listed in ORBIT_MODELS.
+    pub id: String,
+    pub provider: Box<dyn LlmProvider>,
+    native_tools: AtomicBool,
+}
+
+impl ModelEntry {
+    pub fn native_tools(&self) -> bool {
+        self.native_tools.load(Ordering::Relaxed)
+    }
+
+    /// Switches this model to the <tool_request> text protocol for the rest
+    /// of the process.
+    pub fn disable_native_tools(&self) {
+        self.native_tools.store(false, Ordering::Relaxed);
+    }
+}
+
+struct Models {
+    entries: Vec<Arc<ModelEntry>>,
+    active: Mutex<usize>,
+}
+
+static MODELS: OnceLock<Models> = OnceLock::new();
+
+/// Builds one provider from a `provider:model[@base_url]` spec.
+fn build_provider(client: &Client, spec: &str) -> Result<Box<dyn LlmProvider>, String> {
+    let (kind, rest) = spec
+        .split_once(':')
+        .ok_or_else(|| format!("Model '{}' must be written as provider:model", spec))?;
+    let (model, base) = match rest.rsplit_once('@') {
+        Some((model, base)) if base.starts_with("http://") || base.starts_with("https://") => (model, Some(base.to_string())),
+        _ => (rest, None),
+    };
+    if model.is_empty() {
+        return Err(format!("Model '{}' has no model name", spec));
+    }
+
+    match kind.trim().to_lowercase().as_str() {
+        "openai" => {
+            let base = base.unwrap_or_else(|| env::var("LM_STUDIO_API_BASE").unwrap_or_else(|_| "http://localhost:1234/v1".to_string()));
+            let api_key = env::var("LM_STUDIO_API_KEY").unwrap_or_else(|_| "not-needed".to_string());
+            Ok(Box::new(OpenAiProvider::new(client.clone(), &base, &api_key, model)))
+        }
+        "ollama" => {
+            let base = base.unwrap_or_else(|| env::var("OLLAMA_HOST").unwrap_or_else(|_| "http://localhost:11434".to_string()));
+            Ok(Box::new(OllamaProvider::new(client.clone(), &base, model)))
+        }
+        "anthropic" => {
+            let base = base.unwrap_or_else(|| env::var("ANTHROPIC_BASE_URL").unwrap_or_else(|_| "https://api.anthropic.com/v1".to_string()));
+            let api_key = env::var("ANTHROPIC_API_KEY").map_err(|_| format!("Model '{}' needs ANTHROPIC_API_KEY", spec))?;
+            let max_tokens = env::var("ORBIT_ANTHROPIC_MAX_TOKENS").ok().and_then(|v| v.parse().ok()).unwrap_or(4096);
+            Ok(Box::new(AnthropicProvider::new(client.clone(), &base, &api_key, model, max_tokens)))
+        }
+        other => Err(format!("Unknown provider '{}' in '{}' (expected openai, ollama or anthropic)", other, spec)),
+    }
+}
+
+/// Reads ORBIT_MODELS, a comma-separated list of `provider:model[@base_url]`
+/// entries, e.g. `openai:ibm/granite-3.1-8b,ollama:llama3.1:8b`. The first
+/// one is active at startup. Without it the single model is
+/// `openai:<LM_STUDIO_MODEL>`.
+pub fn init_from_env(native_tools: bool) -> Result<Arc<ModelEntry>, String> {
+    let specs = match env::var("ORBIT_MODELS").ok().filter(|v| !v.trim().is_empty()) {
+        Some(list) => list.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect(),
+        None => vec![format!(
+            "openai:{}",
+            env::var("LM_STUDIO_MODEL").unwrap_or_else(|_| "ibm/granite-3.1-8b".to_string())
+        )],
+    };
+
+    let client = Client::new();
+    let mut entries = Vec::new();
+    for spec in specs {
+        let provider = build_provider(&client, &spec)?;
+        entries.push(Arc::new(ModelEntry {
+            id: spec,
+            provider,
+            native_tools: AtomicBool::new(native_tools),
+        }));
+    }
+    if entries.is_empty() {
+        return Err("ORBIT_MODELS lists no models".to_string());
+    }
+
+    MODELS.get_or_init(|| Models { entries, active: Mutex::new(0) });
+    Ok(active())
+}
+
+fn models() -> &'static Models {
+    MODELS.get().expect("llm::init_from_env must run before models are used")
+}
+
+pub fn configured_count() -> usize {
+    models().entries.len()
+}
+
+/// The model new messages are sent to.
+pub fn active() -> Arc<ModelEntry> {
+    let models = models();
+    let index = *models.active.lock().unwrap();
+    models.entries[index].clone()
+}
+
+/// Makes the model with the given id active.
+pub fn select(id: &str) -> Result<Arc<ModelEntry>, String> {
+    let models = models();
+    let index = models
+        .entries
+        .iter()
+        .position(|entry| entry.id == id)
+        .ok_or_else(|| format!("Model '{}' is not configured", id))?;
+    *models.active.lock().unwrap() = index;
+    Ok(models.entries[index].clone())
+}
+
+/// The `models` event: every configured model and the active one.
+pub fn current_event() -> Option<serde_json::Value> {
+    let models = MODELS.get()?;
+    let active = *models.active.lock().unwrap();
+    Some(json!({
+        "type": "models",
+        "models": models.entries.iter().map(|entry| json!({
+            "id": entry.id,
+            "provider": entry.provider.kind(),
+            "model": entry.provider.model(),
+            "native_tools": entry.native_tools()
+        })).collect::<Vec<_>>(),
+        "active": models.entries[active].id
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    fn delta(index: Option<usize>, id: Option<&str>, name: Option<&str>, arguments: Option<&str>) -> ToolCallDelta {
+        ToolCallDelta {
+            index,
+            id: id.map(str::to_string),
+            function: Some(FunctionDelta {
+                name: name.map(str::to_string),
+                arguments: arguments.map(str::to_string),
+            }),
+        }
+    }
+
+    fn summary(calls: &[NativeToolCall]) -> Vec<(&str, &str)> {
+        calls.iter().map(|c| (c.function.name.as_str(), c.function.arguments.as_str())).collect()
+    }
+
+    #[test]
+    fn accumulator_merges_indexed_deltas() {
+        let mut calls = ToolCallAccumulator::default();
+        calls.push(&delta(Some(0), Some("call_a"), Some("add"), Some("")));
+        calls.push(&delta(Some(1), Some("call_b"), Some("sqrt"), None));
+        calls.push(&delta(Some(0), None, None, Some("{\"a\":1,")));
+        calls.push(&delta(Some(1), None, None, Some("{\"value\":9}")));
+        calls.push(&delta(Some(0), None, None, Some("\"b\":2}")));
+
+        let calls = calls.finish();
+        assert_eq!(summary(&calls), [("add", "{\"a\":1,\"b\":2}"), ("sqrt", "{\"value\":9}")]);
+        assert_eq!((calls[0].id.as_str(), calls[1].id.as_str()), ("call_a", "call_b"));
+    }
+
+    #[test]
+    fn accumulator_splits_index_less_deltas_on_a_new_id() {
+        let mut calls = ToolCallAccumulator::default();
+        calls.push(&delta(None, Some("call_a"), Some("add"), None));
+        calls.push(&delta(None, None, None, Some("{\"a\":1}")));
+        calls.push(&delta(None, Some("call_a"), None, Some("")));
+        calls.push(&delta(None, Some("call_b"), Some("sqrt"), Some("{\"value\":")));
+        calls.push(&delta(None, None, None, Some("4}")));
+
+        let calls = calls.finish();
+        assert_eq!(summary(&calls), [("add", "{\"a\":1}"), ("sqrt", "{\"value\":4}")]);
+        assert_eq!(calls[1].id, "call_b");
+    }
+
+    #[test]
+    fn accumulator_names_unnamed_calls_uniquely_and_drops_empty_ones() {
+        let mut first = ToolCallAccumulator::default();
+        first.push(&delta(Some(0), None, Some("add"), Some("{}")));
+        first.push(&delta(Some(2), None, None, Some("{}")));
+        let mut second = ToolCallAccumulator::default();
+        second.push(&delta(Some(0), None, Some("add"), Some("{}")));
+
+        let (first, second) = (first.finish(), second.finish());
+        assert_eq!(first.len(), 1);
+        assert!(!first[0].id.is_empty());
+        assert_ne!(first[0].id, second[0].id);
+    }
+
+    #[test]
+    fn only_tool_related_bad_requests_reject_tools() {
+        assert!(rejects_tools(StatusCode::BAD_REQUEST, r#"{"error":"'tools' is not supported"}"#));
+        assert!(rejects_tools(StatusCode::UNPROCESSABLE_ENTITY, "unknown field tool_choice"));
+        assert!(!rejects_tools(StatusCode::BAD_REQUEST, "context length exceeded"));
+        assert!(!rejects_tools(StatusCode::UNAUTHORIZED, "tools require a paid plan"));
+        assert!(!rejects_tools(StatusCode::TOO_MANY_REQUESTS, "slow down"));
+        assert!(!rejects_tools(StatusCode::NOT_FOUND, "model not found"));
+    }
+}
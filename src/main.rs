@@ -1,37 +1,60 @@
 mod tools;
 mod ws_server;
 mod data_base;
+mod privacy;
+mod search;
+mod analytics;
+mod timestamp;
+mod watcher;
+mod export;
+mod import;
+mod embeddings;
+mod sql_query;
+mod db_health;
+mod retention;
+mod sse;
+mod stream_tags;
+mod llm;
+mod ollama;
+mod anthropic;
 
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::env;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tools::{get_available_tools, execute_tool, ToolCall};
+use tools::{authorize_watch, get_available_tools, execute_tool, is_math_tool, is_mutating_tool, undo_message, Tool, ToolCall, ToolContext, ToolResult};
+use data_base::Database;
 use regex::Regex;
 use ws_server::{WebSocketServer, ClientMessage};
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct Message {
-    role: String,
-    content: String,
+use watcher::{ActivityNotes, ConversationWatcher};
+use llm::{ChatResponse, Message, ModelEntry, NativeFunctionCall, NativeToolCall, StreamSink, ToolsUnsupported};
+
+/// ORBIT_TOOL_CALLING: `native` always sends the tools array, `text` only
+/// describes tools in the prompt and parses <tool_request> tags, `auto`
+/// (default) starts native and switches a model to text for the rest of the
+/// process if its server rejects the tools array.
+fn tool_calling_mode() -> String {
+    env::var("ORBIT_TOOL_CALLING")
+        .map(|v| v.trim().to_lowercase())
+        .ok()
+        .filter(|v| v == "native" || v == "text")
+        .unwrap_or_else(|| "auto".to_string())
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct StreamChunk {
-    choices: Vec<StreamChoice>,
+fn native_tools_enabled() -> bool {
+    llm::active().native_tools()
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct StreamChoice {
-    delta: StreamDelta,
-    finish_reason: Option<String>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct StreamDelta {
-    content: Option<String>,
+/// The tools array for native calling; database tools are left out while
+/// the database is down.
+fn native_tool_schemas() -> Vec<serde_json::Value> {
+    let db_available = db_health::is_available();
+    get_available_tools()
+        .iter()
+        .filter(|t| db_available || is_math_tool(&t.name))
+        .map(Tool::to_function_schema)
+        .collect()
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -40,45 +63,115 @@ struct CustomToolRequest {
     arguments: serde_json::Map<String, serde_json::Value>,
 }
 
-/// Converts our tool definitions to a text format the model can understand
-fn format_tools_for_prompt() -> String {
+fn describe_tool(tool: &Tool) -> String {
+    let mut description = format!("Tool: {}\nDescription: {}\nParameters:\n", tool.name, tool.description);
+    if tool.parameters.is_empty() {
+        description.push_str("  (no parameters)\n");
+    } else {
+        for param in &tool.parameters {
+            description.push_str(&format!("  - {} ({}): {}\n", param.name, param.param_type, param.description));
+        }
+    }
+    description.push('\n');
+    description
+}
+
+/// Converts our tool definitions to a text format the model can understand.
+/// While the database is down its tools are listed as unavailable. With
+/// native tool calling the definitions travel in the request instead, so
+/// only the usage rules are included.
+fn format_tools_for_prompt(db_available: bool) -> String {
+    if native_tools_enabled() {
+        let mut rules = String::from("\n\nTools are provided through function calling. Call them directly.\n");
+        rules.push_str("Write tools change the chat database. Only use them when the user explicitly asks for the change.\n");
+        rules.push_str("Write tools accept an optional idempotency_key. Pick a new key for each intended change and\n");
+        rules.push_str("reuse it when retrying that same call; a repeat returns the original result instead of writing twice.\n");
+        if !db_available {
+            rules.push_str("The database is down and Orbit is reconnecting, so only the mathematical tools are available.\n");
+            rules.push_str("Tell the user that requests needing the database should be tried again shortly.\n");
+        }
+        return rules;
+    }
+
     let tools = get_available_tools();
     let mut tool_descriptions = String::from("\n\nYou have access to the following tools:\n\n");
 
     // Group tools by category
     tool_descriptions.push_str("=== MATHEMATICAL TOOLS ===\n");
-    for tool in tools.iter().filter(|t| matches!(t.name.as_str(), "add" | "subtract" | "multiply" | "divide" | "power" | "sqrt")) {
-        tool_descriptions.push_str(&format!("Tool: {}\n", tool.name));
-        tool_descriptions.push_str(&format!("Description: {}\n", tool.description));
-        tool_descriptions.push_str("Parameters:\n");
-        for param in &tool.parameters {
-            tool_descriptions.push_str(&format!("  - {} ({}): {}\n", param.name, param.param_type, param.description));
-        }
-        tool_descriptions.push_str("\n");
+    for tool in tools.iter().filter(|t| is_math_tool(&t.name)) {
+        tool_descriptions.push_str(&describe_tool(tool));
+    }
+
+    if !db_available {
+        tool_descriptions.push_str("=== DATABASE TOOLS: CURRENTLY UNAVAILABLE ===\n");
+        tool_descriptions.push_str("The database is down and Orbit is reconnecting. Do not call any database tool;\n");
+        tool_descriptions.push_str("tell the user their request needs the database and to try again shortly.\n");
+        tool_descriptions.push_str("Unavailable: ");
+        let names: Vec<&str> = tools.iter().filter(|t| !is_math_tool(&t.name)).map(|t| t.name.as_str()).collect();
+        tool_descriptions.push_str(&names.join(", "));
+        tool_descriptions.push_str("\n\n");
+        tool_descriptions.push_str(TOOL_CALL_INSTRUCTIONS);
+        return tool_descriptions;
     }
 
     tool_descriptions.push_str("=== DATABASE TOOLS ===\n");
-    for tool in tools.iter().filter(|t| !matches!(t.name.as_str(), "add" | "subtract" | "multiply" | "divide" | "power" | "sqrt")) {
-        tool_descriptions.push_str(&format!("Tool: {}\n", tool.name));
-        tool_descriptions.push_str(&format!("Description: {}\n", tool.description));
-        tool_descriptions.push_str("Parameters:\n");
-        if tool.parameters.is_empty() {
-            tool_descriptions.push_str("  (no parameters)\n");
-        } else {
-            for param in &tool.parameters {
-                tool_descriptions.push_str(&format!("  - {} ({}): {}\n", param.name, param.param_type, param.description));
-            }
-        }
-        tool_descriptions.push_str("\n");
+    for tool in tools.iter().filter(|t| !is_math_tool(&t.name) && !is_mutating_tool(&t.name)) {
+        tool_descriptions.push_str(&describe_tool(tool));
     }
 
-    tool_descriptions.push_str("To use a tool, respond with: <tool_request>[{\"name\": \"tool_name\", \"arguments\": {\"param\": value}}]</tool_request>\n");
-    tool_descriptions.push_str("You can call multiple tools by including multiple objects in the array.\n");
-    tool_descriptions.push_str("After receiving tool results, provide your final answer to the user.\n");
+    tool_descriptions.push_str("=== DATABASE WRITE TOOLS ===\n");
+    tool_descriptions.push_str("These change the chat database. Only use them when the user explicitly asks for the change.\n");
+    tool_descriptions.push_str("Each accepts an optional idempotency_key (string). Pick a new key for each intended change and\n");
+    tool_descriptions.push_str("reuse it when retrying that same call; a repeat returns the original result instead of writing twice.\n\n");
+    for tool in tools.iter().filter(|t| is_mutating_tool(&t.name)) {
+        tool_descriptions.push_str(&describe_tool(tool));
+    }
 
+    tool_descriptions.push_str(TOOL_CALL_INSTRUCTIONS);
     tool_descriptions
 }
 
+const TOOL_CALL_INSTRUCTIONS: &str = "To use a tool, respond with: <tool_request>[{\"name\": \"tool_name\", \"arguments\": {\"param\": value}}]</tool_request>\n\
+    You can call multiple tools by including multiple objects in the array.\n\
+    After receiving tool results, provide your final answer to the user.\n";
+
+/// The system prompt; rebuilt when database availability changes.
+fn build_system_prompt(db_available: bool) -> String {
+    format!(
+        "You are Orbit, an advanced AI assistant with access to mathematical tools AND database tools.\n\
+        {}\n\n\
+        DATABASE CONTEXT:\n\
+        You have access to a chat application database with users, conversations, and messages.\n\
+        You can:\n\
+        - Search and summarize conversations\n\
+        - Keep stored summaries up to date: call get_cached_summary first; if it is stale or missing,\n\
+          summarize only new_messages, merge them into the cached summary and store the result with\n\
+          save_conversation_summary using covers_through_id\n\
+        - Search across every conversation a user belongs to\n\
+        - Find messages by meaning with semantic_search when keyword search misses paraphrases\n\
+        - Answer ad-hoc reporting questions for staff with run_sql_query (read-only SELECT, no SELECT *)\n\
+        - Follow reply threads and analyze reactions\n\
+        - Send, edit and delete messages as the session's user\n\
+        - Create conversations, rename them and manage participants\n\
+        - Find users and their conversations\n\
+        - Manage message retention rules and preview what a purge would delete (staff only)\n\
+        - Export a full conversation transcript (Markdown, JSON, CSV or HTML) as a download link\n\
+        - Get conversation statistics and activity analytics (participation, busiest hours, response times, streaks)\n\
+        \n\
+        Database tools act on behalf of the tunispace user bound to this session and can only\n\
+        reach conversations that user belongs to. If a tool reports \"Permission denied\", tell the\n\
+        user instead of retrying with other ids.\n\
+        Personal data in message content is replaced with placeholders such as [EMAIL_1] or [PHONE_2].\n\
+        Use them verbatim when referring to those values and never try to guess the originals.\n\
+        The operator can watch conversations live; new messages in them show up as system notes\n\
+        starting with \"New message\". Mention relevant new activity when it helps the user.\n\
+        When users ask about conversations, users, or messages, use the appropriate database tools.\n\
+        When users ask mathematical questions, use the mathematical tools.\n\
+        After using tools and receiving results, provide a clear, helpful answer to the user.",
+        format_tools_for_prompt(db_available)
+    )
+}
+
 /// Parses custom tool requests from model output
 fn parse_tool_requests(text: &str) -> Option<Vec<CustomToolRequest>> {
     let re = Regex::new(r"<tool_request>\s*(\[.*?\])\s*</tool_request>").ok()?;
@@ -104,95 +197,79 @@ fn parse_tool_requests(text: &str) -> Option<Vec<CustomToolRequest>> {
     None
 }
 
-async fn chat_completion_stream(
-    client: &Client,
-    api_base: &str,
-    api_key: &str,
-    model_name: &str,
-    messages: &[Message],
-    ws_server: Option<&WebSocketServer>,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let response = client
-        .post(format!("{}/chat/completions", api_base))
-        .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .json(&json!({
-            "model": model_name,
-            "messages": messages,
-            "stream": true
-        }))
-        .send()
-        .await
-        .map_err(|e| format!("Failed to send request to LM Studio: {}", e))?;
-
-    let mut stream = response.bytes_stream();
-    let mut accumulated_content = String::new();
-    let mut in_tool_request = false;
-
-    use futures_util::stream::StreamExt;
-
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|e| format!("Error reading stream: {}", e))?;
-        let chunk_str = String::from_utf8_lossy(&chunk);
-
-        for line in chunk_str.split("\n\n") {
-            if line.starts_with("data: ") {
-                let json_str = line.trim_start_matches("data: ");
-                if json_str == "[DONE]" {
-                    break;
-                }
+/// Switches a model to the text tool protocol for the rest of the process
+/// and rebuilds the system prompt to describe the tools in prose.
+async fn fall_back_to_text_tools(model: &ModelEntry, messages: &Mutex<Vec<Message>>) {
+    model.disable_native_tools();
+    if let Some(system) = messages.lock().await.first_mut() {
+        system.content = build_system_prompt(db_health::is_available());
+    }
+}
 
-                match serde_json::from_str::<StreamChunk>(json_str) {
-                    Ok(chunk_data) => {
-                        if let Some(choice) = chunk_data.choices.get(0) {
-                            if let Some(content) = &choice.delta.content {
-                                accumulated_content.push_str(content);
+/// Converts <tool_request> tags a model wrote despite native tool calling
+/// into tool calls, and strips the tags from the visible content.
+fn text_requests_to_native(content: &str) -> Option<(String, Vec<NativeToolCall>)> {
+    let requests = parse_tool_requests(content).filter(|r| !r.is_empty())?;
+    let calls = requests
+        .into_iter()
+        .map(|request| NativeToolCall {
+            id: llm::next_call_id(),
+            kind: "function".to_string(),
+            function: NativeFunctionCall {
+                name: request.name,
+                arguments: serde_json::Value::Object(request.arguments).to_string(),
+            },
+        })
+        .collect();
+    let stripped = Regex::new(r"(?s)<tool_request>.*?</tool_request>")
+        .map(|re| re.replace_all(content, "").trim().to_string())
+        .unwrap_or_default();
+    Some((stripped, calls))
+}
 
-                                // Check for tool request markers
-                                if content.contains("<tool_request>") {
-                                    in_tool_request = true;
-                                }
+/// Scrubs a tool result for the model's history and reports it to the UI.
+/// Returns the text to hand back to the model, or the tool's error.
+async fn report_tool_result(name: &str, result: ToolResult, ws_server: &WebSocketServer) -> Result<String, String> {
+    // Message content is scrubbed before it reaches the history sent to the LLM.
+    let (scrubbed, pii) = privacy::scrub_tool_result(&result.result);
 
-                                // Only send visible content (not tool requests)
-                                if !in_tool_request {
-                                    if let Some(ws) = ws_server {
-                                        ws.broadcast_json(&json!({
-                                            "type": "chunk",
-                                            "content": content
-                                        })).await;
-                                    }
-                                }
+    ws_server.broadcast_json(&json!({
+        "type": "tool_result",
+        "tool": name,
+        "result": scrubbed,
+        "pii": pii,
+        "success": result.success,
+        "mutating": is_mutating_tool(name)
+    })).await;
 
-                                if content.contains("</tool_request>") {
-                                    in_tool_request = false;
-                                }
-                            }
-                        }
-                    }
-                    Err(_) => continue,
-                }
-            }
-        }
+    if result.success {
+        Ok(scrubbed.to_string())
+    } else {
+        Err(result.error.unwrap_or_else(|| "Unknown error".to_string()))
     }
-
-    Ok(accumulated_content)
 }
 
 async fn process_message(
     user_message: String,
+    activity_note: Option<String>,
     messages: Arc<Mutex<Vec<Message>>>,
-    client: &Client,
-    api_base: &str,
-    api_key: &str,
-    model_name: &str,
     ws_server: &WebSocketServer,
+    tool_context: &ToolContext,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // Add user message
+    // Add buffered activity and the user message
     {
         let mut msgs = messages.lock().await;
+        if let Some(note) = activity_note {
+            msgs.push(Message {
+                role: "system".to_string(),
+                content: note,
+                ..Default::default()
+            });
+        }
         msgs.push(Message {
             role: "user".to_string(),
             content: user_message,
+            ..Default::default()
         });
     }
 
@@ -202,141 +279,336 @@ async fn process_message(
         "role": "assistant"
     })).await;
 
+    // The model is fixed for the whole turn even if another is selected meanwhile.
+    let model = llm::active();
     let max_iterations = 5;
     for iteration in 0..max_iterations {
-        let msgs = messages.lock().await.clone();
-        drop(msgs);
-
-        let response = chat_completion_stream(
-            client,
-            api_base,
-            api_key,
-            model_name,
-            &messages.lock().await,
-            Some(ws_server),
-        ).await?;
-
-        // Check for tool requests
-        if let Some(tool_requests) = parse_tool_requests(&response) {
-            // Add assistant message
-            {
-                let mut msgs = messages.lock().await;
-                msgs.push(Message {
-                    role: "assistant".to_string(),
-                    content: response.clone(),
-                });
+        let tools = model.native_tools().then(native_tool_schemas);
+        let history = messages.lock().await.clone();
+
+        let mut sink = StreamSink::new(Some(ws_server));
+        let response = match model.provider.chat_stream(&history, tools.as_deref(), &mut sink).await {
+            Ok(response) => Some(response),
+            Err(e) if e.is::<ToolsUnsupported>() && tool_calling_mode() == "auto" => {
+                eprintln!("\x1b[1;33m! {}: {}; falling back to <tool_request> text protocol\x1b[0m", model.id, e);
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        let response = match response {
+            Some(response) => response,
+            None => {
+                fall_back_to_text_tools(&model, &messages).await;
+                let history = messages.lock().await.clone();
+                sink = StreamSink::new(Some(ws_server));
+                model.provider.chat_stream(&history, None, &mut sink).await?
             }
+        };
+        sink.finish().await;
 
-            // Hide any streaming text and show tool execution UI
+        let finished = if model.native_tools() {
+            run_native_tool_calls(response, &messages, ws_server, tool_context).await
+        } else {
+            run_text_tool_requests(response.content, &messages, ws_server, tool_context).await
+        };
+        if finished {
+            break;
+        }
+
+        if iteration < max_iterations - 1 {
             ws_server.broadcast_json(&json!({
-                "type": "tool_start",
-                "tools": tool_requests.iter().map(|t| t.name.clone()).collect::<Vec<_>>()
+                "type": "start",
+                "role": "assistant"
             })).await;
+        }
+    }
 
-            let mut tool_results = Vec::new();
+    // Send end of message
+    ws_server.broadcast_json(&json!({
+        "type": "end"
+    })).await;
 
-            for tool_req in tool_requests {
-                let tool_call = ToolCall {
-                    name: tool_req.name.clone(),
-                    arguments: serde_json::Value::Object(tool_req.arguments.clone()),
-                };
+    Ok(())
+}
 
-                let result = execute_tool(&tool_call);
+/// Executes the native tool calls of a response, answering each with a
+/// `tool` message. Returns true when the response was the final answer.
+async fn run_native_tool_calls(
+    response: ChatResponse,
+    messages: &Mutex<Vec<Message>>,
+    ws_server: &WebSocketServer,
+    tool_context: &ToolContext,
+) -> bool {
+    let converted = if response.tool_calls.is_empty() {
+        text_requests_to_native(&response.content)
+    } else {
+        Some((response.content.clone(), response.tool_calls))
+    };
+    let (content, calls) = match converted {
+        Some(converted) => converted,
+        None => {
+            // No tool calls, save final response
+            messages.lock().await.push(Message {
+                role: "assistant".to_string(),
+                content: response.content,
+                ..Default::default()
+            });
+            return true;
+        }
+    };
 
-                let result_msg = if result.success {
-                    format!("Tool '{}' returned: {}", tool_req.name, result.result)
-                } else {
-                    format!("Tool '{}' error: {}", tool_req.name, result.error.as_ref().unwrap())
-                };
+    messages.lock().await.push(Message {
+        role: "assistant".to_string(),
+        content,
+        tool_calls: Some(calls.clone()),
+        ..Default::default()
+    });
 
-                tool_results.push(result_msg.clone());
+    // Hide any streaming text and show tool execution UI
+    ws_server.broadcast_json(&json!({
+        "type": "tool_start",
+        "tools": calls.iter().map(|c| c.function.name.clone()).collect::<Vec<_>>()
+    })).await;
 
-                ws_server.broadcast_json(&json!({
-                    "type": "tool_result",
-                    "tool": tool_req.name,
-                    "result": result.result,
-                    "success": result.success
-                })).await;
+    for call in calls {
+        let name = call.function.name;
+        let arguments = if call.function.arguments.trim().is_empty() { "{}" } else { call.function.arguments.as_str() };
+        let result = match serde_json::from_str::<serde_json::Value>(arguments) {
+            Ok(mut arguments @ serde_json::Value::Object(_)) => {
+                if is_mutating_tool(&name) {
+                    privacy::rehydrate_arguments(&mut arguments);
+                }
+                execute_tool(&ToolCall { name: name.clone(), arguments }, tool_context).await
             }
+            Ok(_) => ToolResult {
+                success: false,
+                result: json!(null),
+                error: Some("Arguments must be a JSON object".to_string()),
+            },
+            Err(e) => ToolResult {
+                success: false,
+                result: json!(null),
+                error: Some(format!("Arguments are not valid JSON: {}", e)),
+            },
+        };
+
+        let content = match report_tool_result(&name, result, ws_server).await {
+            Ok(result) => result,
+            Err(error) => json!({ "error": error }).to_string(),
+        };
+        messages.lock().await.push(Message {
+            role: "tool".to_string(),
+            content,
+            tool_call_id: Some(call.id),
+            ..Default::default()
+        });
+    }
 
-            // Add tool results
-            {
-                let mut msgs = messages.lock().await;
-                msgs.push(Message {
-                    role: "tool".to_string(),
-                    content: format!("<tool_results>\n{}\n</tool_results>", tool_results.join("\n")),
-                });
-            }
+    false
+}
 
-            if iteration < max_iterations - 1 {
-                ws_server.broadcast_json(&json!({
-                    "type": "start",
-                    "role": "assistant"
-                })).await;
-            }
-        } else {
+/// Executes <tool_request> tags of a text-protocol response and adds their
+/// results as one message. Returns true when the response was the final answer.
+async fn run_text_tool_requests(
+    response: String,
+    messages: &Mutex<Vec<Message>>,
+    ws_server: &WebSocketServer,
+    tool_context: &ToolContext,
+) -> bool {
+    let tool_requests = match parse_tool_requests(&response) {
+        Some(tool_requests) => tool_requests,
+        None => {
             // No tool requests, save final response
-            {
-                let mut msgs = messages.lock().await;
-                msgs.push(Message {
-                    role: "assistant".to_string(),
-                    content: response,
-                });
-            }
-            break;
+            messages.lock().await.push(Message {
+                role: "assistant".to_string(),
+                content: response,
+                ..Default::default()
+            });
+            return true;
         }
-    }
+    };
 
-    // Send end of message
+    messages.lock().await.push(Message {
+        role: "assistant".to_string(),
+        content: response,
+        ..Default::default()
+    });
+
+    // Hide any streaming text and show tool execution UI
     ws_server.broadcast_json(&json!({
-        "type": "end"
+        "type": "tool_start",
+        "tools": tool_requests.iter().map(|t| t.name.clone()).collect::<Vec<_>>()
     })).await;
 
+    let mut tool_results = Vec::new();
+    for tool_req in tool_requests {
+        let mut tool_call = ToolCall {
+            name: tool_req.name.clone(),
+            arguments: serde_json::Value::Object(tool_req.arguments),
+        };
+        if is_mutating_tool(&tool_call.name) {
+            privacy::rehydrate_arguments(&mut tool_call.arguments);
+        }
+
+        let result = execute_tool(&tool_call, tool_context).await;
+        tool_results.push(match report_tool_result(&tool_req.name, result, ws_server).await {
+            Ok(result) => format!("Tool '{}' returned: {}", tool_req.name, result),
+            Err(error) => format!("Tool '{}' error: {}", tool_req.name, error),
+        });
+    }
+
+    messages.lock().await.push(Message {
+        role: "tool".to_string(),
+        content: format!("<tool_results>\n{}\n</tool_results>", tool_results.join("\n")),
+        ..Default::default()
+    });
+
+    false
+}
+
+/// Checks that a username can be bound to the session. Switching users from
+/// the UI needs the operator token in ORBIT_SESSION_TOKEN; without it the
+/// session stays bound to ORBIT_USER.
+fn validate_session_user(username: &str, token: Option<&str>) -> Result<(), String> {
+    let expected = match env::var("ORBIT_SESSION_TOKEN").ok().filter(|t| !t.is_empty()) {
+        Some(expected) => expected,
+        None => return Err("Switching users is disabled; set ORBIT_USER or ORBIT_SESSION_TOKEN".to_string()),
+    };
+    if !token.is_some_and(|token| constant_time_eq(token.as_bytes(), expected.as_bytes())) {
+        return Err("Invalid session token".to_string());
+    }
+    if username.is_empty() {
+        return Err("Username is required".to_string());
+    }
+
+    let db = Database::new().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    match db.find_user_by_username(username) {
+        Ok(Some(user)) if user.is_active => Ok(()),
+        Ok(Some(_)) => Err(format!("User '{}' is inactive", username)),
+        Ok(None) => Err(format!("User '{}' not found", username)),
+        Err(e) => Err(format!("Database error: {}", e)),
+    }
+}
+
+/// `chat-IBM migrate`: applies the schema changes Orbit needs.
+fn run_migrations() -> Result<(), Box<dyn std::error::Error>> {
+    let applied = Database::new()?.migrate()?;
+    if applied.is_empty() {
+        println!("Database schema is already up to date.");
+    } else {
+        for name in applied {
+            println!("Applied {}", name);
+        }
+    }
     Ok(())
 }
 
+/// Compares secrets without stopping at the first differing byte.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("import") {
+        return tokio::task::block_in_place(|| import::run_cli(&args[2..]));
+    }
+    if args.get(1).map(String::as_str) == Some("purge") {
+        return tokio::task::block_in_place(|| retention::run_cli(&args[2..]));
+    }
+    if args.get(1).map(String::as_str) == Some("migrate") {
+        return tokio::task::block_in_place(run_migrations);
+    }
+
     let api_base = env::var("LM_STUDIO_API_BASE").unwrap_or_else(|_| "http://localhost:1234/v1".to_string());
     let api_key = env::var("LM_STUDIO_API_KEY").unwrap_or_else(|_| "not-needed".to_string());
-    let model_name = env::var("LM_STUDIO_MODEL").unwrap_or_else(|_| "ibm/granite-3.1-8b".to_string());
+    let session_user = env::var("ORBIT_USER").ok().filter(|u| !u.trim().is_empty());
     let ws_port: u16 = env::var("WS_PORT").unwrap_or_else(|_| "8080".to_string()).parse().unwrap_or(8080);
 
-    let tools_description = format_tools_for_prompt();
+    let tool_calling = tool_calling_mode();
+    let model = llm::init_from_env(tool_calling != "text")?;
 
     let messages = Arc::new(Mutex::new(vec![Message {
         role: "system".to_string(),
-        content: format!(
-            "You are Orbit, an advanced AI assistant with access to mathematical tools AND database tools.\n\
-            {}\n\n\
-            DATABASE CONTEXT:\n\
-            You have access to a chat application database with users, conversations, and messages.\n\
-            You can:\n\
-            - Search and summarize conversations\n\
-            - Send messages as any user\n\
-            - Find users and their conversations\n\
-            - Get conversation statistics\n\
-            \n\
-            When users ask about conversations, users, or messages, use the appropriate database tools.\n\
-            When users ask mathematical questions, use the mathematical tools.\n\
-            After using tools and receiving results, provide a clear, helpful answer to the user.",
-            tools_description
-        ),
+        content: build_system_prompt(true),
+        ..Default::default()
     }]));
 
-    let client = Client::new();
+    match embeddings::init_from_env(&api_base, &api_key) {
+        Some(service) => {
+            service.spawn_sync();
+            println!("\x1b[1;32m✓ Semantic search enabled ({} messages indexed)\x1b[0m", service.indexed_messages());
+        }
+        None => println!("\x1b[1;33m! ORBIT_EMBEDDINGS_MODEL not set; semantic search is disabled\x1b[0m"),
+    }
 
     // Start WebSocket server
     let ws_server = WebSocketServer::new(ws_port).await?;
     println!("\x1b[1;32m✓ WebSocket server started on ws://localhost:{}\x1b[0m", ws_port);
     println!("\x1b[1;32m✓ Web UI available at http://localhost:{}\x1b[0m", ws_port);
+    match &session_user {
+        Some(username) => println!("\x1b[1;32m✓ Session acting as tunispace user '{}'\x1b[0m", username),
+        None => println!("\x1b[1;33m! No ORBIT_USER set; database tools are unavailable until a user is selected in the UI with ORBIT_SESSION_TOKEN\x1b[0m"),
+    }
+
+    // Live feed of subscribed conversations; new messages are also buffered
+    // as notes that enter the assistant's history with the next turn.
+    let watcher = ConversationWatcher::new();
+    let (activity_tx, mut activity_rx) = tokio::sync::mpsc::unbounded_channel();
+    watcher.spawn(ws_server.clone(), activity_tx);
+
+    // Database health: mark the database tools unavailable in the prompt
+    // during an outage so the model stops calling them.
+    let (db_status_tx, mut db_status_rx) = tokio::sync::mpsc::unbounded_channel();
+    db_health::spawn(ws_server.clone(), db_status_tx);
+
+    println!("\x1b[1;32m✓ Model {} ({} configured)\x1b[0m", model.id, llm::configured_count());
+    match tool_calling.as_str() {
+        "text" => println!("\x1b[1;33m! Tool calling: <tool_request> text protocol\x1b[0m"),
+        mode => println!("\x1b[1;32m✓ Tool calling: native ({})\x1b[0m", mode),
+    }
+
+    match tokio::task::block_in_place(|| Database::new().and_then(|db| db.pending_migrations())) {
+        Ok(pending) if pending.is_empty() => println!("\x1b[1;32m✓ Database schema is up to date\x1b[0m"),
+        Ok(pending) => println!(
+            "\x1b[1;31m✗ Database schema is missing {}; run `chat-IBM migrate` before using the database tools\x1b[0m",
+            pending.join(", ")
+        ),
+        Err(e) => println!("\x1b[1;33m! Could not check the database schema: {}\x1b[0m", e),
+    }
+
+    match retention::spawn_scheduler() {
+        Some(interval) => println!("\x1b[1;32m✓ Retention purge scheduled every {}h\x1b[0m", interval.as_secs() / 3600),
+        None => println!("\x1b[1;33m! ORBIT_RETENTION_INTERVAL_HOURS not set; retention rules only run via `purge`\x1b[0m"),
+    }
+
+    let prompt_history = messages.clone();
+    tokio::spawn(async move {
+        while let Some(available) = db_status_rx.recv().await {
+            if let Some(system) = prompt_history.lock().await.first_mut() {
+                system.content = build_system_prompt(available);
+            }
+        }
+    });
+
+    let activity_notes = Arc::new(Mutex::new(ActivityNotes::default()));
+    let pending_notes = activity_notes.clone();
+    tokio::spawn(async move {
+        while let Some(activity) = activity_rx.recv().await {
+            let content: String = privacy::scrub_text(&activity.message.content).chars().take(300).collect();
+            pending_notes.lock().await.push(format!(
+                "New message #{} in watched conversation {} from {} at {}: {}",
+                activity.message.id, activity.message.conversation_id, activity.author, activity.message.created_at, content
+            ));
+        }
+    });
 
     let messages_clone = messages.clone();
-    let client_clone = client.clone();
-    let api_base_clone = api_base.clone();
-    let api_key_clone = api_key.clone();
-    let model_name_clone = model_name.clone();
     let ws_server_clone = ws_server.clone();
+    let mut tool_context = ToolContext {
+        acting_username: session_user.clone(),
+    };
 
     // Handle incoming WebSocket messages
     tokio::spawn(async move {
@@ -344,16 +616,100 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             if let Some(msg) = ws_server_clone.receive_message().await {
                 match msg {
                     ClientMessage::SendMessage { content } => {
+                        let notes = activity_notes.lock().await.flush();
                         let _ = process_message(
                             content,
+                            notes,
                             messages_clone.clone(),
-                            &client_clone,
-                            &api_base_clone,
-                            &api_key_clone,
-                            &model_name_clone,
                             &ws_server_clone,
+                            &tool_context,
                         ).await;
                     }
+                    ClientMessage::SetUser { username, token } => {
+                        let username = username.trim().to_string();
+                        match validate_session_user(&username, token.as_deref()) {
+                            Ok(()) => {
+                                tool_context.acting_username = Some(username.clone());
+                                // Subscriptions were authorized for the previous user.
+                                watcher.clear().await;
+                                ws_server_clone.broadcast_json(&json!({
+                                    "type": "subscriptions",
+                                    "conversation_ids": Vec::<i32>::new()
+                                })).await;
+                                messages_clone.lock().await.push(Message {
+                                    role: "system".to_string(),
+                                    content: format!("This session now acts as tunispace user '{}'.", username),
+                                    ..Default::default()
+                                });
+                                ws_server_clone.broadcast_json(&json!({
+                                    "type": "session_user",
+                                    "username": username,
+                                    "success": true
+                                })).await;
+                            }
+                            Err(error) => {
+                                ws_server_clone.broadcast_json(&json!({
+                                    "type": "session_user",
+                                    "username": tool_context.acting_username,
+                                    "success": false,
+                                    "error": error
+                                })).await;
+                            }
+                        }
+                    }
+                    ClientMessage::SetModel { model } => {
+                        match llm::select(&model) {
+                            Ok(entry) => {
+                                // Native and text tool calling need different prompts.
+                                if let Some(system) = messages_clone.lock().await.first_mut() {
+                                    system.content = build_system_prompt(db_health::is_available());
+                                }
+                                println!("\x1b[1;32m✓ Switched to model {}\x1b[0m", entry.id);
+                                if let Some(event) = llm::current_event() {
+                                    ws_server_clone.broadcast_json(&event).await;
+                                }
+                            }
+                            Err(error) => {
+                                let mut event = llm::current_event().unwrap_or_else(|| json!({ "type": "models" }));
+                                event["error"] = json!(error);
+                                ws_server_clone.broadcast_json(&event).await;
+                            }
+                        }
+                    }
+                    ClientMessage::UndoMessage { message_id } => {
+                        let result = undo_message(message_id, &tool_context);
+                        if result.success {
+                            messages_clone.lock().await.push(Message {
+                                role: "system".to_string(),
+                                content: format!("The user undid message #{} sent earlier with send_message; it has been deleted.", message_id),
+                                ..Default::default()
+                            });
+                        }
+                        ws_server_clone.broadcast_json(&json!({
+                            "type": "undo_result",
+                            "message_id": message_id,
+                            "success": result.success,
+                            "error": result.error
+                        })).await;
+                    }
+                    ClientMessage::SubscribeConversation { conversation_id } => {
+                        let outcome = match authorize_watch(conversation_id, &tool_context) {
+                            Ok(()) => watcher.subscribe(conversation_id).await,
+                            Err(error) => Err(error),
+                        };
+                        ws_server_clone.broadcast_json(&json!({
+                            "type": "subscriptions",
+                            "conversation_ids": watcher.subscribed().await,
+                            "error": outcome.err()
+                        })).await;
+                    }
+                    ClientMessage::UnsubscribeConversation { conversation_id } => {
+                        watcher.unsubscribe(conversation_id).await;
+                        ws_server_clone.broadcast_json(&json!({
+                            "type": "subscriptions",
+                            "conversation_ids": watcher.subscribed().await
+                        })).await;
+                    }
                 }
             }
             tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
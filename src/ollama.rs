@@ -0,0 +1,245 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use crate::llm::{error_body, next_call_id, ChatResponse, LlmProvider, Message, NativeFunctionCall, NativeToolCall, StreamSink, ToolsUnsupported};
+
+/// One line of Ollama's newline-delimited `/api/chat` stream.
+#[derive(Deserialize, Debug)]
+struct ChatLine {
+    message: Option<LineMessage>,
+    #[serde(default)]
+    done: bool,
+    error: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LineMessage {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tool_calls: Vec<OllamaToolCall>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaToolCall {
+    function: OllamaFunction,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaFunction {
+    name: String,
+    /// Ollama sends arguments as an object rather than a JSON string.
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// Ollama's native chat API.
+pub struct OllamaProvider {
+    client: Client,
+    base_url: String,
+    model: String,
+}
+
+impl OllamaProvider {
+    pub fn new(client: Client, base_url: &str, model: &str) -> Self {
+        OllamaProvider {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+        }
+    }
+}
+
+/// Converts the conversation to Ollama's format: tool call arguments are
+/// objects and tool results name their tool instead of a call id.
+fn to_ollama_messages(messages: &[Message]) -> Vec<Value> {
+    let mut tool_names: HashMap<&str, &str> = HashMap::new();
+    let mut converted = Vec::new();
+
+    for message in messages {
+        if let Some(calls) = &message.tool_calls {
+            for call in calls {
+                tool_names.insert(&call.id, &call.function.name);
+            }
+            converted.push(json!({
+                "role": message.role,
+                "content": message.content,
+                "tool_calls": calls.iter().map(|call| json!({
+                    "function": {
+                        "name": call.function.name,
+                        "arguments": serde_json::from_str::<Value>(&call.function.arguments).unwrap_or_else(|_| json!({}))
+                    }
+                })).collect::<Vec<_>>()
+            }));
+        } else if let Some(call_id) = &message.tool_call_id {
+            converted.push(json!({
+                "role": "tool",
+                "content": message.content,
+                "tool_name": tool_names.get(call_id.as_str()).copied().unwrap_or_default()
+            }));
+        } else {
+            converted.push(json!({ "role": message.role, "content": message.content }));
+        }
+    }
+
+    converted
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn kind(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: Option<&[Value]>,
+        sink: &mut StreamSink<'_>,
+    ) -> Result<ChatResponse, Box<dyn std::error::Error>> {
+        let mut body = json!({
+            "model": self.model,
+            "messages": to_ollama_messages(messages),
+            "stream": true
+        });
+        if let Some(tools) = tools.filter(|t| !t.is_empty()) {
+            body["tools"] = json!(tools);
+        }
+
+        let response = self.client
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to send request to Ollama: {}", e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let text = error_body(response, 500).await;
+            if tools.is_some() && text.contains("does not support tools") {
+                return Err(ToolsUnsupported(text).into());
+            }
+            return Err(format!("Ollama returned {}: {}", status, text).into());
+        }
+
+        let mut stream = response.bytes_stream();
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut content = String::new();
+        let mut tool_calls = Vec::new();
+        let mut done = false;
+
+        use futures_util::stream::StreamExt;
+
+        'stream: while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| format!("Error reading stream: {}", e))?;
+            buffer.extend_from_slice(&chunk);
+
+            while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8(line).map_err(|_| "Malformed stream from Ollama: invalid UTF-8")?;
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+
+                let parsed: ChatLine = serde_json::from_str(line)
+                    .map_err(|e| format!("Malformed stream line from Ollama ({}): {}", e, line.chars().take(200).collect::<String>()))?;
+                if let Some(error) = parsed.error {
+                    return Err(format!("Ollama reported an error: {}", error).into());
+                }
+                if let Some(message) = parsed.message {
+                    if !message.content.is_empty() {
+                        content.push_str(&message.content);
+                        sink.push(&message.content).await;
+                    }
+                    // Tool calls arrive whole, without ids.
+                    for call in message.tool_calls {
+                        tool_calls.push(NativeToolCall {
+                            id: next_call_id(),
+                            kind: "function".to_string(),
+                            function: NativeFunctionCall {
+                                name: call.function.name,
+                                arguments: match call.function.arguments {
+                                    Value::Null => "{}".to_string(),
+                                    arguments => arguments.to_string(),
+                                },
+                            },
+                        });
+                    }
+                }
+                if parsed.done {
+                    done = true;
+                    break 'stream;
+                }
+            }
+        }
+
+        if !done {
+            let partial = String::from_utf8_lossy(&buffer);
+            return Err(format!("Ollama stream ended before completion: {}", partial.trim()).into());
+        }
+
+        Ok(ChatResponse { content, tool_calls })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> Message {
+        Message { role: role.to_string(), content: content.to_string(), ..Default::default() }
+    }
+
+    fn call(id: &str, name: &str, arguments: &str) -> NativeToolCall {
+        NativeToolCall {
+            id: id.to_string(),
+            kind: "function".to_string(),
+            function: NativeFunctionCall { name: name.to_string(), arguments: arguments.to_string() },
+        }
+    }
+
+    #[test]
+    fn converts_tool_calls_and_names_tool_results() {
+        let converted = to_ollama_messages(&[
+            message("system", "prompt"),
+            message("user", "add"),
+            Message { tool_calls: Some(vec![call("call_1", "add", r#"{"a":1,"b":2}"#), call("call_2", "sqrt", "")]), ..message("assistant", "") },
+            Message { tool_call_id: Some("call_2".to_string()), ..message("tool", "2") },
+            Message { tool_call_id: Some("call_1".to_string()), ..message("tool", "3") },
+            Message { tool_call_id: Some("unknown".to_string()), ..message("tool", "?") },
+        ]);
+
+        assert_eq!(converted[0], json!({ "role": "system", "content": "prompt" }));
+        assert_eq!(converted[2], json!({
+            "role": "assistant",
+            "content": "",
+            "tool_calls": [
+                { "function": { "name": "add", "arguments": { "a": 1, "b": 2 } } },
+                { "function": { "name": "sqrt", "arguments": {} } }
+            ]
+        }));
+        assert_eq!(converted[3], json!({ "role": "tool", "content": "2", "tool_name": "sqrt" }));
+        assert_eq!(converted[4], json!({ "role": "tool", "content": "3", "tool_name": "add" }));
+        assert_eq!(converted[5]["tool_name"], "");
+    }
+
+    #[test]
+    fn tool_names_follow_ids_across_turns() {
+        let converted = to_ollama_messages(&[
+            Message { tool_calls: Some(vec![call("call_orbit_1", "add", "{}")]), ..message("assistant", "") },
+            Message { tool_call_id: Some("call_orbit_1".to_string()), ..message("tool", "3") },
+            Message { tool_calls: Some(vec![call("call_orbit_2", "sqrt", "{}")]), ..message("assistant", "") },
+            Message { tool_call_id: Some("call_orbit_2".to_string()), ..message("tool", "2") },
+        ]);
+
+        assert_eq!(converted[1]["tool_name"], "add");
+        assert_eq!(converted[3]["tool_name"], "sqrt");
+    }
+}
@@ -0,0 +1,464 @@
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::sync::{Mutex, OnceLock};
+use crate::timestamp::Timestamp;
+
+/// How a single user field is exposed in tool results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldPolicy {
+    Show,
+    Mask,
+    Hide,
+}
+
+impl FieldPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "show" => Some(FieldPolicy::Show),
+            "mask" => Some(FieldPolicy::Mask),
+            "hide" => Some(FieldPolicy::Hide),
+            _ => None,
+        }
+    }
+
+    fn from_env(key: &str, default: FieldPolicy) -> Self {
+        env::var(key)
+            .ok()
+            .and_then(|v| FieldPolicy::parse(&v))
+            .unwrap_or(default)
+    }
+}
+
+/// Field-level redaction applied when users are projected for the model or
+/// the web UI. Passwords are never part of the projection.
+#[derive(Debug, Clone, Copy)]
+pub struct UserRedactionPolicy {
+    /// `mask` keeps the first character and the domain: `j***@example.com`.
+    pub email: FieldPolicy,
+    /// `mask` keeps only the account creation date.
+    pub created_at: FieldPolicy,
+}
+
+impl UserRedactionPolicy {
+    /// Reads `ORBIT_REDACT_EMAIL` and `ORBIT_REDACT_CREATED_AT` (show|mask|hide).
+    pub fn from_env() -> Self {
+        UserRedactionPolicy {
+            email: FieldPolicy::from_env("ORBIT_REDACT_EMAIL", FieldPolicy::Mask),
+            created_at: FieldPolicy::from_env("ORBIT_REDACT_CREATED_AT", FieldPolicy::Show),
+        }
+    }
+
+    pub fn apply_email(&self, email: &str) -> Option<String> {
+        match self.email {
+            FieldPolicy::Show => Some(email.to_string()),
+            FieldPolicy::Mask => Some(mask_email(email)),
+            FieldPolicy::Hide => None,
+        }
+    }
+
+    pub fn apply_created_at(&self, created_at: &Timestamp) -> Option<String> {
+        match self.created_at {
+            FieldPolicy::Show => Some(created_at.to_rfc3339()),
+            FieldPolicy::Mask => Some(created_at.date_string()),
+            FieldPolicy::Hide => None,
+        }
+    }
+}
+
+pub fn mask_email(email: &str) -> String {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            let first: String = local.chars().take(1).collect();
+            format!("{}***@{}", first, domain)
+        }
+        None => "***".to_string(),
+    }
+}
+
+// ===== PII SCRUBBING =====
+
+/// Keys whose string values hold user-written text. Only these are
+/// scrubbed, so ids, timestamps and usernames stay intact.
+const CONTENT_KEYS: [&str; 7] = ["content", "snippet", "summary", "previous_content", "current_content", "text", "title"];
+
+static SCRUBBER: OnceLock<PiiScrubber> = OnceLock::new();
+static VAULT: OnceLock<Mutex<PiiVault>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PiiKind {
+    Email,
+    Card,
+    Phone,
+    /// A term from the configured dictionary.
+    Term,
+}
+
+impl PiiKind {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "email" => Some(PiiKind::Email),
+            "card" => Some(PiiKind::Card),
+            "phone" => Some(PiiKind::Phone),
+            "term" | "dictionary" => Some(PiiKind::Term),
+            _ => None,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            PiiKind::Email => "EMAIL",
+            PiiKind::Card => "CARD",
+            PiiKind::Phone => "PHONE",
+            PiiKind::Term => "TERM",
+        }
+    }
+}
+
+/// Maps detected values to placeholders such as `[EMAIL_1]`. The same value
+/// always gets the same placeholder for the lifetime of the process, so the
+/// model can still tell people and numbers apart.
+#[derive(Debug, Default)]
+pub struct PiiVault {
+    placeholders: HashMap<(PiiKind, String), String>,
+    /// Placeholder to the value it was first issued for.
+    originals: HashMap<String, String>,
+    counters: HashMap<PiiKind, usize>,
+}
+
+impl PiiVault {
+    fn placeholder(&mut self, kind: PiiKind, value: &str) -> String {
+        let key = (kind, value.to_lowercase());
+        if let Some(placeholder) = self.placeholders.get(&key) {
+            return placeholder.clone();
+        }
+
+        let counter = self.counters.entry(kind).or_insert(0);
+        *counter += 1;
+        let placeholder = format!("[{}_{}]", kind.label(), counter);
+        self.placeholders.insert(key, placeholder.clone());
+        self.originals.insert(placeholder.clone(), value.to_string());
+        placeholder
+    }
+
+    /// Replaces known placeholders in `text` with their original values.
+    /// Unknown placeholders are left as written.
+    pub fn rehydrate_text(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (placeholder, original) in &self.originals {
+            if text.contains(placeholder.as_str()) {
+                text = text.replace(placeholder.as_str(), original);
+            }
+        }
+        text
+    }
+
+    /// Re-hydrates every string in a JSON value, at any depth.
+    pub fn rehydrate_json(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::String(text) => *text = self.rehydrate_text(text),
+            serde_json::Value::Array(items) => items.iter_mut().for_each(|item| self.rehydrate_json(item)),
+            serde_json::Value::Object(map) => map.values_mut().for_each(|field| self.rehydrate_json(field)),
+            _ => {}
+        }
+    }
+}
+
+/// Pattern and dictionary based PII detection.
+///
+/// Configured with `ORBIT_PII_SCRUB` (comma list of email, card, phone, term;
+/// `off` disables), `ORBIT_PII_DICTIONARY` (file with one term per line) and
+/// `ORBIT_PII_REHYDRATE_UI` (send original values to the local UI, default on).
+pub struct PiiScrubber {
+    kinds: Vec<PiiKind>,
+    email: Regex,
+    card: Regex,
+    phone: Regex,
+    date_like: Regex,
+    /// The `[#id timestamp] author: ` prefix of conversation summary lines.
+    summary_line: Regex,
+    dictionary: Option<Regex>,
+    pub rehydrate_ui: bool,
+}
+
+impl PiiScrubber {
+    pub fn from_env() -> Self {
+        let kinds = match env::var("ORBIT_PII_SCRUB") {
+            Ok(value) if matches!(value.trim().to_lowercase().as_str(), "off" | "none" | "false" | "0") => Vec::new(),
+            Ok(value) if !value.trim().is_empty() => value.split(',').filter_map(PiiKind::parse).collect(),
+            _ => vec![PiiKind::Email, PiiKind::Card, PiiKind::Phone, PiiKind::Term],
+        };
+
+        let dictionary = env::var("ORBIT_PII_DICTIONARY").ok().and_then(|path| {
+            match std::fs::read_to_string(&path) {
+                Ok(text) => dictionary_regex(&text),
+                Err(e) => {
+                    eprintln!("Could not read ORBIT_PII_DICTIONARY '{}': {}", path, e);
+                    None
+                }
+            }
+        });
+
+        let rehydrate_ui = !matches!(
+            env::var("ORBIT_PII_REHYDRATE_UI").unwrap_or_default().trim().to_lowercase().as_str(),
+            "0" | "false" | "off" | "no"
+        );
+        PiiScrubber::new(kinds, dictionary, rehydrate_ui)
+    }
+
+    pub fn new(kinds: Vec<PiiKind>, dictionary: Option<Regex>, rehydrate_ui: bool) -> Self {
+        PiiScrubber {
+            kinds,
+            email: Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").unwrap(),
+            card: Regex::new(r"\b(?:\d[ -]?){12,18}\d\b").unwrap(),
+            phone: Regex::new(r"\+?\(?\d[\d\s().-]{7,}\d").unwrap(),
+            date_like: Regex::new(r"\d{4}-\d{2}-\d{2}").unwrap(),
+            summary_line: Regex::new(r"^\[#\d+ [^\]]*\] [^:\n]*: ").unwrap(),
+            dictionary,
+            rehydrate_ui,
+        }
+    }
+
+    pub fn global() -> &'static PiiScrubber {
+        SCRUBBER.get_or_init(PiiScrubber::from_env)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.kinds.is_empty()
+    }
+
+    /// Replaces detected values in `text`, recording the placeholders used.
+    pub fn scrub_text(&self, text: &str, vault: &mut PiiVault, used: &mut BTreeMap<String, String>) -> String {
+        let mut text = text.to_string();
+
+        for kind in &self.kinds {
+            let re = match kind {
+                PiiKind::Email => &self.email,
+                PiiKind::Card => &self.card,
+                PiiKind::Phone => &self.phone,
+                PiiKind::Term => match &self.dictionary {
+                    Some(re) => re,
+                    None => continue,
+                },
+            };
+
+            let source = text;
+            text = re
+                .replace_all(&source, |caps: &regex::Captures| {
+                    let value = &caps[0];
+                    let before = source[..caps.get(0).map_or(0, |m| m.start())].chars().next_back();
+                    if !self.accepts(*kind, value, before) {
+                        return value.to_string();
+                    }
+                    let placeholder = vault.placeholder(*kind, value);
+                    used.insert(placeholder.clone(), value.to_string());
+                    placeholder
+                })
+                .into_owned();
+        }
+
+        text
+    }
+
+    /// Filters pattern matches that are not really PII. `before` is the
+    /// character preceding the match; a phone number never starts in the
+    /// middle of a word, an id like `#12` or a date.
+    fn accepts(&self, kind: PiiKind, value: &str, before: Option<char>) -> bool {
+        let digits = value.chars().filter(char::is_ascii_digit).count();
+        match kind {
+            PiiKind::Card => (13..=19).contains(&digits) && luhn_valid(value),
+            PiiKind::Phone => {
+                (9..=15).contains(&digits)
+                    && !before.is_some_and(|c| c.is_alphanumeric() || matches!(c, '#' | '-' | '/' | ':' | '.'))
+                    && !self.date_like.is_match(value)
+            }
+            PiiKind::Email | PiiKind::Term => true,
+        }
+    }
+
+    /// Scrubs a summary line by line. Generated summaries prefix each message
+    /// with its id, timestamp and author; only the message text after that
+    /// prefix is scrubbed.
+    fn scrub_summary(&self, text: &str, vault: &mut PiiVault, used: &mut BTreeMap<String, String>) -> String {
+        text.split_inclusive('\n')
+            .map(|line| {
+                let prefix = self.summary_line.find(line).map_or(0, |m| m.end());
+                format!("{}{}", &line[..prefix], self.scrub_text(&line[prefix..], vault, used))
+            })
+            .collect()
+    }
+
+    /// Scrubs the content fields of a tool result, at any depth.
+    pub fn scrub_json(&self, value: &mut serde_json::Value, vault: &mut PiiVault, used: &mut BTreeMap<String, String>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, field) in map.iter_mut() {
+                    match field {
+                        serde_json::Value::String(text) if key == "summary" => {
+                            *text = self.scrub_summary(text, vault, used);
+                        }
+                        serde_json::Value::String(text) if CONTENT_KEYS.contains(&key.as_str()) => {
+                            *text = self.scrub_text(text, vault, used);
+                        }
+                        _ => self.scrub_json(field, vault, used),
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    self.scrub_json(item, vault, used);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn vault() -> &'static Mutex<PiiVault> {
+    VAULT.get_or_init(|| Mutex::new(PiiVault::default()))
+}
+
+/// Scrubs a tool result before it enters the message history. Returns the
+/// scrubbed value and, when UI re-hydration is on, the placeholders it uses.
+pub fn scrub_tool_result(value: &serde_json::Value) -> (serde_json::Value, BTreeMap<String, String>) {
+    let scrubber = PiiScrubber::global();
+    let mut scrubbed = value.clone();
+    let mut used = BTreeMap::new();
+
+    if scrubber.is_enabled() {
+        scrubber.scrub_json(&mut scrubbed, &mut vault().lock().unwrap(), &mut used);
+    }
+    if !scrubber.rehydrate_ui {
+        used.clear();
+    }
+
+    (scrubbed, used)
+}
+
+/// Restores the original values behind placeholders in tool arguments, so
+/// that writes (messages, edits, summaries) store what the placeholders
+/// stand for rather than the placeholders themselves.
+pub fn rehydrate_arguments(arguments: &mut serde_json::Value) {
+    if PiiScrubber::global().is_enabled() {
+        vault().lock().unwrap().rehydrate_json(arguments);
+    }
+}
+
+/// Scrubs free text (e.g. live feed notes) with the shared vault.
+pub fn scrub_text(text: &str) -> String {
+    let scrubber = PiiScrubber::global();
+    if !scrubber.is_enabled() {
+        return text.to_string();
+    }
+    scrubber.scrub_text(text, &mut vault().lock().unwrap(), &mut BTreeMap::new())
+}
+
+/// Whole-word, case-insensitive alternation of dictionary terms. Blank lines
+/// and lines starting with `#` are ignored.
+fn dictionary_regex(text: &str) -> Option<Regex> {
+    let mut terms: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    if terms.is_empty() {
+        return None;
+    }
+    // Longest first so "Jane Doe" wins over "Jane".
+    terms.sort_by_key(|term| std::cmp::Reverse(term.len()));
+    let alternation = terms.iter().map(|t| regex::escape(t)).collect::<Vec<_>>().join("|");
+    Regex::new(&format!(r"(?i)\b(?:{})\b", alternation)).ok()
+}
+
+fn luhn_valid(value: &str) -> bool {
+    let digits: Vec<u32> = value.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| {
+            if i % 2 == 1 {
+                let doubled = d * 2;
+                if doubled > 9 { doubled - 9 } else { doubled }
+            } else {
+                *d
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrubber() -> PiiScrubber {
+        PiiScrubber::new(vec![PiiKind::Email, PiiKind::Card, PiiKind::Phone], None, true)
+    }
+
+    fn scrub(text: &str) -> String {
+        scrubber().scrub_text(text, &mut PiiVault::default(), &mut BTreeMap::new())
+    }
+
+    #[test]
+    fn replaces_emails() {
+        assert_eq!(scrub("mail jane.doe+x@example.co.uk today"), "mail [EMAIL_1] today");
+    }
+
+    #[test]
+    fn replaces_only_luhn_valid_cards() {
+        assert_eq!(scrub("card 4111 1111 1111 1111 ok"), "card [CARD_1] ok");
+        assert_eq!(scrub("order 4111 1111 1111 1112"), "order 4111 1111 1111 1112");
+    }
+
+    #[test]
+    fn replaces_phone_numbers_but_not_dates_or_ids() {
+        assert_eq!(scrub("call +1 (555) 123-4567 now"), "call [PHONE_1] now");
+        assert_eq!(scrub("due 2024-03-01 14:05:00"), "due 2024-03-01 14:05:00");
+        assert_eq!(scrub("see #12 2024-03-01 14"), "see #12 2024-03-01 14");
+        assert_eq!(scrub("ticket ABC123456789"), "ticket ABC123456789");
+    }
+
+    #[test]
+    fn placeholders_are_stable_per_value() {
+        let scrubber = scrubber();
+        let mut vault = PiiVault::default();
+        let mut used = BTreeMap::new();
+        let first = scrubber.scrub_text("a@x.io b@x.io", &mut vault, &mut used);
+        let second = scrubber.scrub_text("B@X.io then a@x.io", &mut vault, &mut used);
+        assert_eq!(first, "[EMAIL_1] [EMAIL_2]");
+        assert_eq!(second, "[EMAIL_2] then [EMAIL_1]");
+        assert_eq!(used.get("[EMAIL_1]").map(String::as_str), Some("a@x.io"));
+    }
+
+    #[test]
+    fn rehydrates_placeholders_in_arguments() {
+        let scrubber = scrubber();
+        let mut vault = PiiVault::default();
+        let scrubbed = scrubber.scrub_text("Mail Jane@Example.com", &mut vault, &mut BTreeMap::new());
+        let mut arguments = serde_json::json!({ "content": format!("Forwarding to {} and [EMAIL_9]", &scrubbed[5..]), "id": 3 });
+        vault.rehydrate_json(&mut arguments);
+        assert_eq!(arguments["content"], "Forwarding to Jane@Example.com and [EMAIL_9]");
+        assert_eq!(arguments["id"], 3);
+    }
+
+    #[test]
+    fn summary_lines_keep_their_prefix() {
+        let mut value = serde_json::json!({
+            "summary": "Conversation summary (last 2 messages):\n\n[#12 2024-03-01 14:05:00+00:00] alice: hi\n[#13 2024-03-01 14:06:00+00:00] bob: call 555 123 4567\n"
+        });
+        scrubber().scrub_json(&mut value, &mut PiiVault::default(), &mut BTreeMap::new());
+        assert_eq!(
+            value["summary"],
+            "Conversation summary (last 2 messages):\n\n[#12 2024-03-01 14:05:00+00:00] alice: hi\n[#13 2024-03-01 14:06:00+00:00] bob: call [PHONE_1]\n"
+        );
+    }
+
+    #[test]
+    fn scrubs_content_keys_only() {
+        let mut value = serde_json::json!({ "id": 5551234567_i64, "username": "a@x.io", "messages": [{ "content": "a@x.io" }] });
+        scrubber().scrub_json(&mut value, &mut PiiVault::default(), &mut BTreeMap::new());
+        assert_eq!(value["username"], "a@x.io");
+        assert_eq!(value["messages"][0]["content"], "[EMAIL_1]");
+    }
+}
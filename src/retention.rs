@@ -0,0 +1,269 @@
+use serde::Serialize;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use tokio::time::{sleep, Duration};
+use crate::data_base::{Conversation, Database, RetentionAudit, RetentionRule};
+use crate::timestamp::Timestamp;
+
+/// Messages archived and deleted per transaction.
+const PURGE_BATCH: usize = 500;
+
+const USAGE: &str = "Usage: chat-IBM purge [--dry-run]";
+
+/// Longest age a rule may keep messages for; use "keep forever" beyond that.
+pub const MAX_AGE_DAYS: u32 = 36_500;
+
+/// Rule scopes, most specific first.
+pub const SCOPES: &[&str] = &["conversation", "group", "direct", "global"];
+
+#[derive(Debug)]
+pub enum RetentionError {
+    Database(mysql::Error),
+    Io(std::io::Error),
+    InvalidRule(String),
+}
+
+impl fmt::Display for RetentionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RetentionError::Database(e) => write!(f, "Database error: {}", e),
+            RetentionError::Io(e) => write!(f, "Archive error: {}", e),
+            RetentionError::InvalidRule(reason) => write!(f, "Invalid retention rule: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for RetentionError {}
+
+impl From<mysql::Error> for RetentionError {
+    fn from(e: mysql::Error) -> Self {
+        RetentionError::Database(e)
+    }
+}
+
+impl From<std::io::Error> for RetentionError {
+    fn from(e: std::io::Error) -> Self {
+        RetentionError::Io(e)
+    }
+}
+
+/// The rule that applies to a conversation: its own rule, then the one for
+/// its kind (group or direct), then the global one. No rule keeps everything.
+pub fn resolve<'a>(rules: &'a [RetentionRule], conversation: &Conversation) -> Option<&'a RetentionRule> {
+    let kind = if conversation.is_group { "group" } else { "direct" };
+    rules
+        .iter()
+        .find(|r| r.scope == "conversation" && r.conversation_id == Some(conversation.id))
+        .or_else(|| rules.iter().find(|r| r.scope == kind))
+        .or_else(|| rules.iter().find(|r| r.scope == "global"))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PurgeEntry {
+    pub conversation_id: i32,
+    pub title: String,
+    pub is_group: bool,
+    /// Scope of the rule that applied.
+    pub rule: String,
+    pub max_age_days: u32,
+    pub cutoff: Timestamp,
+    /// Messages older than the cutoff (to delete on a dry run, deleted otherwise).
+    pub messages: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PurgeReport {
+    pub dry_run: bool,
+    pub triggered_by: String,
+    pub started_at: Timestamp,
+    pub finished_at: Timestamp,
+    pub conversations: Vec<PurgeEntry>,
+    pub messages_deleted: usize,
+    pub archive: Option<String>,
+}
+
+/// Directory purged rows are archived to (ORBIT_RETENTION_ARCHIVE_DIR,
+/// default `retention_archive`).
+pub fn archive_dir() -> PathBuf {
+    env::var("ORBIT_RETENTION_ARCHIVE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("retention_archive"))
+}
+
+/// Applies the retention rules. Each batch is archived to a JSON Lines file
+/// and synced to disk before it is deleted, so an interrupted purge never
+/// loses rows that are not in the archive. Every run, dry or not, successful
+/// or not, leaves a row in `orbit_retention_audit`.
+pub fn run(db: &Database, dry_run: bool, triggered_by: &str) -> Result<PurgeReport, RetentionError> {
+    let mut report = PurgeReport {
+        dry_run,
+        triggered_by: triggered_by.to_string(),
+        started_at: Timestamp::now(),
+        finished_at: Timestamp::now(),
+        conversations: Vec::new(),
+        messages_deleted: 0,
+        archive: None,
+    };
+
+    let result = purge(db, &mut report);
+    report.finished_at = Timestamp::now();
+
+    let audit = RetentionAudit {
+        started_at: report.started_at,
+        finished_at: report.finished_at,
+        triggered_by: report.triggered_by.clone(),
+        dry_run,
+        conversations: report.conversations.len(),
+        messages_deleted: report.messages_deleted,
+        archive_path: report.archive.clone(),
+        details: serde_json::to_string(&report.conversations).unwrap_or_default(),
+        error: result.as_ref().err().map(|e| e.to_string()),
+    };
+    if let Err(e) = db.record_retention_audit(&audit) {
+        eprintln!("Failed to record retention audit: {}", e);
+    }
+
+    result.map(|_| report)
+}
+
+fn purge(db: &Database, report: &mut PurgeReport) -> Result<(), RetentionError> {
+    let rules = db.get_retention_rules()?;
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    let now = Timestamp::now();
+    for conversation in db.get_all_conversations()? {
+        let rule = match resolve(&rules, &conversation) {
+            Some(rule) => rule,
+            None => continue,
+        };
+        let max_age_days = match rule.max_age_days {
+            Some(days) => days,
+            None => continue,
+        };
+        let cutoff = now.days_before(max_age_days).ok_or_else(|| {
+            RetentionError::InvalidRule(format!(
+                "{} rule keeps messages for {} days, more than the supported {}",
+                rule.scope, max_age_days, MAX_AGE_DAYS
+            ))
+        })?;
+        let count = db.count_messages_before(conversation.id, cutoff)?;
+        if count == 0 {
+            continue;
+        }
+
+        report.conversations.push(PurgeEntry {
+            conversation_id: conversation.id,
+            title: conversation.title.clone(),
+            is_group: conversation.is_group,
+            rule: rule.scope.clone(),
+            max_age_days,
+            cutoff,
+            messages: count,
+        });
+    }
+
+    if report.dry_run || report.conversations.is_empty() {
+        return Ok(());
+    }
+
+    let dir = archive_dir();
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("purge-{}-{}.jsonl", now.date_string(), now.unix_seconds()));
+    report.archive = Some(path.display().to_string());
+    let mut archive = BufWriter::new(File::create(&path)?);
+
+    for index in 0..report.conversations.len() {
+        let (conversation_id, cutoff) = (report.conversations[index].conversation_id, report.conversations[index].cutoff);
+        let mut deleted = 0;
+        let mut after_id = 0;
+
+        loop {
+            let batch = db.find_messages_for_archive(conversation_id, cutoff, after_id, PURGE_BATCH)?;
+            let last = match batch.last() {
+                Some(last) => last.message.id,
+                None => break,
+            };
+
+            for message in &batch {
+                serde_json::to_writer(&mut archive, message).map_err(std::io::Error::from)?;
+                archive.write_all(b"\n")?;
+            }
+            archive.flush()?;
+            archive.get_ref().sync_data()?;
+
+            let ids: Vec<i32> = batch.iter().map(|m| m.message.id).collect();
+            deleted += db.purge_messages(conversation_id, &ids)?;
+            after_id = last;
+
+            if batch.len() < PURGE_BATCH {
+                break;
+            }
+        }
+
+        report.conversations[index].messages = deleted;
+        report.messages_deleted += deleted;
+    }
+
+    Ok(())
+}
+
+/// Runs the purge every `ORBIT_RETENTION_INTERVAL_HOURS`. Scheduling is off
+/// when that is unset; `ORBIT_RETENTION_DRY_RUN=1` makes scheduled runs only
+/// report what they would delete.
+pub fn spawn_scheduler() -> Option<Duration> {
+    let hours: u64 = env::var("ORBIT_RETENTION_INTERVAL_HOURS").ok()?.parse().ok().filter(|h| *h > 0)?;
+    let interval = Duration::from_secs(hours * 3600);
+    let dry_run = env::var("ORBIT_RETENTION_DRY_RUN")
+        .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+
+    tokio::spawn(async move {
+        loop {
+            sleep(interval).await;
+
+            let outcome = tokio::task::spawn_blocking(move || {
+                let db = Database::new()?;
+                run(&db, dry_run, "scheduler")
+            })
+            .await;
+            match outcome {
+                Ok(Ok(report)) => println!(
+                    "Retention purge{}: {} messages in {} conversations{}",
+                    if dry_run { " (dry run)" } else { "" },
+                    if dry_run { report.conversations.iter().map(|c| c.messages).sum() } else { report.messages_deleted },
+                    report.conversations.len(),
+                    report.archive.map(|path| format!(", archived to {}", path)).unwrap_or_default(),
+                ),
+                Ok(Err(e)) => eprintln!("Retention purge failed: {}", e),
+                Err(e) => eprintln!("Retention purge task failed: {}", e),
+            }
+        }
+    });
+
+    Some(interval)
+}
+
+/// `chat-IBM purge [--dry-run]`: runs the retention rules once and prints the report.
+pub fn run_cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut dry_run = false;
+    for arg in args {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            _ => return Err(format!("Unexpected argument '{}'\n{}", arg, USAGE).into()),
+        }
+    }
+
+    let db = Database::new()?;
+    let report = run(&db, dry_run, "cli")?;
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if dry_run {
+        println!("Dry run: no messages were deleted.");
+    }
+    Ok(())
+}
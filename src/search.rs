@@ -0,0 +1,182 @@
+use regex::Regex;
+
+/// How a full-text query is interpreted by MySQL's MATCH ... AGAINST.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Plain words ranked by relevance.
+    Natural,
+    /// Supports +required, -excluded, "exact phrases" and prefix* terms.
+    Boolean,
+}
+
+impl SearchMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "natural" => Some(SearchMode::Natural),
+            "boolean" => Some(SearchMode::Boolean),
+            _ => None,
+        }
+    }
+
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            SearchMode::Natural => "IN NATURAL LANGUAGE MODE",
+            SearchMode::Boolean => "IN BOOLEAN MODE",
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchMode::Natural => "natural",
+            SearchMode::Boolean => "boolean",
+        }
+    }
+}
+
+/// Rewrites a boolean-mode query into one MySQL accepts. Operators are
+/// kept only at the start of a term (plus a trailing `*`), words containing
+/// other punctuation such as `@` in an email are quoted as phrases, and
+/// parentheses and unbalanced quotes are dropped.
+pub fn sanitize_boolean_query(query: &str) -> String {
+    let re = Regex::new(r#"([+\-~<>]*)"([^"]*)"|([+\-~<>]*)([^\s"]+)"#).unwrap();
+    let mut terms = Vec::new();
+
+    for cap in re.captures_iter(query) {
+        let (operators, text, is_phrase) = match (cap.get(2), cap.get(4)) {
+            (Some(phrase), _) => (cap.get(1).map_or("", |m| m.as_str()), phrase.as_str(), true),
+            (None, Some(word)) => (cap.get(3).map_or("", |m| m.as_str()), word.as_str(), false),
+            _ => continue,
+        };
+        let operator = operators.chars().next().map(String::from).unwrap_or_default();
+
+        let prefix = !is_phrase && text.ends_with('*');
+        // A lone operator is matched as a word of its own.
+        let text = text.trim_start_matches(['+', '-', '~', '<', '>']).trim_end_matches('*').replace(['(', ')', '*'], " ");
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        if !is_phrase && text.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            terms.push(format!("{}{}{}", operator, text, if prefix { "*" } else { "" }));
+        } else {
+            terms.push(format!("{}\"{}\"", operator, text));
+        }
+    }
+
+    terms.join(" ")
+}
+
+/// Extracts the words and phrases a query is looking for, skipping boolean
+/// operators and excluded (-term) parts. Used to highlight matches.
+pub fn query_terms(query: &str) -> Vec<String> {
+    let re = Regex::new(r#"([+\-~<>]*)"([^"]+)"|([+\-~<>]*)([^\s"()]+)"#).unwrap();
+    let mut terms = Vec::new();
+
+    for cap in re.captures_iter(query) {
+        let (operators, term) = match (cap.get(2), cap.get(4)) {
+            (Some(phrase), _) => (cap.get(1).map_or("", |m| m.as_str()), phrase.as_str()),
+            (None, Some(word)) => (cap.get(3).map_or("", |m| m.as_str()), word.as_str()),
+            _ => continue,
+        };
+
+        if operators.contains('-') {
+            continue;
+        }
+
+        let term = term.trim_end_matches('*').trim();
+        if !term.is_empty() && !terms.iter().any(|t: &String| t.eq_ignore_ascii_case(term)) {
+            terms.push(term.to_string());
+        }
+    }
+
+    terms
+}
+
+/// Returns an excerpt of `content` around the first matching term, with every
+/// match wrapped in `**`. Content without a match is truncated instead.
+pub fn highlight_snippet(content: &str, terms: &[String], radius: usize) -> String {
+    let pattern = terms
+        .iter()
+        .map(|t| regex::escape(t).replace(' ', r"\s+"))
+        .collect::<Vec<_>>()
+        .join("|");
+
+    let re = match Regex::new(&format!("(?i){}", pattern)) {
+        Ok(re) if !terms.is_empty() => re,
+        _ => return truncate_chars(content, radius * 2),
+    };
+
+    let first = match re.find(content) {
+        Some(m) => m,
+        None => return truncate_chars(content, radius * 2),
+    };
+
+    let start = floor_char_boundary(content, first.start().saturating_sub(radius));
+    let end = ceil_char_boundary(content, first.end() + radius);
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    snippet.push_str(&re.replace_all(&content[start..end], "**$0**"));
+    if end < content.len() {
+        snippet.push('…');
+    }
+
+    snippet
+}
+
+fn truncate_chars(content: &str, max_chars: usize) -> String {
+    match content.char_indices().nth(max_chars) {
+        Some((idx, _)) => format!("{}…", &content[..idx]),
+        None => content.to_string(),
+    }
+}
+
+fn floor_char_boundary(s: &str, mut idx: usize) -> usize {
+    while idx > 0 && !s.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    idx
+}
+
+fn ceil_char_boundary(s: &str, mut idx: usize) -> usize {
+    if idx >= s.len() {
+        return s.len();
+    }
+    while !s.is_char_boundary(idx) {
+        idx += 1;
+    }
+    idx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_valid_boolean_syntax() {
+        assert_eq!(sanitize_boolean_query(r#"+deploy -staging "release notes" roll*"#), r#"+deploy -staging "release notes" roll*"#);
+    }
+
+    #[test]
+    fn quotes_words_with_punctuation() {
+        assert_eq!(sanitize_boolean_query("mail jane@example.com"), r#"mail "jane@example.com""#);
+        assert_eq!(sanitize_boolean_query("+e-mail c++"), r#"+"e-mail" "c++""#);
+        assert_eq!(sanitize_boolean_query("@8 @@"), r#""@8" "@@""#);
+    }
+
+    #[test]
+    fn drops_unbalanced_quotes_parentheses_and_stray_operators() {
+        assert_eq!(sanitize_boolean_query(r#"foo "bar baz"#), "foo bar baz");
+        assert_eq!(sanitize_boolean_query("(apple OR pear)"), "apple OR pear");
+        assert_eq!(sanitize_boolean_query("+-~word ** - +"), "+word");
+        assert_eq!(sanitize_boolean_query(r#"say "" twice"#), "say twice");
+    }
+
+    #[test]
+    fn query_terms_skip_excluded_parts() {
+        assert_eq!(query_terms(r#"+deploy -staging "release notes" roll*"#), ["deploy", "release notes", "roll"]);
+    }
+}
@@ -0,0 +1,584 @@
+use mysql::Value as SqlValue;
+use serde_json::{json, Value};
+use sqlparser::ast::{
+    Expr, Query, Select, SelectItem, SetExpr, Statement, TableFactor, Value as AstValue, Visit, Visitor,
+};
+use sqlparser::dialect::MySqlDialect;
+use sqlparser::parser::Parser;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::ops::ControlFlow;
+use std::time::Duration;
+use crate::data_base::ReadOnlyRows;
+
+/// Tables the SQL tool may read and the columns readable on each. Anything
+/// not listed here (password hashes, emails, Orbit's own tables) is refused.
+const ALLOWED_TABLES: &[(&str, &[&str])] = &[
+    ("user", &["id", "username", "chat_role", "is_active", "created_at"]),
+    ("conversations", &["id", "title", "is_group", "created_at"]),
+    ("conversation_users", &["conversation_id", "user_id", "is_admin"]),
+    (
+        "messages",
+        &["id", "conversation_id", "user_id", "content", "reaction", "reply_to_id", "created_at", "edited_at", "deleted_at"],
+    ),
+];
+
+/// Functions that are safe to evaluate: no sleeping, locking, file access
+/// or server introspection.
+const ALLOWED_FUNCTIONS: &[&str] = &[
+    "abs", "avg", "ceil", "ceiling", "char_length", "coalesce", "concat", "concat_ws", "count", "curdate",
+    "current_date", "current_timestamp", "date", "date_add", "date_format", "date_sub", "datediff", "day",
+    "dayname", "dayofmonth", "dayofweek", "dayofyear", "floor", "greatest", "group_concat", "hour", "if",
+    "ifnull", "least", "left", "length", "locate", "lower", "ltrim", "max", "min", "minute", "month",
+    "monthname", "now", "nullif", "quarter", "replace", "right", "round", "rtrim", "second", "substr",
+    "substring", "sum", "timestampdiff", "trim", "upper", "week", "weekday", "year", "yearweek",
+];
+
+/// Column names that are refused wherever they appear, even as an alias.
+fn is_secret_column(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.contains("password") || name.contains("passwd") || name == "pass"
+}
+
+#[derive(Debug)]
+pub enum SqlQueryError {
+    Parse(String),
+    Rejected(String),
+}
+
+impl fmt::Display for SqlQueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SqlQueryError::Parse(e) => write!(f, "Could not parse SQL: {}", e),
+            SqlQueryError::Rejected(reason) => write!(f, "Query rejected: {}", reason),
+        }
+    }
+}
+
+fn rejected<T>(reason: impl Into<String>) -> Result<T, SqlQueryError> {
+    Err(SqlQueryError::Rejected(reason.into()))
+}
+
+/// Most rows a query may return, from `ORBIT_SQL_MAX_ROWS` (default 200).
+pub fn max_rows() -> u64 {
+    env::var("ORBIT_SQL_MAX_ROWS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(200u64)
+        .clamp(1, 10_000)
+}
+
+/// Server-side statement timeout, from `ORBIT_SQL_TIMEOUT_MS` (default 5000).
+pub fn statement_timeout() -> Duration {
+    let millis = env::var("ORBIT_SQL_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(5000u64)
+        .max(100);
+    Duration::from_millis(millis)
+}
+
+/// Parses `sql`, checks that it is a single SELECT over allowlisted tables
+/// and columns, and returns it re-rendered with a LIMIT of at most `max_rows`.
+pub fn validate(sql: &str, max_rows: u64) -> Result<String, SqlQueryError> {
+    let mut statements =
+        Parser::parse_sql(&MySqlDialect {}, sql).map_err(|e| SqlQueryError::Parse(e.to_string()))?;
+    if statements.len() != 1 {
+        return rejected("exactly one statement is allowed");
+    }
+
+    let mut query = match statements.remove(0) {
+        Statement::Query(query) => query,
+        _ => return rejected("only SELECT queries are allowed"),
+    };
+
+    let mut scopes = ScopeCollector::default();
+    if let ControlFlow::Break(reason) = query.visit(&mut scopes) {
+        return rejected(reason);
+    }
+    let mut columns = ColumnCheck {
+        scopes: &scopes.scopes,
+        alias_exprs: &scopes.alias_exprs,
+        next: 0,
+        stack: Vec::new(),
+    };
+    if let ControlFlow::Break(reason) = query.visit(&mut columns) {
+        return rejected(reason);
+    }
+
+    apply_limit(&mut query, max_rows)?;
+    Ok(query.to_string())
+}
+
+fn apply_limit(query: &mut Query, max_rows: u64) -> Result<(), SqlQueryError> {
+    let limit = match &query.limit {
+        None => max_rows,
+        Some(Expr::Value(AstValue::Number(n, _))) => match n.parse::<u64>() {
+            Ok(n) => n.min(max_rows),
+            Err(_) => return rejected("LIMIT must be a whole number"),
+        },
+        Some(_) => return rejected("LIMIT must be a literal number"),
+    };
+    query.limit = Some(Expr::Value(AstValue::Number(limit.to_string(), false)));
+    Ok(())
+}
+
+/// What a name in FROM refers to.
+enum Source {
+    Table(&'static [&'static str]),
+    /// A CTE or derived table; its columns were checked where they were selected.
+    Subquery,
+}
+
+/// Names visible inside one query (or subquery) level.
+#[derive(Default)]
+struct Scope {
+    parent: Option<usize>,
+    sources: HashMap<String, Source>,
+    /// Readable columns of the real tables in this level's FROM.
+    table_columns: HashSet<&'static str>,
+    /// Select-list aliases; MySQL only resolves them in ORDER BY and HAVING.
+    select_aliases: HashSet<String>,
+    /// Output columns of CTEs and subqueries in FROM, usable unqualified.
+    derived_columns: HashSet<String>,
+    /// CTE name -> its output column names.
+    ctes: HashMap<String, Vec<String>>,
+}
+
+/// First pass: one scope per query level, in visit order. Select lists are
+/// visited before FROM, so columns can only be checked once this is done.
+#[derive(Default)]
+struct ScopeCollector {
+    scopes: Vec<Scope>,
+    stack: Vec<usize>,
+    /// Address of each expression in an ORDER BY or HAVING clause -> the
+    /// scope whose select aliases it may use.
+    alias_exprs: HashMap<usize, usize>,
+}
+
+/// Records the expressions of one clause, not descending into subqueries,
+/// which have their own scope.
+struct AliasPositions<'a> {
+    scope: usize,
+    depth: usize,
+    out: &'a mut HashMap<usize, usize>,
+}
+
+impl Visitor for AliasPositions<'_> {
+    type Break = String;
+
+    fn pre_visit_query(&mut self, _query: &Query) -> ControlFlow<String> {
+        self.depth += 1;
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_query(&mut self, _query: &Query) -> ControlFlow<String> {
+        self.depth -= 1;
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<String> {
+        if self.depth == 0 {
+            self.out.insert(expr as *const Expr as usize, self.scope);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// HAVING clauses of the selects that make up a query body.
+fn having_clauses(body: &SetExpr) -> Vec<&Expr> {
+    match body {
+        SetExpr::Select(select) => select.having.iter().collect(),
+        SetExpr::SetOperation { left, right, .. } => {
+            let mut clauses = having_clauses(left);
+            clauses.extend(having_clauses(right));
+            clauses
+        }
+        _ => Vec::new(),
+    }
+}
+
+impl Visitor for ScopeCollector {
+    type Break = String;
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<String> {
+        let parent = self.stack.last().copied();
+        self.scopes.push(Scope { parent, ..Scope::default() });
+        let scope = self.scopes.len() - 1;
+        self.stack.push(scope);
+
+        let order_by = query.order_by.iter().flat_map(|order_by| order_by.exprs.iter().map(|e| &e.expr));
+        for expr in order_by.chain(having_clauses(&query.body)) {
+            let _ = expr.visit(&mut AliasPositions { scope, depth: 0, out: &mut self.alias_exprs });
+        }
+
+        if !query.locks.is_empty() || query.for_clause.is_some() {
+            return ControlFlow::Break("locking and FOR clauses are not allowed".to_string());
+        }
+        if query.fetch.is_some() || !query.limit_by.is_empty() {
+            return ControlFlow::Break("use LIMIT to bound results".to_string());
+        }
+        if let Some(with) = &query.with {
+            for cte in &with.cte_tables {
+                let name = cte.alias.name.value.to_lowercase();
+                let columns = match cte.alias.columns.is_empty() {
+                    true => output_names(&cte.query.body),
+                    false => cte.alias.columns.iter().map(|c| c.name.value.to_lowercase()).collect(),
+                };
+                self.current().ctes.insert(name, columns);
+            }
+        }
+        self.check_body(&query.body)
+    }
+
+    fn post_visit_query(&mut self, _query: &Query) -> ControlFlow<String> {
+        self.stack.pop();
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_table_factor(&mut self, factor: &TableFactor) -> ControlFlow<String> {
+        match factor {
+            TableFactor::Table { name, alias, args: None, .. } => {
+                let parts = &name.0;
+                if parts.len() != 1 {
+                    return ControlFlow::Break(format!("table {} must be referenced without a schema", name));
+                }
+                let table = parts[0].value.to_lowercase();
+                let source = if let Some(columns) = self.cte_columns(&table) {
+                    for column in columns {
+                        self.derived_column(&column)?;
+                    }
+                    Source::Subquery
+                } else {
+                    match ALLOWED_TABLES.iter().find(|(allowed, _)| *allowed == table) {
+                        Some((_, columns)) => {
+                            self.current().table_columns.extend(columns.iter().copied());
+                            Source::Table(columns)
+                        }
+                        None => return ControlFlow::Break(format!("table {} is not readable", table)),
+                    }
+                };
+                let key = alias.as_ref().map(|a| a.name.value.to_lowercase()).unwrap_or(table);
+                self.current().sources.insert(key, source);
+            }
+            TableFactor::Derived { subquery, alias, .. } => {
+                let columns = match alias.as_ref().filter(|a| !a.columns.is_empty()) {
+                    Some(alias) => alias.columns.iter().map(|c| c.name.value.to_lowercase()).collect(),
+                    None => output_names(&subquery.body),
+                };
+                for column in columns {
+                    self.derived_column(&column)?;
+                }
+                if let Some(alias) = alias {
+                    self.current().sources.insert(alias.name.value.to_lowercase(), Source::Subquery);
+                }
+            }
+            TableFactor::NestedJoin { .. } => {}
+            _ => return ControlFlow::Break("only tables, joins and subqueries may appear in FROM".to_string()),
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+impl ScopeCollector {
+    fn current(&mut self) -> &mut Scope {
+        let index = *self.stack.last().expect("visiting inside a query");
+        &mut self.scopes[index]
+    }
+
+    fn cte_columns(&self, name: &str) -> Option<Vec<String>> {
+        let mut scope = self.stack.last().copied();
+        while let Some(index) = scope {
+            if let Some(columns) = self.scopes[index].ctes.get(name) {
+                return Some(columns.clone());
+            }
+            scope = self.scopes[index].parent;
+        }
+        None
+    }
+
+    fn check_name(name: &str) -> ControlFlow<String> {
+        if is_secret_column(name) {
+            return ControlFlow::Break(format!("{} cannot be used as a name", name));
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn alias(&mut self, name: &str) -> ControlFlow<String> {
+        Self::check_name(name)?;
+        self.current().select_aliases.insert(name.to_lowercase());
+        ControlFlow::Continue(())
+    }
+
+    fn derived_column(&mut self, name: &str) -> ControlFlow<String> {
+        Self::check_name(name)?;
+        self.current().derived_columns.insert(name.to_lowercase());
+        ControlFlow::Continue(())
+    }
+
+    fn check_body(&mut self, body: &SetExpr) -> ControlFlow<String> {
+        match body {
+            SetExpr::Select(select) => self.check_select(select),
+            SetExpr::Query(_) => ControlFlow::Continue(()),
+            SetExpr::SetOperation { left, right, .. } => {
+                self.check_body(left)?;
+                self.check_body(right)
+            }
+            _ => ControlFlow::Break("only SELECT queries are allowed".to_string()),
+        }
+    }
+
+    fn check_select(&mut self, select: &Select) -> ControlFlow<String> {
+        if select.into.is_some() {
+            return ControlFlow::Break("SELECT ... INTO is not allowed".to_string());
+        }
+        for item in &select.projection {
+            match item {
+                SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..) => {
+                    return ControlFlow::Break("list the columns you need instead of *".to_string())
+                }
+                SelectItem::ExprWithAlias { alias, .. } => self.alias(&alias.value)?,
+                SelectItem::UnnamedExpr(_) => {}
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Column names a query body produces, as far as they can be named.
+fn output_names(body: &SetExpr) -> Vec<String> {
+    match body {
+        SetExpr::Select(select) => select
+            .projection
+            .iter()
+            .filter_map(|item| match item {
+                SelectItem::ExprWithAlias { alias, .. } => Some(alias.value.to_lowercase()),
+                SelectItem::UnnamedExpr(Expr::Identifier(ident)) => Some(ident.value.to_lowercase()),
+                SelectItem::UnnamedExpr(Expr::CompoundIdentifier(parts)) => {
+                    parts.last().map(|ident| ident.value.to_lowercase())
+                }
+                _ => None,
+            })
+            .collect(),
+        SetExpr::Query(query) => output_names(&query.body),
+        SetExpr::SetOperation { left, .. } => output_names(left),
+        _ => Vec::new(),
+    }
+}
+
+/// Second pass: every identifier and function call, against the scope of
+/// the query level it appears in. Qualified names and table columns may come
+/// from enclosing levels (correlated subqueries); select aliases only count
+/// in the ORDER BY and HAVING of their own level.
+struct ColumnCheck<'a> {
+    scopes: &'a [Scope],
+    alias_exprs: &'a HashMap<usize, usize>,
+    next: usize,
+    stack: Vec<usize>,
+}
+
+impl Visitor for ColumnCheck<'_> {
+    type Break = String;
+
+    fn pre_visit_query(&mut self, _query: &Query) -> ControlFlow<String> {
+        self.stack.push(self.next);
+        self.next += 1;
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_query(&mut self, _query: &Query) -> ControlFlow<String> {
+        self.stack.pop();
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<String> {
+        match expr {
+            Expr::Identifier(ident) => {
+                let alias_allowed = self.alias_exprs.get(&(expr as *const Expr as usize)) == self.stack.last();
+                self.check_column(None, &ident.value, alias_allowed)
+            }
+            Expr::CompoundIdentifier(parts) => match parts.as_slice() {
+                [qualifier, column] => self.check_column(Some(&qualifier.value), &column.value, false),
+                _ => ControlFlow::Break(format!("{} must be written as table.column", expr)),
+            },
+            Expr::Function(function) => {
+                let name = function.name.to_string().to_lowercase();
+                if function.name.0.len() == 1 && ALLOWED_FUNCTIONS.contains(&name.as_str()) {
+                    ControlFlow::Continue(())
+                } else {
+                    ControlFlow::Break(format!("function {} is not allowed", function.name))
+                }
+            }
+            Expr::Wildcard(_) | Expr::QualifiedWildcard(..) => {
+                ControlFlow::Break("list the columns you need instead of *".to_string())
+            }
+            _ => ControlFlow::Continue(()),
+        }
+    }
+}
+
+impl ColumnCheck<'_> {
+    /// Current scope followed by its enclosing ones.
+    fn scope_chain(&self) -> impl Iterator<Item = &Scope> {
+        let mut index = self.stack.last().copied();
+        std::iter::from_fn(move || {
+            let scope = &self.scopes[index?];
+            index = scope.parent;
+            Some(scope)
+        })
+    }
+
+    fn check_column(&self, qualifier: Option<&str>, column: &str, alias_allowed: bool) -> ControlFlow<String> {
+        if column.starts_with('@') {
+            return ControlFlow::Break("variables are not allowed".to_string());
+        }
+        if is_secret_column(column) {
+            return ControlFlow::Break(format!("column {} is never readable", column));
+        }
+
+        let lower = column.to_lowercase();
+        let readable = match qualifier {
+            Some(qualifier) => {
+                let qualifier_lower = qualifier.to_lowercase();
+                match self.scope_chain().find_map(|scope| scope.sources.get(&qualifier_lower)) {
+                    Some(Source::Table(columns)) => columns.contains(&lower.as_str()),
+                    Some(Source::Subquery) => true,
+                    None => return ControlFlow::Break(format!("{} is not a table in this query", qualifier)),
+                }
+            }
+            None => {
+                (alias_allowed && self.scope_chain().next().is_some_and(|scope| scope.select_aliases.contains(&lower)))
+                    || self.scope_chain().any(|scope| {
+                        scope.table_columns.contains(lower.as_str()) || scope.derived_columns.contains(&lower)
+                    })
+            }
+        };
+
+        if readable {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(format!("column {} is not readable", column))
+        }
+    }
+}
+
+/// Rows as JSON objects keyed by column name. The text protocol returns
+/// every value as bytes, so numbers are recovered from the column type.
+pub fn rows_to_json(result: ReadOnlyRows) -> (Value, Value) {
+    let columns = json!(result.columns);
+    let rows = result
+        .rows
+        .into_iter()
+        .map(|row| {
+            let mut object = serde_json::Map::new();
+            for (column, value) in result.columns.iter().zip(row) {
+                object.insert(column.name.clone(), value_to_json(&column.column_type, value));
+            }
+            Value::Object(object)
+        })
+        .collect();
+    (columns, rows)
+}
+
+fn value_to_json(column_type: &str, value: SqlValue) -> Value {
+    match value {
+        SqlValue::NULL => Value::Null,
+        SqlValue::Int(n) => json!(n),
+        SqlValue::UInt(n) => json!(n),
+        SqlValue::Float(n) => json!(n),
+        SqlValue::Double(n) => json!(n),
+        SqlValue::Bytes(bytes) => {
+            let text = String::from_utf8_lossy(&bytes).into_owned();
+            let number = match column_type {
+                "tiny" | "short" | "int24" | "long" | "longlong" | "year" => text.parse::<i64>().ok().map(|n| json!(n)),
+                "float" | "double" => text.parse::<f64>().ok().map(|n| json!(n)),
+                _ => None,
+            };
+            number.unwrap_or(Value::String(text))
+        }
+        other => Value::String(other.as_sql(true).trim_matches('\'').to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accepts(sql: &str) -> String {
+        validate(sql, 200).unwrap_or_else(|e| panic!("{} was rejected: {}", sql, e))
+    }
+
+    fn rejects(sql: &str) {
+        assert!(validate(sql, 200).is_err(), "{} was accepted", sql);
+    }
+
+    #[test]
+    fn accepts_allowlisted_columns_and_injects_a_limit() {
+        assert_eq!(accepts("SELECT id, username FROM user"), "SELECT id, username FROM user LIMIT 200");
+        accepts("SELECT u.username, COUNT(m.id) AS sent FROM user AS u JOIN messages AS m ON m.user_id = u.id GROUP BY u.username");
+    }
+
+    #[test]
+    fn select_aliases_do_not_resolve_in_the_select_list_or_where() {
+        rejects("SELECT id AS email, email AS leaked FROM user");
+        rejects("SELECT id AS email FROM user WHERE email LIKE 'a%'");
+        rejects("SELECT id AS email FROM user GROUP BY email");
+        rejects("SELECT user_id FROM messages GROUP BY user_id HAVING user_id IN (SELECT id AS email FROM user WHERE email LIKE 'a%')");
+    }
+
+    #[test]
+    fn select_aliases_resolve_in_order_by_and_having() {
+        accepts("SELECT user_id, COUNT(id) AS sent FROM messages GROUP BY user_id HAVING sent > 5 ORDER BY sent DESC");
+    }
+
+    #[test]
+    fn derived_table_columns_are_usable_unqualified() {
+        accepts("SELECT n FROM (SELECT COUNT(id) AS n FROM messages) AS counts WHERE n > 1");
+        accepts("WITH active AS (SELECT id FROM user WHERE is_active = 1) SELECT id FROM active");
+    }
+
+    #[test]
+    fn rejects_hidden_columns_and_tables() {
+        rejects("SELECT email FROM user");
+        rejects("SELECT password FROM user");
+        rejects("SELECT username AS password FROM user");
+        rejects("SELECT id FROM orbit_idempotency_keys");
+        rejects("SELECT id FROM mysql.user");
+    }
+
+    #[test]
+    fn rejects_wildcards() {
+        rejects("SELECT * FROM user");
+        rejects("SELECT u.* FROM user AS u");
+    }
+
+    #[test]
+    fn rejects_disallowed_functions_and_variables() {
+        rejects("SELECT SLEEP(5) FROM user");
+        rejects("SELECT LOAD_FILE('/etc/passwd') FROM user");
+        rejects("SELECT @@version");
+        rejects("SELECT id FROM user WHERE id = @target");
+    }
+
+    #[test]
+    fn rejects_anything_but_a_single_select() {
+        rejects("SELECT id FROM user; SELECT id FROM user");
+        rejects("DELETE FROM messages");
+        rejects("SELECT id FROM user INTO OUTFILE '/tmp/x'");
+        rejects("SELECT id FROM user FOR UPDATE");
+    }
+
+    #[test]
+    fn union_limit_is_capped_for_the_whole_query() {
+        assert_eq!(
+            accepts("SELECT id FROM user UNION SELECT id FROM conversations LIMIT 5000"),
+            "SELECT id FROM user UNION SELECT id FROM conversations LIMIT 200"
+        );
+        assert_eq!(
+            accepts("SELECT id FROM user UNION ALL SELECT id FROM conversations LIMIT 5"),
+            "SELECT id FROM user UNION ALL SELECT id FROM conversations LIMIT 5"
+        );
+        rejects("SELECT id FROM user UNION SELECT email FROM user");
+    }
+}
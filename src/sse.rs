@@ -0,0 +1,309 @@
+use std::fmt;
+
+/// Longest line the decoder buffers before giving up on the stream.
+const MAX_LINE_BYTES: usize = 4 * 1024 * 1024;
+
+/// One dispatched server-sent event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    /// The `event:` field, if the server set one.
+    pub event: Option<String>,
+    /// All `data:` lines of the event joined with `\n`.
+    pub data: String,
+    /// The last event id seen on the stream so far.
+    pub id: Option<String>,
+    /// Reconnection time the server asked for, in milliseconds.
+    pub retry: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SseError {
+    /// A line was not valid UTF-8.
+    InvalidUtf8 { line: Vec<u8> },
+    /// A line grew past `MAX_LINE_BYTES` without a line break.
+    LineTooLong,
+    /// The stream ended in the middle of an event.
+    Truncated { partial: String },
+}
+
+impl fmt::Display for SseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SseError::InvalidUtf8 { line } => {
+                write!(f, "invalid UTF-8 in event stream line: {}", String::from_utf8_lossy(line))
+            }
+            SseError::LineTooLong => write!(f, "event stream line exceeds {} bytes", MAX_LINE_BYTES),
+            SseError::Truncated { partial } => write!(f, "event stream ended mid-event: {}", partial),
+        }
+    }
+}
+
+impl std::error::Error for SseError {}
+
+/// Incremental `text/event-stream` decoder. Feed it network chunks as they
+/// arrive, split anywhere, and it yields events once their terminating blank
+/// line has been seen. Handles LF, CR and CRLF line endings, comments, and
+/// the `event`, `data`, `id` and `retry` fields.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    line: Vec<u8>,
+    /// The previous chunk ended in CR, so a leading LF belongs to it.
+    after_cr: bool,
+    /// Data lines of the event being built, each followed by `\n`.
+    data: String,
+    has_data: bool,
+    event: Option<String>,
+    last_id: Option<String>,
+    retry: Option<u64>,
+    /// Any field seen since the last dispatch, for truncation detection.
+    pending: bool,
+    failed: bool,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consumes a chunk and returns the events (or errors) it completed.
+    /// After an error the decoder stops producing events.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Result<SseEvent, SseError>> {
+        let mut out = Vec::new();
+        if self.failed {
+            return out;
+        }
+
+        for &byte in bytes {
+            if self.after_cr {
+                self.after_cr = false;
+                if byte == b'\n' {
+                    continue;
+                }
+            }
+
+            match byte {
+                b'\n' | b'\r' => {
+                    self.after_cr = byte == b'\r';
+                    let line = std::mem::take(&mut self.line);
+                    match self.process_line(&line) {
+                        Ok(Some(event)) => out.push(Ok(event)),
+                        Ok(None) => {}
+                        Err(e) => return self.fail(out, e),
+                    }
+                }
+                _ => {
+                    if self.line.len() >= MAX_LINE_BYTES {
+                        return self.fail(out, SseError::LineTooLong);
+                    }
+                    self.line.push(byte);
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Call at end of stream. An event without its terminating blank line is
+    /// an error rather than being silently dropped.
+    pub fn finish(mut self) -> Result<(), SseError> {
+        if self.failed {
+            return Ok(());
+        }
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            self.process_line(&line)?;
+        }
+        if self.pending {
+            return Err(SseError::Truncated { partial: self.data.trim_end_matches('\n').to_string() });
+        }
+        Ok(())
+    }
+
+    fn fail(&mut self, mut out: Vec<Result<SseEvent, SseError>>, error: SseError) -> Vec<Result<SseEvent, SseError>> {
+        self.failed = true;
+        self.line.clear();
+        out.push(Err(error));
+        out
+    }
+
+    fn process_line(&mut self, line: &[u8]) -> Result<Option<SseEvent>, SseError> {
+        if line.is_empty() {
+            return Ok(self.dispatch());
+        }
+
+        let line = std::str::from_utf8(line).map_err(|_| SseError::InvalidUtf8 { line: line.to_vec() })?;
+        // A UTF-8 BOM may precede the first line.
+        let line = line.strip_prefix('\u{feff}').unwrap_or(line);
+        if line.starts_with(':') {
+            return Ok(None);
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        self.pending = true;
+
+        match field {
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+                self.has_data = true;
+            }
+            "event" => self.event = Some(value.to_string()),
+            "id" if !value.contains('\0') => self.last_id = Some(value.to_string()),
+            "retry" => {
+                if let Ok(retry) = value.parse() {
+                    self.retry = Some(retry);
+                }
+            }
+            // Unknown fields are ignored, as the spec requires.
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    /// Ends the current event. Events without data are not dispatched.
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        self.pending = false;
+        let event = self.event.take();
+        if !self.has_data {
+            return None;
+        }
+
+        self.has_data = false;
+        let mut data = std::mem::take(&mut self.data);
+        data.pop();
+        Some(SseEvent {
+            event,
+            data,
+            id: self.last_id.clone(),
+            retry: self.retry,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(data: &str) -> SseEvent {
+        SseEvent { event: None, data: data.to_string(), id: None, retry: None }
+    }
+
+    /// Decodes `input` in one piece and byte by byte; both must agree.
+    fn decode(input: &[u8]) -> Vec<Result<SseEvent, SseError>> {
+        let mut whole = SseDecoder::new();
+        let expected = whole.feed(input);
+
+        let mut split = SseDecoder::new();
+        let bytewise: Vec<_> = input.iter().flat_map(|byte| split.feed(&[*byte])).collect();
+        assert_eq!(expected, bytewise, "byte-by-byte decoding differs");
+        expected
+    }
+
+    fn events(input: &str) -> Vec<SseEvent> {
+        decode(input.as_bytes()).into_iter().map(|event| event.unwrap()).collect()
+    }
+
+    #[test]
+    fn decodes_openai_style_stream() {
+        let input = "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n\
+                     data: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n\
+                     data: [DONE]\n\n";
+        assert_eq!(
+            events(input),
+            vec![
+                data("{\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}"),
+                data("{\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}"),
+                data("[DONE]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn every_split_point_yields_the_same_events() {
+        let input = "event: message\r\nid: 7\r\ndata: first\r\ndata: second\r\n\r\n: keep-alive\r\ndata: é✓\r\n\r\n";
+        let expected = events(input);
+        assert_eq!(expected.len(), 2);
+
+        for split in 0..=input.len() {
+            let (head, tail) = input.as_bytes().split_at(split);
+            let mut decoder = SseDecoder::new();
+            let mut got: Vec<SseEvent> = decoder.feed(head).into_iter().map(Result::unwrap).collect();
+            got.extend(decoder.feed(tail).into_iter().map(Result::unwrap));
+            assert_eq!(got, expected, "split at byte {}", split);
+            decoder.finish().unwrap();
+        }
+    }
+
+    #[test]
+    fn joins_multi_line_data() {
+        assert_eq!(events("data: a\ndata:b\ndata\ndata:  c\n\n"), vec![data("a\nb\n\n c")]);
+    }
+
+    #[test]
+    fn skips_comments_and_events_without_data() {
+        assert_eq!(events(": ping\n\nevent: noop\n\n:\ndata: x\n\n"), vec![data("x")]);
+    }
+
+    #[test]
+    fn reads_event_id_and_retry_fields() {
+        let got = events("event: delta\nid: 42\nretry: 1500\ndata: x\n\ndata: y\n\n");
+        assert_eq!(
+            got,
+            vec![
+                SseEvent { event: Some("delta".into()), data: "x".into(), id: Some("42".into()), retry: Some(1500) },
+                // The event type resets per event; id and retry persist.
+                SseEvent { event: None, data: "y".into(), id: Some("42".into()), retry: Some(1500) },
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_lf_cr_and_crlf_line_endings() {
+        let expected = vec![data("a"), data("b"), data("c")];
+        assert_eq!(events("data: a\n\ndata: b\r\rdata: c\r\n\r\n"), expected);
+    }
+
+    #[test]
+    fn crlf_split_between_chunks_is_one_line_break() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.feed(b"data: a\r").is_empty());
+        // The LF completes "\r\n"; the CR after it is the blank line.
+        assert_eq!(decoder.feed(b"\n\r"), vec![Ok(data("a"))]);
+        assert_eq!(decoder.feed(b"\ndata: b\n\n"), vec![Ok(data("b"))]);
+    }
+
+    #[test]
+    fn multibyte_characters_survive_byte_splits() {
+        assert_eq!(events("data: 你好 👋\n\n"), vec![data("你好 👋")]);
+    }
+
+    #[test]
+    fn ignores_unknown_fields_and_a_leading_bom() {
+        assert_eq!(events("\u{feff}data: a\nfoo: bar\n\n"), vec![data("a")]);
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error_and_stops_decoding() {
+        let got = decode(b"data: ok\n\ndata: \xff\xfe\n\ndata: later\n\n");
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0], Ok(data("ok")));
+        assert!(matches!(got[1], Err(SseError::InvalidUtf8 { .. })));
+    }
+
+    #[test]
+    fn truncated_event_is_reported_at_finish() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.feed(b"data: {\"choices\":").is_empty());
+        assert_eq!(
+            decoder.finish(),
+            Err(SseError::Truncated { partial: "{\"choices\":".to_string() })
+        );
+
+        let mut decoder = SseDecoder::new();
+        assert_eq!(decoder.feed(b"data: done\n\n: bye\n"), vec![Ok(data("done"))]);
+        assert_eq!(decoder.finish(), Ok(()));
+    }
+}
@@ -0,0 +1,187 @@
+use std::env;
+
+/// A section of model output that is not shown to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HiddenTag {
+    pub open: String,
+    pub close: String,
+}
+
+impl HiddenTag {
+    pub fn new(name: &str) -> Self {
+        HiddenTag {
+            open: format!("<{}>", name),
+            close: format!("</{}>", name),
+        }
+    }
+}
+
+/// The tags hidden from the UI: always `tool_request`, plus the names in
+/// ORBIT_HIDDEN_TAGS (comma-separated, default `think`).
+pub fn hidden_tags_from_env() -> Vec<HiddenTag> {
+    let extra = env::var("ORBIT_HIDDEN_TAGS").unwrap_or_else(|_| "think".to_string());
+    let mut tags = vec![HiddenTag::new("tool_request")];
+    for name in extra.split(',').map(|n| n.trim().trim_start_matches('<').trim_end_matches('>')) {
+        if !name.is_empty() && !tags.iter().any(|t| t.open == format!("<{}>", name)) {
+            tags.push(HiddenTag::new(name));
+        }
+    }
+    tags
+}
+
+/// Removes hidden sections from streamed text. Deltas may split a tag
+/// anywhere, so a trailing fragment that could still become a tag is held
+/// back until the next delta decides it.
+#[derive(Debug)]
+pub struct TagFilter {
+    tags: Vec<HiddenTag>,
+    /// Index into `tags` of the section being hidden.
+    inside: Option<usize>,
+    pending: String,
+}
+
+impl TagFilter {
+    pub fn new(tags: Vec<HiddenTag>) -> Self {
+        TagFilter { tags, inside: None, pending: String::new() }
+    }
+
+    /// Consumes a delta and returns the text that is safe to show.
+    pub fn push(&mut self, delta: &str) -> String {
+        self.pending.push_str(delta);
+        let mut visible = String::new();
+
+        loop {
+            match self.inside {
+                None => {
+                    let found = self
+                        .tags
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(i, tag)| self.pending.find(&tag.open).map(|at| (at, i)))
+                        .min();
+                    match found {
+                        Some((at, i)) => {
+                            visible.push_str(&self.pending[..at]);
+                            self.pending.drain(..at + self.tags[i].open.len());
+                            self.inside = Some(i);
+                        }
+                        None => {
+                            let keep = held_back(&self.pending, self.tags.iter().map(|t| t.open.as_str()));
+                            let cut = self.pending.len() - keep;
+                            visible.push_str(&self.pending[..cut]);
+                            self.pending.drain(..cut);
+                            return visible;
+                        }
+                    }
+                }
+                Some(i) => {
+                    let close = &self.tags[i].close;
+                    match self.pending.find(close.as_str()) {
+                        Some(at) => {
+                            self.pending.drain(..at + close.len());
+                            self.inside = None;
+                        }
+                        None => {
+                            let keep = held_back(&self.pending, std::iter::once(close.as_str()));
+                            self.pending.drain(..self.pending.len() - keep);
+                            return visible;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ends the stream. A held-back fragment that never became a tag is
+    /// visible after all; an unclosed hidden section stays hidden.
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        match self.inside.take() {
+            None => rest,
+            Some(_) => String::new(),
+        }
+    }
+}
+
+/// Length of the longest suffix of `text` that is a proper prefix of one of
+/// `markers`.
+fn held_back<'a>(text: &str, markers: impl Iterator<Item = &'a str>) -> usize {
+    markers
+        .map(|marker| {
+            (1..marker.len().min(text.len() + 1))
+                .rev()
+                .find(|&len| {
+                    let start = text.len() - len;
+                    text.is_char_boundary(start) && marker.starts_with(&text[start..])
+                })
+                .unwrap_or(0)
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_filter() -> TagFilter {
+        TagFilter::new(vec![HiddenTag::new("tool_request"), HiddenTag::new("think")])
+    }
+
+    /// Feeds the deltas, then finishes; returns everything shown.
+    fn run(deltas: &[&str]) -> String {
+        let mut filter = new_filter();
+        let mut shown: String = deltas.iter().map(|d| filter.push(d)).collect();
+        shown.push_str(&filter.finish());
+        shown
+    }
+
+    #[test]
+    fn hides_a_tag_in_one_delta() {
+        assert_eq!(run(&["Hi <tool_request>[{\"name\":\"add\"}]</tool_request> there"]), "Hi  there");
+    }
+
+    #[test]
+    fn hides_tags_split_across_deltas() {
+        let deltas = ["Let me check.", "<tool_", "request>", "[{\"name\":", "\"add\"}]</tool", "_request>", " Done"];
+        assert_eq!(run(&deltas), "Let me check. Done");
+    }
+
+    #[test]
+    fn every_split_point_gives_the_same_output() {
+        let text = "a<think>plan é</think>b <tool_request>[1]</tool_request>c ✓";
+        for split in (0..=text.len()).filter(|&i| text.is_char_boundary(i)) {
+            assert_eq!(run(&[&text[..split], &text[split..]]), "ab c ✓", "split at byte {}", split);
+        }
+        let chars: Vec<String> = text.chars().map(String::from).collect();
+        let deltas: Vec<&str> = chars.iter().map(String::as_str).collect();
+        assert_eq!(run(&deltas), "ab c ✓");
+    }
+
+    #[test]
+    fn holds_back_ambiguous_prefixes_until_decided() {
+        let mut filter = new_filter();
+        assert_eq!(filter.push("x <to"), "x ");
+        assert_eq!(filter.push("p"), "<top");
+        assert_eq!(filter.push(" a<"), " a");
+        assert_eq!(filter.push("think>secret"), "");
+        assert_eq!(filter.push("</thi"), "");
+        assert_eq!(filter.push("nk>visible"), "visible");
+    }
+
+    #[test]
+    fn finish_releases_a_dangling_prefix_but_not_an_unclosed_section() {
+        let mut filter = new_filter();
+        assert_eq!(filter.push("1 < 2 and <tool"), "1 < 2 and ");
+        assert_eq!(filter.finish(), "<tool");
+
+        let mut filter = new_filter();
+        assert_eq!(filter.push("<think>never closed"), "");
+        assert_eq!(filter.finish(), "");
+    }
+
+    #[test]
+    fn closing_tag_of_another_section_does_not_end_the_current_one() {
+        assert_eq!(run(&["<think>a</tool_request>b</think>c"]), "c");
+    }
+}
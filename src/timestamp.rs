@@ -0,0 +1,328 @@
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Timelike, Utc};
+use mysql::prelude::FromValue;
+use mysql::{FromValueError, Value};
+use mysql_common::value::convert::ParseIr;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::sync::OnceLock;
+
+static CONFIGURED_ZONE: OnceLock<Option<DbZone>> = OnceLock::new();
+static SERVER_ZONE: OnceLock<DbZone> = OnceLock::new();
+
+/// The zone naive DATETIME values in the tunispace database are in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbZone {
+    Fixed(FixedOffset),
+    /// This machine's zone; the offset is looked up per value, so daylight
+    /// saving changes are followed.
+    Local,
+}
+
+impl DbZone {
+    /// Offset in effect at a naive local time. In a DST gap or overlap the
+    /// earlier reading wins.
+    fn offset_at_local(self, naive: NaiveDateTime) -> FixedOffset {
+        match self {
+            DbZone::Fixed(offset) => offset,
+            DbZone::Local => Local
+                .offset_from_local_datetime(&naive)
+                .earliest()
+                .unwrap_or_else(|| Local.offset_from_utc_datetime(&naive))
+                .fix(),
+        }
+    }
+
+    /// Offset in effect at a UTC instant.
+    fn offset_at_utc(self, utc: NaiveDateTime) -> FixedOffset {
+        match self {
+            DbZone::Fixed(offset) => offset,
+            DbZone::Local => Local.offset_from_utc_datetime(&utc).fix(),
+        }
+    }
+}
+
+/// `DB_TIMEZONE` as configured: `UTC`, `local`, or a fixed offset such as
+/// `+02:00`. None when unset (or invalid), in which case the server's zone
+/// is used.
+fn configured_zone() -> Option<DbZone> {
+    *CONFIGURED_ZONE.get_or_init(|| {
+        let value = env::var("DB_TIMEZONE").unwrap_or_default();
+        let zone = parse_timezone(&value);
+        if zone.is_none() && !value.trim().is_empty() {
+            eprintln!("Invalid DB_TIMEZONE '{}', using the database server's zone", value);
+        }
+        zone
+    })
+}
+
+/// The zone Timestamp reads and writes DATETIME values in: `DB_TIMEZONE`
+/// when set, otherwise the zone detected from the server (this machine's
+/// zone until a connection has reported it).
+pub fn db_zone() -> DbZone {
+    configured_zone()
+        .or_else(|| SERVER_ZONE.get().copied())
+        .unwrap_or(DbZone::Local)
+}
+
+/// The `SET time_zone` value for new database sessions. Only a fixed
+/// `DB_TIMEZONE` overrides the server's zone, so `NOW()` and
+/// `CURRENT_TIMESTAMP` agree with what is read back.
+pub fn session_time_zone() -> Option<String> {
+    match configured_zone() {
+        Some(DbZone::Fixed(offset)) => Some(mysql_offset(offset)),
+        _ => None,
+    }
+}
+
+/// Whether the server's zone still has to be detected.
+pub fn needs_server_zone() -> bool {
+    configured_zone().is_none() && SERVER_ZONE.get().is_none()
+}
+
+/// Records the server's zone from its current UTC offset and
+/// `@@session.time_zone`. A server on the system zone with the same offset
+/// as this machine is taken to share its zone, DST included.
+pub fn set_server_zone(offset_seconds: i32, session_zone: &str) {
+    let local = Local::now().offset().fix().local_minus_utc();
+    let zone = if session_zone.eq_ignore_ascii_case("SYSTEM") && offset_seconds == local {
+        DbZone::Local
+    } else {
+        match FixedOffset::east_opt(offset_seconds) {
+            Some(offset) => DbZone::Fixed(offset),
+            None => return,
+        }
+    };
+    let _ = SERVER_ZONE.set(zone);
+}
+
+fn parse_timezone(value: &str) -> Option<DbZone> {
+    match value.trim() {
+        "" => None,
+        v if v.eq_ignore_ascii_case("utc") || v == "Z" => Some(DbZone::Fixed(Utc.fix())),
+        v if v.eq_ignore_ascii_case("local") => Some(DbZone::Local),
+        v => {
+            let (sign, rest) = match v.as_bytes()[0] {
+                b'+' => (1, &v[1..]),
+                b'-' => (-1, &v[1..]),
+                _ => return None,
+            };
+            let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+            let seconds = hours.parse::<i32>().ok()? * 3600 + minutes.parse::<i32>().ok()? * 60;
+            FixedOffset::east_opt(sign * seconds).map(DbZone::Fixed)
+        }
+    }
+}
+
+/// Offset in the `+HH:MM` form MySQL accepts for `SET time_zone`.
+pub fn mysql_offset(offset: FixedOffset) -> String {
+    let seconds = offset.local_minus_utc();
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.abs();
+    format!("{}{:02}:{:02}", sign, seconds / 3600, (seconds % 3600) / 60)
+}
+
+/// A point in time read from or written to a DATETIME column.
+///
+/// Stored with the database offset and serialized as ISO-8601 with that
+/// offset, e.g. `2024-03-01T14:05:00+00:00`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Timestamp(DateTime<FixedOffset>);
+
+impl Timestamp {
+    /// Interprets a naive database value according to [`db_zone`].
+    pub fn from_db(naive: NaiveDateTime) -> Self {
+        let offset = db_zone().offset_at_local(naive);
+        Timestamp(DateTime::from_naive_utc_and_offset(naive - offset, offset))
+    }
+
+    /// The naive value to compare against DATETIME columns.
+    pub fn to_db(self) -> NaiveDateTime {
+        self.0.with_timezone(&db_zone().offset_at_utc(self.0.naive_utc())).naive_local()
+    }
+
+    fn from_utc(utc: DateTime<Utc>) -> Self {
+        Timestamp(utc.with_timezone(&db_zone().offset_at_utc(utc.naive_utc())))
+    }
+
+    /// Parses a filter bound. Accepts RFC 3339 with an offset, or
+    /// `YYYY-MM-DD[ HH:MM[:SS]]` in the database timezone. A date-only upper
+    /// bound covers the whole day.
+    pub fn parse_bound(value: &str, end_of_day: bool) -> Option<Self> {
+        let value = value.trim();
+
+        if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+            return Some(Timestamp(dt));
+        }
+
+        for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"] {
+            if let Ok(naive) = NaiveDateTime::parse_from_str(value, format) {
+                return Some(Timestamp::from_db(naive));
+            }
+        }
+
+        let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+        let time = if end_of_day {
+            NaiveTime::from_hms_opt(23, 59, 59)?
+        } else {
+            NaiveTime::MIN
+        };
+        Some(Timestamp::from_db(date.and_time(time)))
+    }
+
+    pub fn now() -> Self {
+        Timestamp::from_utc(Utc::now())
+    }
+
+    /// Converts Unix seconds to a timestamp in the database offset.
+    pub fn from_unix(seconds: i64) -> Option<Self> {
+        DateTime::from_timestamp(seconds, 0).map(Timestamp::from_utc)
+    }
+
+    /// None when the result falls outside the representable date range.
+    pub fn days_before(self, days: u32) -> Option<Self> {
+        chrono::Duration::try_days(days as i64)
+            .and_then(|age| self.0.checked_sub_signed(age))
+            .map(Timestamp)
+    }
+
+    pub fn unix_seconds(self) -> i64 {
+        self.0.timestamp()
+    }
+
+    pub fn hour(self) -> u32 {
+        self.0.hour()
+    }
+
+    /// 0 = Monday.
+    pub fn weekday(self) -> u32 {
+        self.0.weekday().num_days_from_monday()
+    }
+
+    /// Consecutive calendar days have consecutive numbers.
+    pub fn day_number(self) -> i64 {
+        self.0.date_naive().num_days_from_ce() as i64
+    }
+
+    /// `YYYY-MM-DD` in the timestamp's offset.
+    pub fn date_string(self) -> String {
+        self.0.format("%Y-%m-%d").to_string()
+    }
+
+    pub fn to_rfc3339(self) -> String {
+        self.0.to_rfc3339()
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.format("%Y-%m-%d %H:%M:%S"))
+    }
+}
+
+/// Keeps the raw value around so a failed row conversion can hand it back.
+#[doc(hidden)]
+pub struct TimestampIr(Timestamp, Value);
+
+impl TryFrom<Value> for TimestampIr {
+    type Error = FromValueError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let ParseIr(naive, value) = NaiveDateTime::get_intermediate(value)?;
+        Ok(TimestampIr(Timestamp::from_db(naive), value))
+    }
+}
+
+impl From<TimestampIr> for Timestamp {
+    fn from(ir: TimestampIr) -> Self {
+        ir.0
+    }
+}
+
+impl From<TimestampIr> for Value {
+    fn from(ir: TimestampIr) -> Self {
+        ir.1
+    }
+}
+
+impl FromValue for Timestamp {
+    type Intermediate = TimestampIr;
+}
+
+impl From<Timestamp> for Value {
+    fn from(timestamp: Timestamp) -> Self {
+        Value::from(timestamp.to_db())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn parses_timezones() {
+        let offset = |seconds| Some(DbZone::Fixed(FixedOffset::east_opt(seconds).unwrap()));
+        assert_eq!(parse_timezone("UTC"), offset(0));
+        assert_eq!(parse_timezone(" z "), None);
+        assert_eq!(parse_timezone("Z"), offset(0));
+        assert_eq!(parse_timezone("+02:00"), offset(7200));
+        assert_eq!(parse_timezone("-05:30"), offset(-19800));
+        assert_eq!(parse_timezone("+5"), offset(18000));
+        assert_eq!(parse_timezone("Local"), Some(DbZone::Local));
+        assert_eq!(parse_timezone(""), None);
+        assert_eq!(parse_timezone("Europe/Paris"), None);
+        assert_eq!(parse_timezone("+25:00"), None);
+    }
+
+    #[test]
+    fn fixed_zone_offsets_do_not_depend_on_the_date() {
+        let zone = DbZone::Fixed(FixedOffset::east_opt(3600).unwrap());
+        for value in ["2024-01-15 12:00:00", "2024-07-15 12:00:00"] {
+            assert_eq!(zone.offset_at_local(naive(value)).local_minus_utc(), 3600);
+            assert_eq!(zone.offset_at_utc(naive(value)).local_minus_utc(), 3600);
+        }
+    }
+
+    #[test]
+    fn local_zone_looks_up_the_offset_per_value() {
+        for value in ["2024-01-15 12:00:00", "2024-07-15 12:00:00"] {
+            let expected = Local.offset_from_utc_datetime(&naive(value)).fix();
+            assert_eq!(DbZone::Local.offset_at_utc(naive(value)), expected);
+        }
+    }
+
+    #[test]
+    fn parses_rfc3339_bounds_with_their_offset() {
+        let bound = Timestamp::parse_bound("2024-03-01T10:00:00+02:00", false).unwrap();
+        assert_eq!(bound.unix_seconds(), 1709280000);
+    }
+
+    #[test]
+    fn parses_naive_bounds_in_the_database_zone() {
+        let parse = |value, end_of_day| Timestamp::parse_bound(value, end_of_day).map(Timestamp::to_db);
+        assert_eq!(parse("2024-03-01 14:05:09", false), Some(naive("2024-03-01 14:05:09")));
+        assert_eq!(parse("2024-03-01T14:05", false), Some(naive("2024-03-01 14:05:00")));
+        assert_eq!(parse("2024-03-01", false), Some(naive("2024-03-01 00:00:00")));
+        assert_eq!(parse("2024-03-01", true), Some(naive("2024-03-01 23:59:59")));
+        assert_eq!(parse(" 2024-03-01 ", true), Some(naive("2024-03-01 23:59:59")));
+    }
+
+    #[test]
+    fn rejects_invalid_bounds() {
+        for value in ["", "yesterday", "2024-13-01", "2024-02-30", "01/03/2024"] {
+            assert_eq!(Timestamp::parse_bound(value, false), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn days_before_stops_at_the_representable_range() {
+        let now = Timestamp::from_unix(1709280000).unwrap();
+        assert_eq!(now.days_before(1).map(Timestamp::unix_seconds), Some(1709280000 - 86400));
+        assert_eq!(now.days_before(u32::MAX), None);
+    }
+}
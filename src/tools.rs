@@ -1,5 +1,16 @@
 use serde::{Deserialize, Serialize};
-use crate::data_base::Database;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+use crate::data_base::{self, Conversation, Database, IdempotencyClaim, MessageQuery, PublicUser, ReactionScope, SearchOptions, User};
+use crate::export::{self, ExportFormat};
+use crate::db_health;
+use crate::embeddings;
+use crate::privacy::UserRedactionPolicy;
+use crate::retention;
+use crate::sql_query;
+use crate::search::SearchMode;
+use crate::timestamp::Timestamp;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Tool {
@@ -8,6 +19,38 @@ pub struct Tool {
     pub parameters: Vec<Parameter>,
 }
 
+impl Tool {
+    /// OpenAI-style function definition for native tool calling. Mutating
+    /// tools also advertise the optional idempotency_key argument.
+    pub fn to_function_schema(&self) -> serde_json::Value {
+        let mut properties = serde_json::Map::new();
+        for param in &self.parameters {
+            properties.insert(
+                param.name.clone(),
+                serde_json::json!({ "type": param.param_type, "description": param.description }),
+            );
+        }
+        if is_mutating_tool(&self.name) {
+            properties.insert(
+                "idempotency_key".to_string(),
+                serde_json::json!({
+                    "type": "string",
+                    "description": "Optional. Reuse the same key when retrying this exact call; a repeat returns the original result",
+                }),
+            );
+        }
+
+        serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": self.name,
+                "description": self.description,
+                "parameters": { "type": "object", "properties": properties },
+            },
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Parameter {
     pub name: String,
@@ -28,6 +71,13 @@ pub struct ToolResult {
     pub error: Option<String>,
 }
 
+/// Who tools act on behalf of. A chat session is bound to one tunispace user
+/// and every database tool checks access as that user.
+#[derive(Debug, Clone, Default)]
+pub struct ToolContext {
+    pub acting_username: Option<String>,
+}
+
 pub fn get_available_tools() -> Vec<Tool> {
     vec![
         // Mathematical tools
@@ -125,7 +175,7 @@ pub fn get_available_tools() -> Vec<Tool> {
         // Database tools
         Tool {
             name: "get_conversation_summary".to_string(),
-            description: "Get a summary of messages in a conversation".to_string(),
+            description: "Get the most recent messages of a conversation, with cursors to page through older or newer history".to_string(),
             parameters: vec![
                 Parameter {
                     name: "conversation_id".to_string(),
@@ -137,11 +187,47 @@ pub fn get_available_tools() -> Vec<Tool> {
                     param_type: "number".to_string(),
                     description: "Number of recent messages to include (default: 50)".to_string(),
                 },
+                Parameter {
+                    name: "before_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Only messages older than this message id; pass a previous older_cursor to page back (optional)".to_string(),
+                },
+                Parameter {
+                    name: "after_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Only messages newer than this message id; pass a previous newer_cursor to page forward (optional)".to_string(),
+                },
+                Parameter {
+                    name: "since".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Only return messages on or after this date, YYYY-MM-DD[ HH:MM:SS] in the database timezone or ISO-8601 with an offset (optional)".to_string(),
+                },
+                Parameter {
+                    name: "until".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Only return messages on or before this date, YYYY-MM-DD[ HH:MM:SS] in the database timezone or ISO-8601 with an offset (optional)".to_string(),
+                },
+            ],
+        },
+        Tool {
+            name: "get_cached_summary".to_string(),
+            description: "Get the stored summary of a conversation with a freshness indicator, plus only the messages written since it was saved. Use this before summarizing from scratch".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "conversation_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "ID of the conversation".to_string(),
+                },
+                Parameter {
+                    name: "max_new_messages".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Maximum number of new messages to return (default: 100, max: 500)".to_string(),
+                },
             ],
         },
         Tool {
             name: "search_conversation".to_string(),
-            description: "Search for messages in a conversation".to_string(),
+            description: "Full-text search for messages in a conversation, ranked by relevance with highlighted snippets".to_string(),
             parameters: vec![
                 Parameter {
                     name: "conversation_id".to_string(),
@@ -151,13 +237,94 @@ pub fn get_available_tools() -> Vec<Tool> {
                 Parameter {
                     name: "search_term".to_string(),
                     param_type: "string".to_string(),
-                    description: "Text to search for in messages".to_string(),
+                    description: "Search query. In boolean mode supports +required, -excluded, \"exact phrase\" and prefix* terms".to_string(),
+                },
+                Parameter {
+                    name: "mode".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Query mode: 'boolean' or 'natural' (default: boolean)".to_string(),
+                },
+                Parameter {
+                    name: "author".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Only return messages from this username or email (optional)".to_string(),
+                },
+                Parameter {
+                    name: "before_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Only messages with an id lower than this (optional). Results are ranked by relevance, not paged".to_string(),
+                },
+                Parameter {
+                    name: "after_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Only messages with an id higher than this (optional). Results are ranked by relevance, not paged".to_string(),
+                },
+                Parameter {
+                    name: "since".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Only return messages on or after this date, YYYY-MM-DD[ HH:MM:SS] in the database timezone or ISO-8601 with an offset (optional)".to_string(),
+                },
+                Parameter {
+                    name: "until".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Only return messages on or before this date, YYYY-MM-DD[ HH:MM:SS] in the database timezone or ISO-8601 with an offset (optional)".to_string(),
+                },
+                Parameter {
+                    name: "limit".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Maximum number of results (default: 20, max: 100)".to_string(),
+                },
+            ],
+        },
+        Tool {
+            name: "search_all_conversations".to_string(),
+            description: "Full-text search across every conversation the session's user belongs to, with hits grouped by conversation".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "search_term".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Search query. In boolean mode supports +required, -excluded, \"exact phrase\" and prefix* terms".to_string(),
+                },
+                Parameter {
+                    name: "mode".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Query mode: 'boolean' or 'natural' (default: boolean)".to_string(),
+                },
+                Parameter {
+                    name: "author".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Only return messages from this username or email (optional)".to_string(),
+                },
+                Parameter {
+                    name: "before_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Only messages with an id lower than this (optional). Results are ranked by relevance, not paged".to_string(),
+                },
+                Parameter {
+                    name: "after_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Only messages with an id higher than this (optional). Results are ranked by relevance, not paged".to_string(),
+                },
+                Parameter {
+                    name: "since".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Only return messages on or after this date, YYYY-MM-DD[ HH:MM:SS] in the database timezone or ISO-8601 with an offset (optional)".to_string(),
+                },
+                Parameter {
+                    name: "until".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Only return messages on or before this date, YYYY-MM-DD[ HH:MM:SS] in the database timezone or ISO-8601 with an offset (optional)".to_string(),
+                },
+                Parameter {
+                    name: "limit".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Maximum number of results across all conversations (default: 20, max: 100)".to_string(),
                 },
             ],
         },
         Tool {
             name: "send_message".to_string(),
-            description: "Send a message to a conversation as a specific user".to_string(),
+            description: "Send a message to a conversation as the session's user".to_string(),
             parameters: vec![
                 Parameter {
                     name: "conversation_id".to_string(),
@@ -165,14 +332,77 @@ pub fn get_available_tools() -> Vec<Tool> {
                     description: "ID of the conversation".to_string(),
                 },
                 Parameter {
-                    name: "username".to_string(),
+                    name: "content".to_string(),
                     param_type: "string".to_string(),
-                    description: "Username of the sender".to_string(),
+                    description: "Message content".to_string(),
                 },
+            ],
+        },
+        Tool {
+            name: "run_sql_query".to_string(),
+            description: "Run a single read-only SELECT against the user, conversations, conversation_users and messages tables for questions the other tools cannot answer (staff only). List columns explicitly; results are capped with a LIMIT".to_string(),
+            parameters: vec![
                 Parameter {
-                    name: "content".to_string(),
+                    name: "sql".to_string(),
                     param_type: "string".to_string(),
-                    description: "Message content".to_string(),
+                    description: "The SELECT statement (MySQL syntax)".to_string(),
+                },
+            ],
+        },
+        Tool {
+            name: "get_retention_rules".to_string(),
+            description: "List message retention rules: per conversation, for all group or all direct conversations, and global (staff only)".to_string(),
+            parameters: vec![],
+        },
+        Tool {
+            name: "set_retention_rule".to_string(),
+            description: "Set or remove a retention rule (staff only). The most specific rule wins: conversation, then group/direct, then global. Conversations without any rule keep everything".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "scope".to_string(),
+                    param_type: "string".to_string(),
+                    description: "conversation, group, direct or global".to_string(),
+                },
+                Parameter {
+                    name: "conversation_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Conversation the rule is for (scope conversation only)".to_string(),
+                },
+                Parameter {
+                    name: "max_age_days".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Delete messages older than this many days (at most 36500); 0 keeps them forever".to_string(),
+                },
+                Parameter {
+                    name: "remove".to_string(),
+                    param_type: "boolean".to_string(),
+                    description: "Remove the rule instead of setting it (default: false)".to_string(),
+                },
+            ],
+        },
+        Tool {
+            name: "preview_retention_purge".to_string(),
+            description: "Dry run of the retention purge: which conversations would lose how many messages (staff only). Nothing is deleted".to_string(),
+            parameters: vec![],
+        },
+        Tool {
+            name: "semantic_search".to_string(),
+            description: "Find messages with a similar meaning to a query across the session user's conversations, including paraphrases that keyword search misses".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "query".to_string(),
+                    param_type: "string".to_string(),
+                    description: "What the messages should be about".to_string(),
+                },
+                Parameter {
+                    name: "conversation_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Only search this conversation (optional)".to_string(),
+                },
+                Parameter {
+                    name: "limit".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Maximum number of results (default: 10, max: 50)".to_string(),
                 },
             ],
         },
@@ -198,9 +428,105 @@ pub fn get_available_tools() -> Vec<Tool> {
                 },
             ],
         },
+        Tool {
+            name: "get_conversation_analytics".to_string(),
+            description: "Get detailed activity analytics for a conversation: messages per participant, activity by hour and weekday, response times, active-day streaks and the largest reply chains".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "conversation_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "ID of the conversation".to_string(),
+                },
+                Parameter {
+                    name: "top_chains".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Number of reply chains to return (default: 5, max: 20)".to_string(),
+                },
+            ],
+        },
+        Tool {
+            name: "export_conversation".to_string(),
+            description: "Export an entire conversation with authors, replies and reactions. Returns a temporary download link".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "conversation_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "ID of the conversation".to_string(),
+                },
+                Parameter {
+                    name: "format".to_string(),
+                    param_type: "string".to_string(),
+                    description: "'md' (default), 'json', 'csv' or 'html'".to_string(),
+                },
+            ],
+        },
+        Tool {
+            name: "get_message_thread".to_string(),
+            description: "Get a message with the messages it replies to and the full tree of replies it received".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "message_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "ID of the message".to_string(),
+                },
+            ],
+        },
+        Tool {
+            name: "get_message_context".to_string(),
+            description: "Get a single message with the surrounding messages of its conversation".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "message_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "ID of the message".to_string(),
+                },
+                Parameter {
+                    name: "before".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Number of earlier messages to include (default: 5)".to_string(),
+                },
+                Parameter {
+                    name: "after".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Number of later messages to include (default: 5)".to_string(),
+                },
+            ],
+        },
+        Tool {
+            name: "get_reaction_stats".to_string(),
+            description: "Aggregate reactions by type, by the author who received them, and find the most reacted messages. Scope by conversation or by author".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "conversation_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Conversation to aggregate (optional if username is given)".to_string(),
+                },
+                Parameter {
+                    name: "username".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Aggregate reactions received by this user across conversations (optional)".to_string(),
+                },
+                Parameter {
+                    name: "top".to_string(),
+                    param_type: "number".to_string(),
+                    description: "Number of most reacted messages to return (default: 10)".to_string(),
+                },
+            ],
+        },
+        Tool {
+            name: "get_message_history".to_string(),
+            description: "Get the edit and delete history of a message".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "message_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "ID of the message".to_string(),
+                },
+            ],
+        },
         Tool {
             name: "list_all_conversations".to_string(),
-            description: "List all conversations in the database".to_string(),
+            description: "List the conversations visible to the session's user (all conversations for admins)".to_string(),
             parameters: vec![],
         },
         Tool {
@@ -214,52 +540,258 @@ pub fn get_available_tools() -> Vec<Tool> {
                 },
             ],
         },
-    ]
-}
-
-pub fn execute_tool(tool_call: &ToolCall) -> ToolResult {
-    match tool_call.name.as_str() {
-        // Mathematical tools
-        "add" => execute_add(tool_call),
-        "subtract" => execute_subtract(tool_call),
-        "multiply" => execute_multiply(tool_call),
-        "divide" => execute_divide(tool_call),
-        "power" => execute_power(tool_call),
-        "sqrt" => execute_sqrt(tool_call),
-
-        // Database tools
-        "get_conversation_summary" => execute_get_conversation_summary(tool_call),
-        "search_conversation" => execute_search_conversation(tool_call),
-        "send_message" => execute_send_message(tool_call),
-        "get_user_conversations" => execute_get_user_conversations(tool_call),
-        "get_conversation_stats" => execute_get_conversation_stats(tool_call),
-        "list_all_conversations" => execute_list_all_conversations(tool_call),
-        "find_user" => execute_find_user(tool_call),
-
-        _ => ToolResult {
-            success: false,
-            result: serde_json::json!(null),
-            error: Some(format!("Unknown tool: {}", tool_call.name)),
+        // Database write tools
+        Tool {
+            name: "create_conversation".to_string(),
+            description: "Create a direct or group conversation with the session's user as creator (admin of groups). A direct conversation that already exists between the two users is returned instead of creating a duplicate".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "participants".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Comma-separated usernames to add besides the session's user".to_string(),
+                },
+                Parameter {
+                    name: "title".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Conversation title (required for groups)".to_string(),
+                },
+                Parameter {
+                    name: "is_group".to_string(),
+                    param_type: "boolean".to_string(),
+                    description: "Create a group conversation (default: true when more than one participant)".to_string(),
+                },
+                Parameter {
+                    name: "first_message".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Message to post as the session's user; written in the same transaction as the conversation (optional)".to_string(),
+                },
+            ],
         },
-    }
-}
-
-// ===== MATHEMATICAL TOOL IMPLEMENTATIONS =====
-
-fn execute_add(tool_call: &ToolCall) -> ToolResult {
-    let a = tool_call.arguments["a"].as_f64();
-    let b = tool_call.arguments["b"].as_f64();
-
-    match (a, b) {
-        (Some(a), Some(b)) => ToolResult {
-            success: true,
-            result: serde_json::json!(a + b),
-            error: None,
+        Tool {
+            name: "rename_conversation".to_string(),
+            description: "Rename a conversation. Group conversations require the session's user to be an admin".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "conversation_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "ID of the conversation".to_string(),
+                },
+                Parameter {
+                    name: "title".to_string(),
+                    param_type: "string".to_string(),
+                    description: "New title".to_string(),
+                },
+            ],
         },
-        _ => ToolResult {
-            success: false,
-            result: serde_json::json!(null),
-            error: Some("Invalid arguments for add".to_string()),
+        Tool {
+            name: "add_participant".to_string(),
+            description: "Add a user to a group conversation. Requires the session's user to be an admin".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "conversation_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "ID of the group conversation".to_string(),
+                },
+                Parameter {
+                    name: "username".to_string(),
+                    param_type: "string".to_string(),
+                    description: "User to add".to_string(),
+                },
+                Parameter {
+                    name: "is_admin".to_string(),
+                    param_type: "boolean".to_string(),
+                    description: "Make the new participant an admin (default: false)".to_string(),
+                },
+            ],
+        },
+        Tool {
+            name: "remove_participant".to_string(),
+            description: "Remove a user from a group conversation. Requires the session's user to be an admin, unless they remove themselves".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "conversation_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "ID of the group conversation".to_string(),
+                },
+                Parameter {
+                    name: "username".to_string(),
+                    param_type: "string".to_string(),
+                    description: "User to remove".to_string(),
+                },
+            ],
+        },
+        Tool {
+            name: "edit_message".to_string(),
+            description: "Edit a message written by the session's user. The previous content is kept in the edit history".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "message_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "ID of the message".to_string(),
+                },
+                Parameter {
+                    name: "content".to_string(),
+                    param_type: "string".to_string(),
+                    description: "New message content".to_string(),
+                },
+            ],
+        },
+        Tool {
+            name: "delete_message".to_string(),
+            description: "Delete a message. Allowed for its author and for admins of the group conversation. The content is kept in the edit history".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "message_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "ID of the message".to_string(),
+                },
+            ],
+        },
+        Tool {
+            name: "save_conversation_summary".to_string(),
+            description: "Store a summary of a conversation covering messages up to covers_through_id. Merge the cached summary with the new messages before saving".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "conversation_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "ID of the conversation".to_string(),
+                },
+                Parameter {
+                    name: "summary".to_string(),
+                    param_type: "string".to_string(),
+                    description: "The complete merged summary".to_string(),
+                },
+                Parameter {
+                    name: "covers_through_id".to_string(),
+                    param_type: "number".to_string(),
+                    description: "covers_through_id from get_cached_summary".to_string(),
+                },
+            ],
+        },
+    ]
+}
+
+/// Tools that write to the chat database. They are listed separately in the
+/// prompt, flagged in tool_result events and refused in read-only mode.
+pub const MUTATING_TOOLS: &[&str] = &[
+    "send_message",
+    "create_conversation",
+    "rename_conversation",
+    "add_participant",
+    "remove_participant",
+    "edit_message",
+    "delete_message",
+    "save_conversation_summary",
+    "set_retention_rule",
+];
+
+pub fn is_mutating_tool(name: &str) -> bool {
+    MUTATING_TOOLS.contains(&name)
+}
+
+pub fn is_math_tool(name: &str) -> bool {
+    matches!(name, "add" | "subtract" | "multiply" | "divide" | "power" | "sqrt")
+}
+
+/// Set ORBIT_READ_ONLY=1 to refuse every mutating tool.
+fn read_only_mode() -> bool {
+    std::env::var("ORBIT_READ_ONLY")
+        .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+pub async fn execute_tool(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    if is_mutating_tool(&tool_call.name) && read_only_mode() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Tool '{}' modifies the database and Orbit is running in read-only mode", tool_call.name)),
+        };
+    }
+
+    // Fail fast during an outage instead of waiting on a connect timeout.
+    if !is_math_tool(&tool_call.name) && !db_health::is_available() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!({ "error_kind": "DatabaseUnavailable" }),
+            error: Some("The database is currently unavailable; Orbit is reconnecting in the background. Do not retry this tool until it is back.".to_string()),
+        };
+    }
+
+    if is_mutating_tool(&tool_call.name) {
+        if let (Some(key), Some(username)) = (idempotency_key(tool_call), ctx.acting_username.as_deref()) {
+            return execute_idempotent(tool_call, ctx, username, &key).await;
+        }
+    }
+
+    dispatch_tool(tool_call, ctx).await
+}
+
+async fn dispatch_tool(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    match tool_call.name.as_str() {
+        // Mathematical tools
+        "add" => execute_add(tool_call),
+        "subtract" => execute_subtract(tool_call),
+        "multiply" => execute_multiply(tool_call),
+        "divide" => execute_divide(tool_call),
+        "power" => execute_power(tool_call),
+        "sqrt" => execute_sqrt(tool_call),
+
+        // Database tools
+        "get_conversation_summary" => execute_get_conversation_summary(tool_call, ctx),
+        "search_conversation" => execute_search_conversation(tool_call, ctx),
+        "search_all_conversations" => execute_search_all_conversations(tool_call, ctx),
+        "send_message" => execute_send_message(tool_call, ctx),
+        "get_user_conversations" => execute_get_user_conversations(tool_call, ctx),
+        "get_conversation_stats" => execute_get_conversation_stats(tool_call, ctx),
+        "get_conversation_analytics" => execute_get_conversation_analytics(tool_call, ctx),
+        "export_conversation" => execute_export_conversation(tool_call, ctx),
+        "get_message_thread" => execute_get_message_thread(tool_call, ctx),
+        "get_message_context" => execute_get_message_context(tool_call, ctx),
+        "get_reaction_stats" => execute_get_reaction_stats(tool_call, ctx),
+        "get_message_history" => execute_get_message_history(tool_call, ctx),
+        "get_cached_summary" => execute_get_cached_summary(tool_call, ctx),
+        "semantic_search" => execute_semantic_search(tool_call, ctx).await,
+        "run_sql_query" => execute_run_sql_query(tool_call, ctx),
+        "get_retention_rules" => execute_get_retention_rules(ctx),
+        "set_retention_rule" => execute_set_retention_rule(tool_call, ctx),
+        "preview_retention_purge" => execute_preview_retention_purge(ctx),
+        "list_all_conversations" => execute_list_all_conversations(ctx),
+        "find_user" => execute_find_user(tool_call, ctx),
+
+        // Database write tools
+        "create_conversation" => execute_create_conversation(tool_call, ctx),
+        "rename_conversation" => execute_rename_conversation(tool_call, ctx),
+        "add_participant" => execute_add_participant(tool_call, ctx),
+        "remove_participant" => execute_remove_participant(tool_call, ctx),
+        "edit_message" => execute_edit_message(tool_call, ctx),
+        "delete_message" => execute_delete_message(tool_call, ctx),
+        "save_conversation_summary" => execute_save_conversation_summary(tool_call, ctx),
+
+        _ => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Unknown tool: {}", tool_call.name)),
+        },
+    }
+}
+
+// ===== MATHEMATICAL TOOL IMPLEMENTATIONS =====
+
+fn execute_add(tool_call: &ToolCall) -> ToolResult {
+    let a = tool_call.arguments["a"].as_f64();
+    let b = tool_call.arguments["b"].as_f64();
+
+    match (a, b) {
+        (Some(a), Some(b)) => ToolResult {
+            success: true,
+            result: serde_json::json!(a + b),
+            error: None,
+        },
+        _ => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("Invalid arguments for add".to_string()),
         },
     }
 }
@@ -375,98 +907,134 @@ fn execute_sqrt(tool_call: &ToolCall) -> ToolResult {
 
 // ===== DATABASE TOOL IMPLEMENTATIONS =====
 
-fn execute_get_conversation_summary(tool_call: &ToolCall) -> ToolResult {
-    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
-    let message_limit = tool_call.arguments["message_limit"].as_i64().unwrap_or(50) as i32;
+fn permission_denied(reason: String) -> ToolResult {
+    ToolResult {
+        success: false,
+        result: serde_json::json!({ "error_kind": "PermissionDenied" }),
+        error: Some(format!("Permission denied: {}", reason)),
+    }
+}
 
-    match Database::new() {
-        Ok(db) => match db.get_conversation_summary(conversation_id, message_limit) {
-            Ok(summary) => ToolResult {
-                success: true,
-                result: serde_json::json!(summary),
-                error: None,
-            },
-            Err(e) => ToolResult {
-                success: false,
-                result: serde_json::json!(null),
-                error: Some(format!("Database error: {}", e)),
-            },
-        },
-        Err(e) => ToolResult {
+/// chat_role values that count as staff unless ORBIT_STAFF_ROLES lists others.
+const DEFAULT_STAFF_ROLES: &[&str] = &["admin"];
+
+/// Users whose chat_role grants read access to every conversation. Roles
+/// must match an entry of ORBIT_STAFF_ROLES (comma-separated) exactly.
+fn is_staff(user: &User) -> bool {
+    let role = user.chat_role.trim();
+    match std::env::var("ORBIT_STAFF_ROLES") {
+        Ok(roles) => roles.split(',').map(str::trim).any(|r| !r.is_empty() && r.eq_ignore_ascii_case(role)),
+        Err(_) => DEFAULT_STAFF_ROLES.iter().any(|r| r.eq_ignore_ascii_case(role)),
+    }
+}
+
+/// Resolves the session's user; database tools refuse to run without one.
+fn require_acting_user(db: &Database, ctx: &ToolContext) -> Result<User, ToolResult> {
+    let username = match ctx.acting_username.as_deref() {
+        Some(username) if !username.is_empty() => username,
+        _ => return Err(permission_denied("no tunispace user is bound to this session".to_string())),
+    };
+
+    match db.find_user_by_username(username) {
+        Ok(Some(user)) if user.is_active => Ok(user),
+        Ok(Some(_)) => Err(permission_denied(format!("user '{}' is inactive", username))),
+        Ok(None) => Err(permission_denied(format!("session user '{}' does not exist", username))),
+        Err(e) => Err(ToolResult {
             success: false,
             result: serde_json::json!(null),
-            error: Some(format!("Failed to connect to database: {}", e)),
-        },
+            error: Some(format!("Database error: {}", e)),
+        }),
     }
 }
 
-fn execute_search_conversation(tool_call: &ToolCall) -> ToolResult {
-    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
-    let search_term = tool_call.arguments["search_term"].as_str().unwrap_or("");
+/// Reading a conversation requires membership unless the user is staff.
+fn authorize_conversation(db: &Database, user: &User, conversation_id: i32) -> Result<(), ToolResult> {
+    if is_staff(user) {
+        return Ok(());
+    }
 
-    match Database::new() {
-        Ok(db) => match db.search_messages(conversation_id, search_term) {
-            Ok(messages) => ToolResult {
-                success: true,
-                result: serde_json::json!({
-                    "found": messages.len(),
-                    "messages": messages,
-                }),
-                error: None,
-            },
-            Err(e) => ToolResult {
-                success: false,
-                result: serde_json::json!(null),
-                error: Some(format!("Search error: {}", e)),
-            },
-        },
-        Err(e) => ToolResult {
+    match db.user_in_conversation(conversation_id, user.id) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(permission_denied(format!(
+            "'{}' is not a participant of conversation {}",
+            user.username, conversation_id
+        ))),
+        Err(e) => Err(ToolResult {
             success: false,
             result: serde_json::json!(null),
-            error: Some(format!("Failed to connect to database: {}", e)),
-        },
+            error: Some(format!("Database error: {}", e)),
+        }),
     }
 }
 
-fn execute_send_message(tool_call: &ToolCall) -> ToolResult {
-    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
-    let username = tool_call.arguments["username"].as_str().unwrap_or("");
-    let content = tool_call.arguments["content"].as_str().unwrap_or("");
+/// Checks that the session user may watch a conversation's live feed.
+pub fn authorize_watch(conversation_id: i32, ctx: &ToolContext) -> Result<(), String> {
+    let db = Database::new().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let user = require_acting_user(&db, ctx).map_err(|result| result.error.unwrap_or_default())?;
 
-    if username.is_empty() || content.is_empty() {
-        return ToolResult {
+    match db.find_conversation_by_id(conversation_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(format!("Conversation {} not found", conversation_id)),
+        Err(e) => return Err(format!("Database error: {}", e)),
+    }
+
+    authorize_conversation(&db, &user, conversation_id).map_err(|result| result.error.unwrap_or_default())
+}
+
+/// Reads an optional date bound argument, reporting malformed values as a tool error.
+fn parse_date_argument(tool_call: &ToolCall, key: &str, end_of_day: bool) -> Result<Option<Timestamp>, ToolResult> {
+    let value = match tool_call.arguments[key].as_str().map(str::trim) {
+        Some(value) if !value.is_empty() => value,
+        _ => return Ok(None),
+    };
+
+    match Timestamp::parse_bound(value, end_of_day) {
+        Some(bound) => Ok(Some(bound)),
+        None => Err(ToolResult {
             success: false,
             result: serde_json::json!(null),
-            error: Some("Username and content are required".to_string()),
-        };
+            error: Some(format!("Invalid {} date '{}', expected YYYY-MM-DD[ HH:MM:SS] or ISO-8601 with an offset", key, value)),
+        }),
     }
+}
+
+fn execute_get_conversation_summary(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
+    let message_limit = tool_call.arguments["message_limit"].as_i64().unwrap_or(50).clamp(1, 500) as i32;
+
+    let since = match parse_date_argument(tool_call, "since", false) {
+        Ok(since) => since,
+        Err(result) => return result,
+    };
+    let until = match parse_date_argument(tool_call, "until", true) {
+        Ok(until) => until,
+        Err(result) => return result,
+    };
+
+    let query = MessageQuery {
+        limit: message_limit,
+        before_id: tool_call.arguments["before_id"].as_i64().map(|id| id as i32),
+        after_id: tool_call.arguments["after_id"].as_i64().map(|id| id as i32),
+        since,
+        until,
+    };
 
     match Database::new() {
         Ok(db) => {
-            match db.find_user_by_username(username) {
-                Ok(Some(user)) => {
-                    match db.insert_message(conversation_id, user.id, content, None) {
-                        Ok(message_id) => ToolResult {
-                            success: true,
-                            result: serde_json::json!({
-                                "message_id": message_id,
-                                "conversation_id": conversation_id,
-                                "user": username,
-                                "content": content,
-                            }),
-                            error: None,
-                        },
-                        Err(e) => ToolResult {
-                            success: false,
-                            result: serde_json::json!(null),
-                            error: Some(format!("Failed to send message: {}", e)),
-                        },
-                    }
-                },
-                Ok(None) => ToolResult {
-                    success: false,
-                    result: serde_json::json!(null),
-                    error: Some(format!("User '{}' not found", username)),
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+
+            if let Err(result) = authorize_conversation(&db, &user, conversation_id) {
+                return result;
+            }
+
+            match db.get_conversation_summary(conversation_id, &query) {
+                Ok(summary) => ToolResult {
+                    success: true,
+                    result: serde_json::json!(summary),
+                    error: None,
                 },
                 Err(e) => ToolResult {
                     success: false,
@@ -483,46 +1051,102 @@ fn execute_send_message(tool_call: &ToolCall) -> ToolResult {
     }
 }
 
-fn execute_get_user_conversations(tool_call: &ToolCall) -> ToolResult {
-    let username = tool_call.arguments["username"].as_str().unwrap_or("");
+/// Builds search options from the shared search_conversation / search_all_conversations arguments.
+fn search_options_from_args(db: &Database, tool_call: &ToolCall) -> Result<SearchOptions, ToolResult> {
+    let search_term = tool_call.arguments["search_term"].as_str().unwrap_or("").trim();
+    let mode = tool_call.arguments["mode"].as_str().unwrap_or("boolean");
+    let author = tool_call.arguments["author"].as_str().unwrap_or("").trim();
+    let limit = tool_call.arguments["limit"].as_i64().unwrap_or(20).clamp(1, 100) as i32;
 
-    if username.is_empty() {
-        return ToolResult {
+    if search_term.is_empty() {
+        return Err(ToolResult {
             success: false,
             result: serde_json::json!(null),
-            error: Some("Username is required".to_string()),
-        };
+            error: Some("Search term is required".to_string()),
+        });
     }
 
-    match Database::new() {
-        Ok(db) => {
-            match db.find_user_by_username(username) {
-                Ok(Some(user)) => {
-                    match db.find_conversations_by_user(user.id) {
-                        Ok(conversations) => ToolResult {
-                            success: true,
-                            result: serde_json::json!({
-                                "user": username,
-                                "conversations": conversations,
-                            }),
-                            error: None,
-                        },
-                        Err(e) => ToolResult {
-                            success: false,
-                            result: serde_json::json!(null),
-                            error: Some(format!("Failed to get conversations: {}", e)),
-                        },
-                    }
-                },
-                Ok(None) => ToolResult {
+    let mode = SearchMode::parse(mode).ok_or_else(|| ToolResult {
+        success: false,
+        result: serde_json::json!(null),
+        error: Some(format!("Invalid mode '{}', expected 'boolean' or 'natural'", mode)),
+    })?;
+
+    let since = parse_date_argument(tool_call, "since", false)?;
+    let until = parse_date_argument(tool_call, "until", true)?;
+
+    let author_id = if author.is_empty() {
+        None
+    } else {
+        let user = match db.find_user_by_username(author) {
+            Ok(None) => db.find_user_by_email(author),
+            other => other,
+        };
+        match user {
+            Ok(Some(user)) => Some(user.id),
+            Ok(None) => {
+                return Err(ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Author '{}' not found", author)),
+                })
+            }
+            Err(e) => {
+                return Err(ToolResult {
                     success: false,
                     result: serde_json::json!(null),
-                    error: Some(format!("User '{}' not found", username)),
+                    error: Some(format!("Database error: {}", e)),
+                })
+            }
+        }
+    };
+
+    Ok(SearchOptions {
+        query: search_term.to_string(),
+        mode,
+        author_id,
+        before_id: tool_call.arguments["before_id"].as_i64().map(|id| id as i32),
+        after_id: tool_call.arguments["after_id"].as_i64().map(|id| id as i32),
+        since,
+        until,
+        limit,
+    })
+}
+
+fn execute_search_conversation(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
+
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+
+            if let Err(result) = authorize_conversation(&db, &user, conversation_id) {
+                return result;
+            }
+
+            let options = match search_options_from_args(&db, tool_call) {
+                Ok(options) => options,
+                Err(result) => return result,
+            };
+
+            match db.search_messages(conversation_id, &options) {
+                Ok((hits, mode)) => ToolResult {
+                    success: true,
+                    result: serde_json::json!({
+                        "found": hits.len(),
+                        "query": options.query,
+                        "mode": mode.as_str(),
+                        "hits": hits,
+                    }),
+                    error: None,
                 },
                 Err(e) => ToolResult {
                     success: false,
                     result: serde_json::json!(null),
-                    error: Some(format!("Database error: {}", e)),
+                    error: Some(format!("Search error: {}", e)),
                 },
             }
         },
@@ -534,21 +1158,37 @@ fn execute_get_user_conversations(tool_call: &ToolCall) -> ToolResult {
     }
 }
 
-fn execute_get_conversation_stats(tool_call: &ToolCall) -> ToolResult {
-    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
-
+fn execute_search_all_conversations(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
     match Database::new() {
-        Ok(db) => match db.get_conversation_statistics(conversation_id) {
-            Ok(stats) => ToolResult {
-                success: true,
-                result: stats,
-                error: None,
-            },
-            Err(e) => ToolResult {
-                success: false,
-                result: serde_json::json!(null),
-                error: Some(format!("Failed to get statistics: {}", e)),
-            },
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+
+            let options = match search_options_from_args(&db, tool_call) {
+                Ok(options) => options,
+                Err(result) => return result,
+            };
+
+            match db.search_messages_global(user.id, &options) {
+                Ok((groups, mode)) => ToolResult {
+                    success: true,
+                    result: serde_json::json!({
+                        "found": groups.iter().map(|g| g.hits.len()).sum::<usize>(),
+                        "conversations_matched": groups.len(),
+                        "query": options.query,
+                        "mode": mode.as_str(),
+                        "conversations": groups,
+                    }),
+                    error: None,
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Search error: {}", e)),
+                },
+            }
         },
         Err(e) => ToolResult {
             success: false,
@@ -558,22 +1198,1092 @@ fn execute_get_conversation_stats(tool_call: &ToolCall) -> ToolResult {
     }
 }
 
-fn execute_list_all_conversations(tool_call: &ToolCall) -> ToolResult {
-    match Database::new() {
-        Ok(db) => match db.get_all_conversations() {
-            Ok(conversations) => ToolResult {
-                success: true,
-                result: serde_json::json!({
-                    "total": conversations.len(),
-                    "conversations": conversations,
-                }),
-                error: None,
-            },
-            Err(e) => ToolResult {
-                success: false,
+fn execute_send_message(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
+    let content = tool_call.arguments["content"].as_str().unwrap_or("");
+
+    if content.is_empty() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("Content is required".to_string()),
+        };
+    }
+
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+
+            // Posting always requires membership, even for staff accounts.
+            match db.user_in_conversation(conversation_id, user.id) {
+                Ok(true) => {}
+                Ok(false) => {
+                    return permission_denied(format!(
+                        "'{}' is not a participant of conversation {}",
+                        user.username, conversation_id
+                    ))
+                }
+                Err(e) => {
+                    return ToolResult {
+                        success: false,
+                        result: serde_json::json!(null),
+                        error: Some(format!("Database error: {}", e)),
+                    }
+                }
+            }
+
+            match db.insert_message(conversation_id, user.id, content, None) {
+                Ok(message_id) => {
+                    undoable_messages().insert(message_id, (Instant::now(), user.id));
+                    ToolResult {
+                        success: true,
+                        result: serde_json::json!({
+                            "message_id": message_id,
+                            "conversation_id": conversation_id,
+                            "user": user.username,
+                            "content": content,
+                            "undo": {
+                                "message_id": message_id,
+                                "expires_in_secs": undo_window().as_secs(),
+                            },
+                        }),
+                        error: None,
+                    }
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to send message: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_get_user_conversations(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let username = tool_call.arguments["username"].as_str().unwrap_or("");
+
+    if username.is_empty() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("Username is required".to_string()),
+        };
+    }
+
+    match Database::new() {
+        Ok(db) => {
+            let viewer = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+            let user = match require_user(&db, username) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+
+            // Other users' conversations are limited to the ones the acting
+            // user can see as well.
+            let conversations = if viewer.id == user.id || is_staff(&viewer) {
+                db.find_conversations_by_user(user.id)
+            } else {
+                db.find_conversations_by_user(user.id).and_then(|conversations| {
+                    let visible: Vec<i32> = db.find_conversations_by_user(viewer.id)?.iter().map(|c| c.id).collect();
+                    Ok(conversations.into_iter().filter(|c| visible.contains(&c.id)).collect())
+                })
+            };
+
+            match conversations {
+                Ok(conversations) => ToolResult {
+                    success: true,
+                    result: serde_json::json!({
+                        "user": username,
+                        "conversations": conversations,
+                    }),
+                    error: None,
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to get conversations: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_get_conversation_stats(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
+
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+
+            if let Err(result) = authorize_conversation(&db, &user, conversation_id) {
+                return result;
+            }
+
+            match db.get_conversation_statistics(conversation_id) {
+                Ok(stats) => ToolResult {
+                    success: true,
+                    result: stats,
+                    error: None,
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to get statistics: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_get_conversation_analytics(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
+    let top_chains = tool_call.arguments["top_chains"].as_i64().unwrap_or(5).clamp(1, 20) as usize;
+
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+
+            if let Err(result) = authorize_conversation(&db, &user, conversation_id) {
+                return result;
+            }
+
+            match db.get_conversation_analytics(conversation_id, top_chains) {
+                Ok(analytics) => ToolResult {
+                    success: true,
+                    result: serde_json::json!(analytics),
+                    error: None,
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to get analytics: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_export_conversation(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
+    let format = tool_call.arguments["format"].as_str().unwrap_or("md");
+
+    let format = match ExportFormat::parse(format) {
+        Some(format) => format,
+        None => {
+            return ToolResult {
+                success: false,
+                result: serde_json::json!(null),
+                error: Some(format!("Invalid format '{}', expected md, json, csv or html", format)),
+            }
+        }
+    };
+
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+
+            if let Err(result) = authorize_conversation(&db, &user, conversation_id) {
+                return result;
+            }
+
+            match db.find_conversation_by_id(conversation_id) {
+                Ok(Some(conversation)) => ToolResult {
+                    success: true,
+                    result: serde_json::json!({
+                        "conversation_id": conversation_id,
+                        "title": conversation.title,
+                        "format": format.extension(),
+                        "url": export::create_download_link(conversation_id, format),
+                        "expires_in_secs": export::link_ttl().as_secs(),
+                    }),
+                    error: None,
+                },
+                Ok(None) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Conversation {} not found", conversation_id)),
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Database error: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_get_message_thread(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let message_id = tool_call.arguments["message_id"].as_i64().unwrap_or(0) as i32;
+
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+            match db.get_message_thread(message_id) {
+                Ok(Some(thread)) => match authorize_conversation(&db, &user, thread.message.message.message.conversation_id) {
+                    Ok(()) => ToolResult {
+                        success: true,
+                        result: serde_json::json!(thread),
+                        error: None,
+                    },
+                    Err(result) => result,
+                },
+                Ok(None) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Message {} not found", message_id)),
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Database error: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_get_message_context(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let message_id = tool_call.arguments["message_id"].as_i64().unwrap_or(0) as i32;
+    let before = tool_call.arguments["before"].as_i64().unwrap_or(5).clamp(0, 50) as i32;
+    let after = tool_call.arguments["after"].as_i64().unwrap_or(5).clamp(0, 50) as i32;
+
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+            match db.get_message_context(message_id, before, after) {
+                Ok(Some(context)) => match authorize_conversation(&db, &user, context.message.message.conversation_id) {
+                    Ok(()) => ToolResult {
+                        success: true,
+                        result: serde_json::json!(context),
+                        error: None,
+                    },
+                    Err(result) => result,
+                },
+                Ok(None) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Message {} not found", message_id)),
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Database error: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_get_reaction_stats(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let conversation_id = tool_call.arguments["conversation_id"].as_i64().map(|id| id as i32);
+    let username = tool_call.arguments["username"].as_str().unwrap_or("");
+    let top = tool_call.arguments["top"].as_u64().unwrap_or(10).min(100) as usize;
+
+    if conversation_id.is_none() && username.is_empty() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("Either conversation_id or username is required".to_string()),
+        };
+    }
+
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+            if let Some(id) = conversation_id {
+                if let Err(result) = authorize_conversation(&db, &user, id) {
+                    return result;
+                }
+            }
+            let viewer = if is_staff(&user) { None } else { Some(user.id) };
+
+            let scope = match conversation_id {
+                Some(id) => ReactionScope::Conversation(id),
+                None => match db.find_user_by_username(username) {
+                    Ok(Some(user)) => ReactionScope::Author(user.id),
+                    Ok(None) => {
+                        return ToolResult {
+                            success: false,
+                            result: serde_json::json!(null),
+                            error: Some(format!("User '{}' not found", username)),
+                        }
+                    }
+                    Err(e) => {
+                        return ToolResult {
+                            success: false,
+                            result: serde_json::json!(null),
+                            error: Some(format!("Database error: {}", e)),
+                        }
+                    }
+                },
+            };
+
+            match db.get_reaction_stats(scope, viewer, top) {
+                Ok(stats) => ToolResult {
+                    success: true,
+                    result: serde_json::json!(stats),
+                    error: None,
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to get reaction statistics: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_get_message_history(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let message_id = tool_call.arguments["message_id"].as_i64().unwrap_or(0) as i32;
+
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+
+            // Deleted messages keep their history, so they are looked up too.
+            let (message, deleted_at) = match db.find_message_including_deleted(message_id) {
+                Ok(Some(found)) => found,
+                Ok(None) => {
+                    return ToolResult {
+                        success: false,
+                        result: serde_json::json!(null),
+                        error: Some(format!("Message {} not found", message_id)),
+                    }
+                }
+                Err(e) => {
+                    return ToolResult {
+                        success: false,
+                        result: serde_json::json!(null),
+                        error: Some(format!("Database error: {}", e)),
+                    }
+                }
+            };
+
+            if let Err(result) = authorize_conversation(&db, &user, message.conversation_id) {
+                return result;
+            }
+
+            match db.get_message_edit_history(message_id) {
+                Ok(edits) => ToolResult {
+                    success: true,
+                    result: serde_json::json!({
+                        "message_id": message_id,
+                        "current_content": message.content,
+                        "deleted_at": deleted_at,
+                        "edits": edits,
+                    }),
+                    error: None,
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to get message history: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_list_all_conversations(ctx: &ToolContext) -> ToolResult {
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+            let conversations = if is_staff(&user) {
+                db.get_all_conversations()
+            } else {
+                db.find_conversations_by_user(user.id)
+            };
+
+            match conversations {
+                Ok(conversations) => ToolResult {
+                    success: true,
+                    result: serde_json::json!({
+                        "total": conversations.len(),
+                        "conversations": conversations,
+                    }),
+                    error: None,
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to list conversations: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_find_user(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let search_term = tool_call.arguments["search_term"].as_str().unwrap_or("");
+
+    if search_term.is_empty() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("Search term is required".to_string()),
+        };
+    }
+
+    match Database::new() {
+        Ok(db) => {
+            if let Err(result) = require_acting_user(&db, ctx) {
+                return result;
+            }
+
+            match db.search_users(search_term, None) {
+                Ok(users) => {
+                    let policy = UserRedactionPolicy::from_env();
+                    let users: Vec<PublicUser> = users.iter().map(|u| u.to_public(&policy)).collect();
+                    ToolResult {
+                        success: true,
+                        result: serde_json::json!({
+                            "found": users.len(),
+                            "users": users,
+                        }),
+                        error: None,
+                    }
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Search error: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+// ===== CONVERSATION MANAGEMENT TOOL IMPLEMENTATIONS =====
+
+/// Looks up a user by username, turning a missing user into a tool error.
+fn require_user(db: &Database, username: &str) -> Result<User, ToolResult> {
+    match db.find_user_by_username(username) {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("User '{}' not found", username)),
+        }),
+        Err(e) => Err(ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Database error: {}", e)),
+        }),
+    }
+}
+
+/// Checks that `user` may manage `conversation_id`: members may manage direct
+/// conversations, only admins may manage groups.
+fn require_manager(db: &Database, conversation_id: i32, user: &User) -> Result<Conversation, ToolResult> {
+    let conversation = match db.find_conversation_by_id(conversation_id) {
+        Ok(Some(conversation)) => conversation,
+        Ok(None) => {
+            return Err(ToolResult {
+                success: false,
                 result: serde_json::json!(null),
-                error: Some(format!("Failed to list conversations: {}", e)),
-            },
+                error: Some(format!("Conversation {} not found", conversation_id)),
+            })
+        }
+        Err(e) => {
+            return Err(ToolResult {
+                success: false,
+                result: serde_json::json!(null),
+                error: Some(format!("Database error: {}", e)),
+            })
+        }
+    };
+
+    match db.find_membership(conversation_id, user.id) {
+        Ok(Some(membership)) if membership.is_admin || !conversation.is_group => Ok(conversation),
+        Ok(Some(_)) => Err(permission_denied(format!(
+            "'{}' is not an admin of conversation {}",
+            user.username, conversation_id
+        ))),
+        Ok(None) => Err(permission_denied(format!(
+            "'{}' is not a participant of conversation {}",
+            user.username, conversation_id
+        ))),
+        Err(e) => Err(ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Database error: {}", e)),
+        }),
+    }
+}
+
+fn participants_json(db: &Database, conversation_id: i32) -> serde_json::Value {
+    let policy = UserRedactionPolicy::from_env();
+    match db.get_conversation_participants(conversation_id) {
+        Ok(users) => serde_json::json!(users.iter().map(|u| u.to_public(&policy)).collect::<Vec<PublicUser>>()),
+        Err(_) => serde_json::json!(null),
+    }
+}
+
+fn execute_create_conversation(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let acting_username = ctx.acting_username.as_deref().unwrap_or("");
+    let participants: Vec<&str> = tool_call.arguments["participants"]
+        .as_str()
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty() && *p != acting_username)
+        .collect();
+    let title = tool_call.arguments["title"].as_str().unwrap_or("").trim();
+    let is_group = tool_call.arguments["is_group"].as_bool().unwrap_or(participants.len() > 1);
+    let first_message = tool_call.arguments["first_message"]
+        .as_str()
+        .map(str::trim)
+        .filter(|m| !m.is_empty());
+
+    if participants.is_empty() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("At least one other participant is required".to_string()),
+        };
+    }
+    if !is_group && participants.len() != 1 {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("A direct conversation must have exactly one other participant".to_string()),
+        };
+    }
+    if is_group && title.is_empty() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("A title is required for group conversations".to_string()),
+        };
+    }
+
+    match Database::new() {
+        Ok(db) => {
+            let creator = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+            let mut members = Vec::new();
+            for username in &participants {
+                match require_user(&db, username) {
+                    Ok(user) => members.push(user),
+                    Err(result) => return result,
+                }
+            }
+
+            if !is_group {
+                match db.find_direct_conversation(creator.id, members[0].id) {
+                    Ok(Some(existing)) => {
+                        let first_message_id = match first_message {
+                            Some(content) => match db.insert_message(existing, creator.id, content, None) {
+                                Ok(message_id) => Some(message_id),
+                                Err(e) => {
+                                    return ToolResult {
+                                        success: false,
+                                        result: serde_json::json!(null),
+                                        error: Some(format!("Failed to send message: {}", e)),
+                                    }
+                                }
+                            },
+                            None => None,
+                        };
+                        return ToolResult {
+                            success: true,
+                            result: serde_json::json!({
+                                "conversation_id": existing,
+                                "existing": true,
+                                "participants": participants_json(&db, existing),
+                                "first_message_id": first_message_id,
+                            }),
+                            error: None,
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        return ToolResult {
+                            success: false,
+                            result: serde_json::json!(null),
+                            error: Some(format!("Database error: {}", e)),
+                        }
+                    }
+                }
+            }
+
+            let title = if title.is_empty() { members[0].username.clone() } else { title.to_string() };
+            let member_ids: Vec<i32> = members.iter().map(|u| u.id).collect();
+
+            // Conversation, members and first message land together or not at all.
+            let created = db.transaction(|tx| {
+                let conversation_id = data_base::create_conversation_in(tx, &title, is_group, creator.id, &member_ids)?;
+                let first_message_id = match first_message {
+                    Some(content) => Some(data_base::insert_message_in(tx, conversation_id, creator.id, content, None)?),
+                    None => None,
+                };
+                Ok((conversation_id, first_message_id))
+            });
+
+            match created {
+                Ok((conversation_id, first_message_id)) => ToolResult {
+                    success: true,
+                    result: serde_json::json!({
+                        "conversation_id": conversation_id,
+                        "existing": false,
+                        "title": title,
+                        "is_group": is_group,
+                        "participants": participants_json(&db, conversation_id),
+                        "first_message_id": first_message_id,
+                    }),
+                    error: None,
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to create conversation: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_rename_conversation(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
+    let title = tool_call.arguments["title"].as_str().unwrap_or("").trim();
+
+    if title.is_empty() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("Title is required".to_string()),
+        };
+    }
+
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+            let conversation = match require_manager(&db, conversation_id, &user) {
+                Ok(conversation) => conversation,
+                Err(result) => return result,
+            };
+
+            match db.rename_conversation(conversation_id, title) {
+                Ok(_) => ToolResult {
+                    success: true,
+                    result: serde_json::json!({
+                        "conversation_id": conversation_id,
+                        "old_title": conversation.title,
+                        "title": title,
+                    }),
+                    error: None,
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to rename conversation: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_add_participant(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
+    let username = tool_call.arguments["username"].as_str().unwrap_or("").trim();
+    let is_admin = tool_call.arguments["is_admin"].as_bool().unwrap_or(false);
+
+    if username.is_empty() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("Username is required".to_string()),
+        };
+    }
+
+    match Database::new() {
+        Ok(db) => {
+            let (actor, target) = match (require_acting_user(&db, ctx), require_user(&db, username)) {
+                (Ok(actor), Ok(target)) => (actor, target),
+                (Err(result), _) | (_, Err(result)) => return result,
+            };
+            match require_manager(&db, conversation_id, &actor) {
+                Ok(conversation) if !conversation.is_group => {
+                    return ToolResult {
+                        success: false,
+                        result: serde_json::json!(null),
+                        error: Some("Participants cannot be added to a direct conversation; create a group instead".to_string()),
+                    }
+                }
+                Ok(_) => {}
+                Err(result) => return result,
+            }
+
+            match db.add_participant(conversation_id, target.id, is_admin) {
+                Ok(added) => ToolResult {
+                    success: true,
+                    result: serde_json::json!({
+                        "conversation_id": conversation_id,
+                        "user": username,
+                        "added": added,
+                        "participants": participants_json(&db, conversation_id),
+                    }),
+                    error: None,
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to add participant: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_remove_participant(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
+    let username = tool_call.arguments["username"].as_str().unwrap_or("").trim();
+
+    if username.is_empty() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("Username is required".to_string()),
+        };
+    }
+
+    match Database::new() {
+        Ok(db) => {
+            let (actor, target) = match (require_acting_user(&db, ctx), require_user(&db, username)) {
+                (Ok(actor), Ok(target)) => (actor, target),
+                (Err(result), _) | (_, Err(result)) => return result,
+            };
+
+            // Anyone may leave a group; removing others needs admin rights.
+            let conversation = if actor.id == target.id {
+                match db.find_conversation_by_id(conversation_id) {
+                    Ok(Some(conversation)) => conversation,
+                    Ok(None) => {
+                        return ToolResult {
+                            success: false,
+                            result: serde_json::json!(null),
+                            error: Some(format!("Conversation {} not found", conversation_id)),
+                        }
+                    }
+                    Err(e) => {
+                        return ToolResult {
+                            success: false,
+                            result: serde_json::json!(null),
+                            error: Some(format!("Database error: {}", e)),
+                        }
+                    }
+                }
+            } else {
+                match require_manager(&db, conversation_id, &actor) {
+                    Ok(conversation) => conversation,
+                    Err(result) => return result,
+                }
+            };
+
+            if !conversation.is_group {
+                return ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some("Participants cannot be removed from a direct conversation".to_string()),
+                };
+            }
+
+            let membership = match db.find_membership(conversation_id, target.id) {
+                Ok(Some(membership)) => membership,
+                Ok(None) => {
+                    return ToolResult {
+                        success: false,
+                        result: serde_json::json!(null),
+                        error: Some(format!("'{}' is not a participant of conversation {}", username, conversation_id)),
+                    }
+                }
+                Err(e) => {
+                    return ToolResult {
+                        success: false,
+                        result: serde_json::json!(null),
+                        error: Some(format!("Database error: {}", e)),
+                    }
+                }
+            };
+
+            if membership.is_admin {
+                let admins = db.count_conversation_admins(conversation_id).unwrap_or(0);
+                let participants = db.get_conversation_participants(conversation_id).map(|p| p.len()).unwrap_or(0);
+                if admins <= 1 && participants > 1 {
+                    return ToolResult {
+                        success: false,
+                        result: serde_json::json!(null),
+                        error: Some(format!("'{}' is the last admin of conversation {}; add another admin first", username, conversation_id)),
+                    };
+                }
+            }
+
+            match db.remove_participant(conversation_id, target.id) {
+                Ok(removed) => ToolResult {
+                    success: true,
+                    result: serde_json::json!({
+                        "conversation_id": conversation_id,
+                        "user": username,
+                        "removed": removed,
+                        "participants": participants_json(&db, conversation_id),
+                    }),
+                    error: None,
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to remove participant: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+// ===== MESSAGE EDIT TOOL IMPLEMENTATIONS =====
+
+/// Messages sent through send_message that the web UI may still undo.
+static UNDOABLE_MESSAGES: OnceLock<Mutex<HashMap<i32, (Instant, i32)>>> = OnceLock::new();
+
+/// Locks the undoable messages, dropping entries whose window has closed so
+/// the map stays bounded even when nothing is ever undone.
+fn undoable_messages() -> MutexGuard<'static, HashMap<i32, (Instant, i32)>> {
+    let mut pending = UNDOABLE_MESSAGES.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+    let window = undo_window();
+    pending.retain(|_, (sent_at, _)| sent_at.elapsed() < window);
+    pending
+}
+
+/// How long an assistant-sent message can be undone (ORBIT_UNDO_WINDOW_SECS, default 120).
+fn undo_window() -> Duration {
+    let secs = std::env::var("ORBIT_UNDO_WINDOW_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(120);
+    Duration::from_secs(secs)
+}
+
+/// Deletes a message sent by the assistant if it is still inside the undo
+/// window. Triggered by the "undo" action on the web UI's tool_result card.
+pub fn undo_message(message_id: i32, ctx: &ToolContext) -> ToolResult {
+    let pending = undoable_messages().get(&message_id).copied();
+
+    let sender_id = match pending {
+        Some((_, sender_id)) => sender_id,
+        None => {
+            return ToolResult {
+                success: false,
+                result: serde_json::json!(null),
+                error: Some(format!("Message {} can no longer be undone", message_id)),
+            }
+        }
+    };
+
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+
+            if user.id != sender_id {
+                return permission_denied(format!("'{}' did not send message {}", user.username, message_id));
+            }
+
+            let deleted = db.soft_delete_message(message_id, user.id, "undo");
+            // A failed attempt stays undoable until the window closes.
+            if deleted.is_ok() {
+                undoable_messages().remove(&message_id);
+            }
+
+            match deleted {
+                Ok(true) => ToolResult {
+                    success: true,
+                    result: serde_json::json!({ "message_id": message_id, "undone": true }),
+                    error: None,
+                },
+                Ok(false) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Message {} not found", message_id)),
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to undo message: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_edit_message(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let message_id = tool_call.arguments["message_id"].as_i64().unwrap_or(0) as i32;
+    let content = tool_call.arguments["content"].as_str().unwrap_or("");
+
+    if content.is_empty() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("Content is required".to_string()),
+        };
+    }
+
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+
+            match db.find_message_by_id(message_id) {
+                Ok(Some(message)) if message.user_id == user.id => {}
+                Ok(Some(_)) => return permission_denied(format!("'{}' can only edit their own messages", user.username)),
+                Ok(None) => {
+                    return ToolResult {
+                        success: false,
+                        result: serde_json::json!(null),
+                        error: Some(format!("Message {} not found", message_id)),
+                    }
+                }
+                Err(e) => {
+                    return ToolResult {
+                        success: false,
+                        result: serde_json::json!(null),
+                        error: Some(format!("Database error: {}", e)),
+                    }
+                }
+            }
+
+            match db.edit_message(message_id, user.id, content) {
+                Ok(edited) => ToolResult {
+                    success: edited,
+                    result: serde_json::json!({ "message_id": message_id, "content": content, "edited": edited }),
+                    error: if edited { None } else { Some(format!("Message {} not found", message_id)) },
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to edit message: {}", e)),
+                },
+            }
         },
         Err(e) => ToolResult {
             success: false,
@@ -583,37 +2293,626 @@ fn execute_list_all_conversations(tool_call: &ToolCall) -> ToolResult {
     }
 }
 
-fn execute_find_user(tool_call: &ToolCall) -> ToolResult {
-    let search_term = tool_call.arguments["search_term"].as_str().unwrap_or("");
+fn execute_delete_message(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let message_id = tool_call.arguments["message_id"].as_i64().unwrap_or(0) as i32;
 
-    if search_term.is_empty() {
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+
+            let message = match db.find_message_by_id(message_id) {
+                Ok(Some(message)) => message,
+                Ok(None) => {
+                    return ToolResult {
+                        success: false,
+                        result: serde_json::json!(null),
+                        error: Some(format!("Message {} not found", message_id)),
+                    }
+                }
+                Err(e) => {
+                    return ToolResult {
+                        success: false,
+                        result: serde_json::json!(null),
+                        error: Some(format!("Database error: {}", e)),
+                    }
+                }
+            };
+
+            if message.user_id != user.id {
+                match require_manager(&db, message.conversation_id, &user) {
+                    Ok(conversation) if conversation.is_group => {}
+                    Ok(_) => return permission_denied(format!("'{}' can only delete their own messages", user.username)),
+                    Err(result) => return result,
+                }
+            }
+
+            match db.soft_delete_message(message_id, user.id, "delete") {
+                Ok(deleted) => ToolResult {
+                    success: deleted,
+                    result: serde_json::json!({ "message_id": message_id, "deleted": deleted }),
+                    error: if deleted { None } else { Some(format!("Message {} not found", message_id)) },
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to delete message: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+// ===== SUMMARY CACHE TOOL IMPLEMENTATIONS =====
+
+fn execute_get_cached_summary(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
+    let max_new_messages = tool_call.arguments["max_new_messages"].as_i64().unwrap_or(100).clamp(1, 500) as i32;
+
+    match Database::new() {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+
+            if let Err(result) = authorize_conversation(&db, &user, conversation_id) {
+                return result;
+            }
+
+            let lookup = db.get_cached_summary(conversation_id).and_then(|cached| {
+                let after_id = cached.as_ref().map_or(0, |c| c.last_message_id);
+                let new_count = db.count_messages_after_id(conversation_id, after_id)?;
+                let new_messages = db.find_messages_after_id(conversation_id, after_id, max_new_messages)?;
+                Ok((cached, new_count, db.with_authors(new_messages)?))
+            });
+
+            match lookup {
+                Ok((cached, new_count, new_messages)) => {
+                    let freshness = match (&cached, new_count) {
+                        (None, _) => "missing",
+                        (Some(_), 0) => "fresh",
+                        (Some(_), _) => "stale",
+                    };
+                    // Summarizing the returned messages brings the summary up to this id.
+                    let covers_through_id = new_messages
+                        .last()
+                        .map(|m| m.message.id)
+                        .or(cached.as_ref().map(|c| c.last_message_id));
+
+                    ToolResult {
+                        success: true,
+                        result: serde_json::json!({
+                            "conversation_id": conversation_id,
+                            "freshness": freshness,
+                            "summary": cached.as_ref().map(|c| &c.summary),
+                            "summarized_through_id": cached.as_ref().map(|c| c.last_message_id),
+                            "updated_at": cached.as_ref().map(|c| c.updated_at),
+                            "new_message_count": new_count,
+                            "new_messages": new_messages.iter().map(|m| serde_json::json!({
+                                "id": m.message.id,
+                                "author": m.author,
+                                "created_at": m.message.created_at,
+                                "content": m.message.content,
+                            })).collect::<Vec<_>>(),
+                            "has_more_new_messages": new_count > new_messages.len() as i64,
+                            "covers_through_id": covers_through_id,
+                        }),
+                        error: None,
+                    }
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Database error: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+fn execute_save_conversation_summary(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let conversation_id = tool_call.arguments["conversation_id"].as_i64().unwrap_or(0) as i32;
+    let covers_through_id = tool_call.arguments["covers_through_id"].as_i64().unwrap_or(0) as i32;
+    let summary = tool_call.arguments["summary"].as_str().unwrap_or("").trim();
+
+    if summary.is_empty() {
         return ToolResult {
             success: false,
             result: serde_json::json!(null),
-            error: Some("Search term is required".to_string()),
+            error: Some("Summary is required".to_string()),
         };
     }
 
     match Database::new() {
-        Ok(db) => match db.search_users(search_term, None) {
-            Ok(users) => ToolResult {
+        Ok(db) => {
+            let user = match require_acting_user(&db, ctx) {
+                Ok(user) => user,
+                Err(result) => return result,
+            };
+
+            if let Err(result) = authorize_conversation(&db, &user, conversation_id) {
+                return result;
+            }
+
+            // The covered message may have been deleted since it was summarized.
+            match db.find_message_including_deleted(covers_through_id) {
+                Ok(Some((message, _))) if message.conversation_id == conversation_id => {}
+                Ok(_) => {
+                    return ToolResult {
+                        success: false,
+                        result: serde_json::json!(null),
+                        error: Some(format!("Message {} is not part of conversation {}", covers_through_id, conversation_id)),
+                    }
+                }
+                Err(e) => {
+                    return ToolResult {
+                        success: false,
+                        result: serde_json::json!(null),
+                        error: Some(format!("Database error: {}", e)),
+                    }
+                }
+            }
+
+            match db.save_cached_summary(conversation_id, summary, covers_through_id, user.id) {
+                Ok(true) => ToolResult {
+                    success: true,
+                    result: serde_json::json!({
+                        "conversation_id": conversation_id,
+                        "covers_through_id": covers_through_id,
+                        "saved": true,
+                    }),
+                    error: None,
+                },
+                Ok(false) => ToolResult {
+                    success: false,
+                    result: serde_json::json!({ "saved": false }),
+                    error: Some("A summary covering newer messages is already stored".to_string()),
+                },
+                Err(e) => ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Failed to save summary: {}", e)),
+                },
+            }
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Failed to connect to database: {}", e)),
+        },
+    }
+}
+
+// ===== SEMANTIC SEARCH TOOL IMPLEMENTATION =====
+
+async fn execute_semantic_search(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let query = tool_call.arguments["query"].as_str().unwrap_or("").trim().to_string();
+    let conversation_id = tool_call.arguments["conversation_id"].as_i64().map(|id| id as i32);
+    let limit = tool_call.arguments["limit"].as_i64().unwrap_or(10).clamp(1, 50) as usize;
+
+    if query.is_empty() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("Query is required".to_string()),
+        };
+    }
+
+    let service = match embeddings::service() {
+        Some(service) => service,
+        None => {
+            return ToolResult {
+                success: false,
+                result: serde_json::json!(null),
+                error: Some("Semantic search is not configured (set ORBIT_EMBEDDINGS_MODEL)".to_string()),
+            }
+        }
+    };
+
+    let db = match Database::new() {
+        Ok(db) => db,
+        Err(e) => {
+            return ToolResult {
+                success: false,
+                result: serde_json::json!(null),
+                error: Some(format!("Failed to connect to database: {}", e)),
+            }
+        }
+    };
+
+    let user = match require_acting_user(&db, ctx) {
+        Ok(user) => user,
+        Err(result) => return result,
+    };
+
+    let allowed: HashSet<i32> = match conversation_id {
+        Some(id) => {
+            if let Err(result) = authorize_conversation(&db, &user, id) {
+                return result;
+            }
+            HashSet::from([id])
+        }
+        None => match db.find_conversations_by_user(user.id) {
+            Ok(conversations) => conversations.into_iter().map(|c| c.id).collect(),
+            Err(e) => {
+                return ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Database error: {}", e)),
+                }
+            }
+        },
+    };
+
+    // Over-fetch: some hits may have been deleted since they were indexed.
+    let scored = match service.search(&query, Some(&allowed), limit * 2).await {
+        Ok(scored) => scored,
+        Err(e) => {
+            return ToolResult {
+                success: false,
+                result: serde_json::json!(null),
+                error: Some(format!("Semantic search failed: {}", e)),
+            }
+        }
+    };
+
+    let ids: Vec<i32> = scored.iter().map(|(id, _)| *id).collect();
+    let messages = match db.find_messages_by_ids(&ids).and_then(|messages| db.with_authors(messages)) {
+        Ok(messages) => messages,
+        Err(e) => {
+            return ToolResult {
+                success: false,
+                result: serde_json::json!(null),
+                error: Some(format!("Database error: {}", e)),
+            }
+        }
+    };
+
+    let mut by_id: HashMap<i32, _> = messages.into_iter().map(|m| (m.message.id, m)).collect();
+    let results: Vec<serde_json::Value> = scored
+        .into_iter()
+        .filter_map(|(id, score)| by_id.remove(&id).map(|message| (message, score)))
+        .take(limit)
+        .map(|(message, score)| {
+            let mut value = serde_json::json!(message);
+            value["score"] = serde_json::json!((score * 1000.0).round() / 1000.0);
+            value
+        })
+        .collect();
+
+    ToolResult {
+        success: true,
+        result: serde_json::json!({
+            "query": query,
+            "indexed_messages": service.indexed_messages(),
+            "results": results,
+        }),
+        error: None,
+    }
+}
+
+// ===== SQL QUERY TOOL IMPLEMENTATION =====
+
+fn execute_run_sql_query(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let sql = tool_call.arguments["sql"].as_str().unwrap_or("").trim();
+    if sql.is_empty() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("sql is required".to_string()),
+        };
+    }
+
+    let db = match Database::new() {
+        Ok(db) => db,
+        Err(e) => {
+            return ToolResult {
+                success: false,
+                result: serde_json::json!(null),
+                error: Some(format!("Failed to connect to database: {}", e)),
+            }
+        }
+    };
+
+    let user = match require_acting_user(&db, ctx) {
+        Ok(user) => user,
+        Err(result) => return result,
+    };
+    if !is_staff(&user) {
+        return permission_denied("only staff may run SQL queries".to_string());
+    }
+
+    let max_rows = sql_query::max_rows();
+    let checked = match sql_query::validate(sql, max_rows) {
+        Ok(checked) => checked,
+        Err(e) => {
+            return ToolResult {
+                success: false,
+                result: serde_json::json!({ "error_kind": "QueryRejected" }),
+                error: Some(e.to_string()),
+            }
+        }
+    };
+
+    match db.run_read_only_query(&checked, sql_query::statement_timeout()) {
+        Ok(result) => {
+            let row_count = result.rows.len();
+            let (columns, rows) = sql_query::rows_to_json(result);
+            ToolResult {
                 success: true,
                 result: serde_json::json!({
-                    "found": users.len(),
-                    "users": users,
+                    "sql": checked,
+                    "columns": columns,
+                    "rows": rows,
+                    "row_count": row_count,
+                    "may_be_truncated": row_count as u64 >= max_rows,
                 }),
                 error: None,
-            },
-            Err(e) => ToolResult {
+            }
+        }
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Query failed: {}", e)),
+        },
+    }
+}
+
+// ===== IDEMPOTENCY KEYS =====
+
+/// How long an idempotency key is remembered (ORBIT_IDEMPOTENCY_WINDOW_SECS, default 3600).
+fn idempotency_window() -> Duration {
+    let secs = std::env::var("ORBIT_IDEMPOTENCY_WINDOW_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3600);
+    Duration::from_secs(secs)
+}
+
+fn idempotency_key(tool_call: &ToolCall) -> Option<String> {
+    let key = tool_call.arguments["idempotency_key"].as_str()?.trim();
+    (!key.is_empty()).then(|| key.chars().take(191).collect())
+}
+
+/// Runs a mutating tool at most once per (session user, key). A repeat with
+/// the same key and arguments inside the window returns the stored result
+/// instead of writing again.
+async fn execute_idempotent(tool_call: &ToolCall, ctx: &ToolContext, username: &str, key: &str) -> ToolResult {
+    // Arguments are a BTreeMap-backed object, so this is canonical.
+    let mut arguments = tool_call.arguments.clone();
+    if let Some(object) = arguments.as_object_mut() {
+        object.remove("idempotency_key");
+    }
+    let arguments = arguments.to_string();
+
+    let db = match Database::new() {
+        Ok(db) => db,
+        Err(e) => {
+            return ToolResult {
                 success: false,
                 result: serde_json::json!(null),
-                error: Some(format!("Search error: {}", e)),
-            },
+                error: Some(format!("Failed to connect to database: {}", e)),
+            }
+        }
+    };
+
+    match db.claim_idempotency_key(username, key, &tool_call.name, &arguments, idempotency_window()) {
+        Ok(IdempotencyClaim::Claimed) => {}
+        Ok(IdempotencyClaim::Existing { tool, arguments: previous, result }) => {
+            if tool != tool_call.name || previous != arguments {
+                return ToolResult {
+                    success: false,
+                    result: serde_json::json!({ "error_kind": "IdempotencyKeyReused" }),
+                    error: Some(format!("Idempotency key '{}' was already used for a different {} call", key, tool)),
+                };
+            }
+            return match result.and_then(|result| serde_json::from_str::<serde_json::Value>(&result).ok()) {
+                Some(mut result) => {
+                    result["idempotent_replay"] = serde_json::json!(true);
+                    ToolResult {
+                        success: true,
+                        result,
+                        error: None,
+                    }
+                }
+                None => ToolResult {
+                    success: false,
+                    result: serde_json::json!({ "error_kind": "IdempotencyKeyInProgress" }),
+                    error: Some(format!(
+                        "A {} call with idempotency key '{}' is still in progress or did not finish; do not retry it",
+                        tool, key
+                    )),
+                },
+            };
+        }
+        Err(e) => {
+            return ToolResult {
+                success: false,
+                result: serde_json::json!(null),
+                error: Some(format!("Database error: {}", e)),
+            }
+        }
+    }
+
+    let result = dispatch_tool(tool_call, ctx).await;
+    let stored = if result.success {
+        db.complete_idempotency_key(username, key, &result.result.to_string())
+    } else {
+        db.release_idempotency_key(username, key)
+    };
+    if let Err(e) = stored {
+        eprintln!("Failed to record idempotency key '{}': {}", key, e);
+    }
+    result
+}
+
+// ===== RETENTION TOOL IMPLEMENTATIONS =====
+
+/// Connects and resolves the session user, who must be staff.
+fn require_staff(ctx: &ToolContext, action: &str) -> Result<(Database, User), ToolResult> {
+    let db = Database::new().map_err(|e| ToolResult {
+        success: false,
+        result: serde_json::json!(null),
+        error: Some(format!("Failed to connect to database: {}", e)),
+    })?;
+    let user = require_acting_user(&db, ctx)?;
+    if !is_staff(&user) {
+        return Err(permission_denied(format!("only staff may {}", action)));
+    }
+    Ok((db, user))
+}
+
+fn execute_get_retention_rules(ctx: &ToolContext) -> ToolResult {
+    let (db, _) = match require_staff(ctx, "view retention rules") {
+        Ok(staff) => staff,
+        Err(result) => return result,
+    };
+
+    match db.get_retention_rules() {
+        Ok(rules) => ToolResult {
+            success: true,
+            result: serde_json::json!({
+                "rules": rules,
+                "scheduled_every_hours": std::env::var("ORBIT_RETENTION_INTERVAL_HOURS").ok(),
+            }),
+            error: None,
         },
         Err(e) => ToolResult {
             success: false,
             result: serde_json::json!(null),
-            error: Some(format!("Failed to connect to database: {}", e)),
+            error: Some(format!("Database error: {}", e)),
+        },
+    }
+}
+
+fn execute_set_retention_rule(tool_call: &ToolCall, ctx: &ToolContext) -> ToolResult {
+    let scope = tool_call.arguments["scope"].as_str().unwrap_or("").trim().to_lowercase();
+    let conversation_id = tool_call.arguments["conversation_id"].as_i64().map(|id| id as i32);
+    let max_age_days = tool_call.arguments["max_age_days"].as_u64();
+    let remove = tool_call.arguments["remove"].as_bool().unwrap_or(false);
+
+    if !retention::SCOPES.contains(&scope.as_str()) {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Scope must be one of: {}", retention::SCOPES.join(", "))),
+        };
+    }
+    let conversation_id = match (scope.as_str(), conversation_id) {
+        ("conversation", Some(id)) => Some(id),
+        ("conversation", None) => {
+            return ToolResult {
+                success: false,
+                result: serde_json::json!(null),
+                error: Some("conversation_id is required for scope conversation".to_string()),
+            }
+        }
+        _ => None,
+    };
+    if !remove && max_age_days.is_none() {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some("max_age_days is required (0 keeps messages forever)".to_string()),
+        };
+    }
+    if !remove && max_age_days.is_some_and(|d| d > retention::MAX_AGE_DAYS as u64) {
+        return ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("max_age_days must be at most {} (0 keeps messages forever)", retention::MAX_AGE_DAYS)),
+        };
+    }
+
+    let (db, user) = match require_staff(ctx, "change retention rules") {
+        Ok(staff) => staff,
+        Err(result) => return result,
+    };
+    if let Some(id) = conversation_id {
+        match db.find_conversation_by_id(id) {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                return ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Conversation {} not found", id)),
+                }
+            }
+            Err(e) => {
+                return ToolResult {
+                    success: false,
+                    result: serde_json::json!(null),
+                    error: Some(format!("Database error: {}", e)),
+                }
+            }
+        }
+    }
+
+    let outcome = if remove {
+        db.delete_retention_rule(&scope, conversation_id).map(|removed| serde_json::json!({
+            "scope": scope,
+            "conversation_id": conversation_id,
+            "removed": removed,
+        }))
+    } else {
+        let days = max_age_days.filter(|d| *d > 0).map(|d| d as u32);
+        db.set_retention_rule(&scope, conversation_id, days, user.id).map(|_| serde_json::json!({
+            "scope": scope,
+            "conversation_id": conversation_id,
+            "max_age_days": days,
+            "keeps_forever": days.is_none(),
+        }))
+    };
+
+    match outcome {
+        Ok(result) => ToolResult {
+            success: true,
+            result,
+            error: None,
+        },
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Database error: {}", e)),
+        },
+    }
+}
+
+fn execute_preview_retention_purge(ctx: &ToolContext) -> ToolResult {
+    let (db, user) = match require_staff(ctx, "preview retention purges") {
+        Ok(staff) => staff,
+        Err(result) => return result,
+    };
+
+    match retention::run(&db, true, &format!("tool:{}", user.username)) {
+        Ok(report) => {
+            let would_delete: usize = report.conversations.iter().map(|c| c.messages).sum();
+            ToolResult {
+                success: true,
+                result: serde_json::json!({
+                    "would_delete": would_delete,
+                    "conversations": report.conversations,
+                }),
+                error: None,
+            }
+        }
+        Err(e) => ToolResult {
+            success: false,
+            result: serde_json::json!(null),
+            error: Some(format!("Retention preview failed: {}", e)),
         },
     }
-}
\ No newline at end of file
+}
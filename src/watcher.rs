@@ -0,0 +1,205 @@
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::{sleep, Duration};
+use crate::data_base::{Database, MessageWithAuthor};
+use crate::ws_server::WebSocketServer;
+
+/// Most messages picked up per conversation and poll.
+const MAX_MESSAGES_PER_POLL: i32 = 100;
+
+/// Most activity notes handed to the assistant per turn.
+const MAX_ACTIVITY_NOTES: usize = 20;
+
+/// Notes about watched-conversation activity, held until the next turn.
+/// They cannot go into the history while a turn runs: a note between an
+/// assistant tool call and its results breaks the pairing providers require.
+#[derive(Debug, Default)]
+pub struct ActivityNotes {
+    notes: VecDeque<String>,
+    /// Older notes dropped to stay within MAX_ACTIVITY_NOTES.
+    omitted: usize,
+}
+
+impl ActivityNotes {
+    pub fn push(&mut self, note: String) {
+        if self.notes.len() == MAX_ACTIVITY_NOTES {
+            self.notes.pop_front();
+            self.omitted += 1;
+        }
+        self.notes.push_back(note);
+    }
+
+    /// Empties the buffer into one system note, newest notes last.
+    pub fn flush(&mut self) -> Option<String> {
+        if self.notes.is_empty() {
+            return None;
+        }
+        let mut text = String::from("Activity in watched conversations since the last turn:");
+        if self.omitted > 0 {
+            text.push_str(&format!("\n({} earlier messages omitted)", self.omitted));
+        }
+        for note in self.notes.drain(..) {
+            text.push_str("\n- ");
+            text.push_str(&note);
+        }
+        self.omitted = 0;
+        Some(text)
+    }
+}
+
+/// Polls subscribed conversations for new message ids and pushes them to
+/// WebSocket clients as `db_message` events.
+///
+/// Only inserts are seen: edits and deletes of already delivered messages
+/// are not replayed.
+#[derive(Clone)]
+pub struct ConversationWatcher {
+    /// Conversation id -> id of the last message delivered.
+    subscriptions: Arc<Mutex<HashMap<i32, i32>>>,
+}
+
+impl ConversationWatcher {
+    pub fn new() -> Self {
+        ConversationWatcher {
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Starts watching a conversation from its current latest message.
+    pub async fn subscribe(&self, conversation_id: i32) -> Result<(), String> {
+        if self.subscriptions.lock().await.contains_key(&conversation_id) {
+            return Ok(());
+        }
+
+        let latest = tokio::task::spawn_blocking(move || {
+            let db = Database::new()?;
+            db.latest_message_id(conversation_id)
+        })
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Database error: {}", e))?;
+
+        self.subscriptions
+            .lock()
+            .await
+            .entry(conversation_id)
+            .or_insert(latest.unwrap_or(0));
+        Ok(())
+    }
+
+    pub async fn unsubscribe(&self, conversation_id: i32) -> bool {
+        self.subscriptions.lock().await.remove(&conversation_id).is_some()
+    }
+
+    pub async fn clear(&self) {
+        self.subscriptions.lock().await.clear();
+    }
+
+    pub async fn subscribed(&self) -> Vec<i32> {
+        let mut ids: Vec<i32> = self.subscriptions.lock().await.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Runs the polling loop. Every delivered message is also sent on
+    /// `activity` so the assistant can be told about it.
+    /// Interval is `ORBIT_WATCH_INTERVAL_MS` (default 2000).
+    pub fn spawn(&self, ws_server: WebSocketServer, activity: mpsc::UnboundedSender<MessageWithAuthor>) {
+        let interval = env::var("ORBIT_WATCH_INTERVAL_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(2000u64)
+            .max(250);
+        let subscriptions = self.subscriptions.clone();
+
+        tokio::spawn(async move {
+            loop {
+                sleep(Duration::from_millis(interval)).await;
+
+                let snapshot: Vec<(i32, i32)> = subscriptions
+                    .lock()
+                    .await
+                    .iter()
+                    .map(|(conversation_id, last_id)| (*conversation_id, *last_id))
+                    .collect();
+                if snapshot.is_empty() {
+                    continue;
+                }
+
+                let polled = tokio::task::spawn_blocking(move || poll(&snapshot)).await;
+                let batches = match polled {
+                    Ok(Ok(batches)) => batches,
+                    Ok(Err(e)) => {
+                        eprintln!("Conversation watcher: database error: {}", e);
+                        continue;
+                    }
+                    Err(e) => {
+                        eprintln!("Conversation watcher: poll task failed: {}", e);
+                        continue;
+                    }
+                };
+
+                for (conversation_id, messages) in batches {
+                    {
+                        // Skip conversations unsubscribed while we were polling.
+                        let mut subscriptions = subscriptions.lock().await;
+                        match (subscriptions.get_mut(&conversation_id), messages.last()) {
+                            (Some(last_id), Some(last)) => *last_id = last.message.id,
+                            _ => continue,
+                        }
+                    }
+
+                    for message in messages {
+                        ws_server.broadcast_json(&json!({
+                            "type": "db_message",
+                            "conversation_id": conversation_id,
+                            "message": message,
+                        })).await;
+                        let _ = activity.send(message);
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn poll(snapshot: &[(i32, i32)]) -> mysql::Result<Vec<(i32, Vec<MessageWithAuthor>)>> {
+    let db = Database::new()?;
+    let mut batches = Vec::new();
+
+    for &(conversation_id, last_id) in snapshot {
+        let messages = db.find_messages_after_id(conversation_id, last_id, MAX_MESSAGES_PER_POLL)?;
+        if !messages.is_empty() {
+            batches.push((conversation_id, db.with_authors(messages)?));
+        }
+    }
+
+    Ok(batches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn activity_notes_flush_once_and_keep_the_newest() {
+        let mut notes = ActivityNotes::default();
+        assert_eq!(notes.flush(), None);
+
+        for i in 0..MAX_ACTIVITY_NOTES + 3 {
+            notes.push(format!("note {}", i));
+        }
+        let text = notes.flush().unwrap();
+        assert!(text.contains("(3 earlier messages omitted)"));
+        assert!(!text.contains("- note 2\n"));
+        assert!(text.contains("- note 3\n"));
+        assert!(text.ends_with(&format!("- note {}", MAX_ACTIVITY_NOTES + 2)));
+        assert_eq!(notes.flush(), None);
+
+        notes.push("later".to_string());
+        assert_eq!(notes.flush().unwrap(), "Activity in watched conversations since the last turn:\n- later");
+    }
+}
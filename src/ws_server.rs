@@ -1,7 +1,8 @@
 use axum::{
+    body::Body,
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        State,
+        Path, Query, State,
     },
     response::{Html, IntoResponse, Response},
     http::{StatusCode, header},
@@ -11,7 +12,11 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use futures_util::{SinkExt, StreamExt};
-use tokio::sync::{broadcast, Mutex};
+use tokio::sync::{broadcast, mpsc, Mutex};
+use crate::data_base::Database;
+use crate::db_health;
+use crate::llm;
+use crate::export::{self, ChannelWriter, ExportFormat};
 
 #[derive(Clone)]
 pub struct WebSocketServer {
@@ -24,6 +29,21 @@ pub struct WebSocketServer {
 pub enum ClientMessage {
     #[serde(rename = "send_message")]
     SendMessage { content: String },
+    #[serde(rename = "set_user")]
+    SetUser {
+        username: String,
+        /// Must match ORBIT_SESSION_TOKEN.
+        #[serde(default)]
+        token: Option<String>,
+    },
+    #[serde(rename = "set_model")]
+    SetModel { model: String },
+    #[serde(rename = "undo_message")]
+    UndoMessage { message_id: i32 },
+    #[serde(rename = "subscribe_conversation")]
+    SubscribeConversation { conversation_id: i32 },
+    #[serde(rename = "unsubscribe_conversation")]
+    UnsubscribeConversation { conversation_id: i32 },
 }
 
 impl WebSocketServer {
@@ -46,6 +66,7 @@ impl WebSocketServer {
             .route("/styles.css", get(serve_css))
             .route("/app.js", get(serve_js))
             .route("/ws", get(ws_handler))
+            .route("/export/:conversation_id", get(export_handler))
             .with_state(app_state);
 
         let addr = format!("0.0.0.0:{}", port);
@@ -98,6 +119,75 @@ async fn serve_js() -> impl IntoResponse {
         .unwrap()
 }
 
+#[derive(Deserialize)]
+struct ExportParams {
+    format: Option<String>,
+    token: String,
+}
+
+/// Streams a conversation export. Requires a token issued by the
+/// export_conversation tool.
+async fn export_handler(
+    Path(conversation_id): Path<i32>,
+    Query(params): Query<ExportParams>,
+) -> Response {
+    let format = match ExportFormat::parse(params.format.as_deref().unwrap_or("md")) {
+        Some(format) => format,
+        None => return (StatusCode::BAD_REQUEST, "Unknown format, expected md, json, csv or html").into_response(),
+    };
+
+    if !export::link_is_valid(&params.token, conversation_id) {
+        return (StatusCode::FORBIDDEN, "Export link is invalid or has expired").into_response();
+    }
+
+    let (tx, mut rx) = mpsc::channel::<std::io::Result<Vec<u8>>>(16);
+    tokio::task::spawn_blocking(move || {
+        let result = Database::new()
+            .map_err(export::ExportError::from)
+            .and_then(|db| {
+                let writer = std::io::BufWriter::new(ChannelWriter(tx.clone()));
+                export::write_conversation(&db, conversation_id, format, writer)
+            });
+        if let Err(e) = result {
+            eprintln!("Export of conversation {} failed: {}", conversation_id, e);
+            // Abort the body so the client sees a failed download, not a truncated file.
+            let kind = match e {
+                export::ExportError::NotFound(_) => std::io::ErrorKind::NotFound,
+                _ => std::io::ErrorKind::Other,
+            };
+            let _ = tx.blocking_send(Err(std::io::Error::new(kind, e.to_string())));
+        }
+    });
+
+    // Hold the response until the first chunk so a missing conversation can
+    // still be reported with a proper status code.
+    let first = match rx.recv().await {
+        Some(Ok(chunk)) => chunk,
+        Some(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return (StatusCode::NOT_FOUND, e.to_string()).into_response()
+        }
+        Some(Err(e)) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        None => Vec::new(),
+    };
+
+    let stream = futures_util::stream::unfold((Some(first), rx), |(first, mut rx)| async move {
+        match first {
+            Some(chunk) => Some((Ok(chunk), (None, rx))),
+            None => rx.recv().await.map(|chunk| (chunk, (None, rx))),
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"conversation-{}.{}\"", conversation_id, format.extension()),
+        )
+        .body(Body::from_stream(stream))
+        .unwrap()
+}
+
 async fn ws_handler(
     ws: WebSocketUpgrade,
     State(state): State<AppState>,
@@ -116,6 +206,12 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
             "message": "Connected to Chat-IBM"
         }).to_string()
     )).await;
+    if let Some(status) = db_health::current_event() {
+        let _ = sender.send(Message::Text(status.to_string())).await;
+    }
+    if let Some(models) = llm::current_event() {
+        let _ = sender.send(Message::Text(models.to_string())).await;
+    }
 
     // Spawn task to send messages to client
     let mut send_task = tokio::spawn(async move {
@@ -130,8 +226,6 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
     let client_messages = state.client_messages.clone();
     let mut recv_task = tokio::spawn(async move {
         while let Some(Ok(Message::Text(text))) = receiver.next().await {
-            use futures_util::stream::StreamExt;
-
             if let Ok(msg) = serde_json::from_str::<ClientMessage>(&text) {
                 let mut messages = client_messages.lock().await;
                 messages.push(msg);
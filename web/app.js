@@ -2,6 +2,9 @@ let ws;
 let currentAssistantMessage = null;
 let isProcessing = false;
 let currentToolExecutions = [];
+// Placeholder -> original value for PII scrubbed out of tool results.
+// Only used for local display; the model only ever sees the placeholders.
+const piiValues = {};
 
 function connect() {
     const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
@@ -55,11 +58,13 @@ function handleMessage(data) {
                     typingIndicator.remove();
                 }
 
+                // Keep the raw text so placeholders split across chunks still resolve.
+                content.dataset.raw = (content.dataset.raw || '') + data.content;
                 const textNode = Array.from(content.childNodes).find(node => node.nodeType === Node.TEXT_NODE);
                 if (textNode) {
-                    textNode.textContent += data.content;
+                    textNode.textContent = rehydrate(content.dataset.raw);
                 } else {
-                    content.appendChild(document.createTextNode(data.content));
+                    content.appendChild(document.createTextNode(rehydrate(content.dataset.raw)));
                 }
                 scrollToBottom();
             }
@@ -83,14 +88,59 @@ function handleMessage(data) {
                 const toolsDiv = currentAssistantMessage.querySelector('#current-tools');
                 if (toolsDiv) {
                     const resultDiv = document.createElement('div');
-                    resultDiv.className = 'tool-result';
-                    resultDiv.textContent = `${data.tool}: ${JSON.stringify(data.result)}`;
+                    resultDiv.className = data.mutating ? 'tool-result mutating' : 'tool-result';
+                    Object.assign(piiValues, data.pii || {});
+                    resultDiv.textContent = rehydrate(`${data.tool}: ${JSON.stringify(data.result)}`);
+                    if (data.success && data.result && data.result.undo) {
+                        resultDiv.appendChild(createUndoButton(data.result.undo));
+                    }
+                    if (data.success && data.result && data.result.url) {
+                        const link = document.createElement('a');
+                        link.className = 'download-link';
+                        link.href = data.result.url;
+                        link.innerHTML = '<i class="bi bi-download"></i> Download';
+                        resultDiv.appendChild(link);
+                    }
                     toolsDiv.appendChild(resultDiv);
                     scrollToBottom();
                 }
             }
             break;
 
+        case 'session_user': {
+            const input = document.getElementById('session-user');
+            input.value = data.username || '';
+            input.parentElement.classList.toggle('invalid', !data.success);
+            input.parentElement.title = data.success ? `Acting as ${data.username}` : data.error;
+            break;
+        }
+
+        case 'undo_result': {
+            const button = document.querySelector(`.undo-button[data-message-id="${data.message_id}"]`);
+            if (button) {
+                button.disabled = true;
+                button.textContent = data.success ? 'Undone' : 'Undo failed';
+                button.title = data.error || '';
+            }
+            break;
+        }
+
+        case 'subscriptions':
+            renderSubscriptions(data.conversation_ids || [], data.error);
+            break;
+
+        case 'db_message':
+            createLiveMessage(data.conversation_id, data.message);
+            break;
+
+        case 'db_status':
+            updateDbStatus(data);
+            break;
+
+        case 'models':
+            updateModels(data);
+            break;
+
         case 'end':
             isProcessing = false;
             updateSendButton();
@@ -100,6 +150,60 @@ function handleMessage(data) {
     }
 }
 
+function updateDbStatus(status) {
+    const element = document.getElementById('db-status');
+    const text = document.getElementById('db-status-text');
+    element.classList.toggle('down', !status.available);
+    if (status.available) {
+        text.textContent = 'Database';
+        element.title = `Database reachable (checked ${status.checked_at})`;
+    } else {
+        text.textContent = `Database down, retry in ${status.retry_in_secs}s`;
+        element.title = `Unavailable since ${status.down_since}: ${status.error}`;
+    }
+}
+
+function updateModels(data) {
+    const select = document.getElementById('model-select');
+    select.innerHTML = '';
+    (data.models || []).forEach(model => {
+        const option = document.createElement('option');
+        option.value = model.id;
+        option.textContent = `${model.model} (${model.provider})`;
+        option.title = model.native_tools ? 'Native tool calling' : 'Text tool protocol';
+        select.appendChild(option);
+    });
+    select.value = data.active;
+    const control = document.getElementById('model-control');
+    control.classList.toggle('invalid', !!data.error);
+    control.title = data.error || `Model: ${data.active}`;
+}
+
+function rehydrate(text) {
+    return text.replace(/\[(EMAIL|CARD|PHONE|TERM)_\d+\]/g, placeholder => piiValues[placeholder] || placeholder);
+}
+
+function createUndoButton(undo) {
+    const button = document.createElement('button');
+    button.className = 'undo-button';
+    button.dataset.messageId = undo.message_id;
+    button.innerHTML = '<i class="bi bi-arrow-counterclockwise"></i> Undo';
+    button.onclick = () => {
+        button.disabled = true;
+        ws.send(JSON.stringify({
+            type: 'undo_message',
+            message_id: undo.message_id
+        }));
+    };
+    setTimeout(() => {
+        if (!button.disabled) {
+            button.disabled = true;
+            button.textContent = 'Undo expired';
+        }
+    }, undo.expires_in_secs * 1000);
+    return button;
+}
+
 function createMessage(role, content) {
     const messagesDiv = document.getElementById('messages');
     const welcomeMsg = messagesDiv.querySelector('.welcome-message');
@@ -149,6 +253,94 @@ function sendMessage() {
     }
 }
 
+function setSessionUser() {
+    const username = document.getElementById('session-user').value.trim();
+    const token = document.getElementById('session-token').value;
+    if (username && token && ws && ws.readyState === WebSocket.OPEN) {
+        ws.send(JSON.stringify({
+            type: 'set_user',
+            username: username,
+            token: token
+        }));
+    }
+}
+
+function setModel(model) {
+    if (ws && ws.readyState === WebSocket.OPEN) {
+        ws.send(JSON.stringify({
+            type: 'set_model',
+            model: model
+        }));
+    }
+}
+
+function handleWatchKey(event) {
+    if (event.key !== 'Enter') {
+        return;
+    }
+    const input = event.target;
+    const conversationId = parseInt(input.value, 10);
+    if (conversationId > 0 && ws && ws.readyState === WebSocket.OPEN) {
+        ws.send(JSON.stringify({
+            type: 'subscribe_conversation',
+            conversation_id: conversationId
+        }));
+        input.value = '';
+    }
+}
+
+function unwatchConversation(conversationId) {
+    if (ws && ws.readyState === WebSocket.OPEN) {
+        ws.send(JSON.stringify({
+            type: 'unsubscribe_conversation',
+            conversation_id: conversationId
+        }));
+    }
+}
+
+function renderSubscriptions(conversationIds, error) {
+    const list = document.getElementById('watch-list');
+    list.innerHTML = '';
+    conversationIds.forEach(id => {
+        const chip = document.createElement('span');
+        chip.className = 'watch-chip';
+        chip.textContent = `#${id}`;
+        const close = document.createElement('i');
+        close.className = 'bi bi-x';
+        close.onclick = () => unwatchConversation(id);
+        chip.appendChild(close);
+        list.appendChild(chip);
+    });
+    const control = list.parentElement;
+    control.classList.toggle('invalid', !!error);
+    control.title = error || '';
+}
+
+function createLiveMessage(conversationId, message) {
+    const messagesDiv = document.getElementById('messages');
+    const welcomeMsg = messagesDiv.querySelector('.welcome-message');
+    if (welcomeMsg) {
+        welcomeMsg.remove();
+    }
+
+    const card = document.createElement('div');
+    card.className = 'live-message';
+
+    const meta = document.createElement('div');
+    meta.className = 'live-message-meta';
+    const time = new Date(message.created_at).toLocaleTimeString();
+    meta.innerHTML = '<i class="bi bi-broadcast"></i> ';
+    meta.appendChild(document.createTextNode(`#${conversationId} · ${message.author} · ${time}`));
+
+    const body = document.createElement('div');
+    body.textContent = message.content;
+
+    card.appendChild(meta);
+    card.appendChild(body);
+    messagesDiv.appendChild(card);
+    scrollToBottom();
+}
+
 function sendSuggestion(text) {
     const input = document.getElementById('message-input');
     input.value = text;